@@ -1,42 +1,63 @@
-use std::io::{BufRead, BufReader};
-use std::fs::OpenOptions;
-use std::path::PathBuf;
-use std::process::exit;
+use std::path::{Path, PathBuf};
 
 use serde::Serialize;
 
-use crate::gemtext::parse_gemtext;
+use crate::frontmatter::{self, AboutFrontmatter};
+use crate::gemini::generate_gemini_from_tokens;
+use crate::gemtext::{gemini_body, select_output, parse_gemtext_lossy};
+use crate::html::{generate_html_from_tokens, HtmlOptions};
+use crate::notify::report_diagnostics;
 
 #[derive(Clone, Default, Debug, Serialize)]
 pub struct About {
+    pub title: String,
+    pub filename: String,
+    pub updated: String,
     pub html_content: String,
     pub gemini_content: String,
 }
 
 impl About {
-    pub fn from_source(source_path: PathBuf) -> About {
-        // Read from source .gmi file.
-        let source = OpenOptions::new().read(true).open(&source_path);
-        let source = match source {
-            Ok(s) => s,
-            Err(_) => {
-                eprintln!("Error: Could not open file {}",
-                    &source_path.to_string_lossy());
-                exit(1);
-            },
-        };
-        let reader = BufReader::new(source);
-        let lines: Vec<String> = reader.lines().map(|l| l.unwrap()).collect();
+    // The HTML and Gemini versions of the page can come from different files,
+    // the frontmatter of the HTML one wins.
+    pub fn from_sources(html_source_path: PathBuf, gemini_source_path: PathBuf, filename: &str, default_title: &str, html_options: HtmlOptions) -> About {
+        let (html_frontmatter, html_lines) = read_source(&html_source_path);
+        let (gemini_frontmatter, gemini_lines) = read_source(&gemini_source_path);
 
-        let mut about = About::default();
+        let mut about = About {
+            title: html_frontmatter.title
+                .or(gemini_frontmatter.title)
+                .unwrap_or_else(|| default_title.to_string()),
+            filename: filename.to_string(),
+            updated: html_frontmatter.updated
+                .or(gemini_frontmatter.updated)
+                .unwrap_or_default(),
+            ..Default::default()
+        };
 
         // Generate content bodies for HTML and Gemini.
-        let tokens = parse_gemtext(&lines);
-        for token in tokens {
-            about.html_content.push_str(&token.as_html())
+        let (tokens, diagnostics) = parse_gemtext_lossy(&select_output(&html_lines, "html"));
+        report_diagnostics(&html_source_path, &diagnostics);
+        let html_options = HtmlOptions {
+            raw_html: html_frontmatter.raw_html.unwrap_or(html_options.raw_html),
+            reflow: html_frontmatter.reflow.unwrap_or(html_options.reflow),
+            ..html_options
+        };
+        about.html_content = generate_html_from_tokens(&tokens, &html_options);
+        let (tokens, diagnostics) = parse_gemtext_lossy(&gemini_body(&gemini_lines));
+        if gemini_source_path != html_source_path {
+            report_diagnostics(&gemini_source_path, &diagnostics);
         }
-        about.gemini_content = lines.join("\n");
+        about.gemini_content = generate_gemini_from_tokens(&tokens);
 
         about
     }
 }
+
+// Read a source file and split off its optional frontmatter.
+fn read_source(source_path: &Path) -> (AboutFrontmatter, Vec<String>) {
+    let lines = frontmatter::read_lines(source_path);
+
+    let (frontmatter, body_start) = frontmatter::parse_optional(&lines, source_path);
+    (frontmatter, lines[body_start..].to_vec())
+}
@@ -0,0 +1,89 @@
+use std::collections::HashSet;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use std::thread::sleep;
+use std::time::Duration;
+
+use crate::gemtext::{Parser, TokenKind};
+use crate::notify::warning;
+use crate::post::Post;
+
+const SAVE_URL: &str = "https://web.archive.org/save/";
+
+// Collect every http(s) link found in the given posts, in order of first
+// appearance.
+pub fn external_links(posts: &[Post]) -> Vec<String> {
+    let mut seen = HashSet::new();
+    let mut links = Vec::new();
+
+    for post in posts {
+        for token in Parser::new(post.gemini_content.as_bytes()).flatten() {
+            if token.kind != TokenKind::Link {
+                continue;
+            }
+            if !(token.data.starts_with("http://") || token.data.starts_with("https://")) {
+                continue;
+            }
+            if seen.insert(token.data.clone()) {
+                links.push(token.data);
+            }
+        }
+    }
+
+    links
+}
+
+// Submit links that haven't been archived yet to the Wayback Machine. Every
+// successful submission is appended to the cache file immediately so an
+// interrupted run doesn't resubmit links on the next build.
+pub fn submit_links(links: &[String], cache_path: &PathBuf, rate_limit: u64) {
+    let cached: HashSet<String> = match fs::read_to_string(cache_path) {
+        Ok(c) => c.lines().map(String::from).collect(),
+        Err(_) => HashSet::new(),
+    };
+    let pending: Vec<&String> = links.iter().filter(|l| !cached.contains(*l)).collect();
+    if pending.is_empty() {
+        return;
+    }
+
+    let cache = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(cache_path);
+    let mut cache = match cache {
+        Ok(c) => c,
+        Err(_) => {
+            warning!("Could not open archive cache {}, skipping link archival",
+                &cache_path.to_string_lossy());
+            return;
+        }
+    };
+
+    let agent = ureq::AgentBuilder::new()
+        .timeout(Duration::from_secs(60))
+        .build();
+
+    for (i, link) in pending.iter().enumerate() {
+        if i > 0 {
+            sleep(Duration::from_secs(rate_limit));
+        }
+
+        println!("Archiving {}", link);
+        match agent.get(&format!("{}{}", SAVE_URL, link)).call() {
+            Ok(_) => {
+                if writeln!(cache, "{}", link).is_err() {
+                    warning!("Could not write to archive cache {}",
+                        &cache_path.to_string_lossy());
+                }
+            },
+            Err(ureq::Error::Status(429, _)) => {
+                warning!("Wayback Machine rate limit reached, remaining links will be archived next build");
+                return;
+            },
+            Err(e) => {
+                warning!("Could not archive {}: {}", link, e);
+            },
+        }
+    }
+}
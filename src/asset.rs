@@ -0,0 +1,83 @@
+use std::collections::BTreeMap;
+
+use crate::config::Site;
+use crate::export::page_path;
+use crate::gemtext::{GemtextToken, TokenKind};
+
+const ASSET_SCHEME: &str = "asset:";
+
+// Parse an assets.toml, a flat table of asset names to the path of the file
+// relative to the output roots, or a full URL.
+pub fn parse(contents: &str) -> Result<BTreeMap<String, String>, toml::de::Error> {
+    toml::from_str(contents)
+}
+
+// The URLs the assets are published at.
+pub fn urls(manifest: &BTreeMap<String, String>, site: &Site) -> BTreeMap<String, String> {
+    manifest.iter()
+        .map(|(name, path)| {
+            let url = if path.contains("://") {
+                path.clone()
+            } else {
+                page_path(site, path.trim_start_matches('/'))
+            };
+            (name.clone(), url)
+        })
+        .collect()
+}
+
+fn asset_name(url: &str) -> Option<&str> {
+    url.strip_prefix(ASSET_SCHEME)
+}
+
+fn resolve(url: &str, urls: &BTreeMap<String, String>) -> Result<Option<String>, String> {
+    match asset_name(url) {
+        Some(name) => match urls.get(name) {
+            Some(u) => Ok(Some(u.clone())),
+            None => Err(name.to_string()),
+        },
+        None => Ok(None),
+    }
+}
+
+// Replace asset: links in a token chain. Returns the name of the first asset
+// missing from the manifest.
+pub fn resolve_tokens(tokens: &[GemtextToken], urls: &BTreeMap<String, String>) -> Result<Vec<GemtextToken>, String> {
+    tokens.iter()
+        .map(|t| {
+            if !matches!(t.kind, TokenKind::Link | TokenKind::Reference) {
+                return Ok(t.clone());
+            }
+            Ok(match resolve(&t.data, urls)? {
+                Some(url) => GemtextToken { data: url, ..t.clone() },
+                None => t.clone(),
+            })
+        })
+        .collect()
+}
+
+// Replace asset: links in gemtext, where the Gemini output keeps its source.
+pub fn resolve_gemtext(content: &str, urls: &BTreeMap<String, String>) -> Result<String, String> {
+    let mut in_pft = false;
+    let lines: Result<Vec<String>, String> = content.lines()
+        .map(|line| {
+            if line.starts_with("```") {
+                in_pft = !in_pft;
+            }
+            let link = match line.strip_prefix("=>") {
+                Some(l) if !in_pft => l.trim_start(),
+                _ => return Ok(line.to_string()),
+            };
+            let (url, name) = match link.split_once(char::is_whitespace) {
+                Some((u, n)) => (u, Some(n)),
+                None => (link, None),
+            };
+            Ok(match (resolve(url, urls)?, name) {
+                (Some(u), Some(n)) => format!("=> {} {}", u, n),
+                (Some(u), None) => format!("=> {}", u),
+                (None, _) => line.to_string(),
+            })
+        })
+        .collect();
+    lines.map(|l| l.join("\n"))
+}
@@ -0,0 +1,72 @@
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+
+use crate::config::Config;
+use crate::export::page_path;
+use crate::post::Post;
+use crate::toc::heading_anchor;
+
+// The author of a post as templates get it. Whatever the config doesn't say
+// about them is empty, and so are the paths unless [author_pages] is enabled.
+#[derive(Clone, Debug, Default, Serialize, Eq, PartialEq, Ord, PartialOrd)]
+pub struct AuthorLink {
+    pub key: String,
+    pub name: String,
+    pub email: String,
+    pub url: String,
+    pub slug: String,
+    pub path: String,
+    pub gemini_path: String,
+}
+
+// An author and their posts, newest first.
+#[derive(Clone, Serialize)]
+pub struct AuthorPosts {
+    #[serde(flatten)]
+    pub link: AuthorLink,
+    pub count: usize,
+    pub posts: Vec<Post>,
+}
+
+// The author with the given key, or the site's default author for an empty
+// one. A key that isn't in [authors] is taken to be the author's name.
+pub fn link(key: &str, config: &Config) -> AuthorLink {
+    let key = match key.trim() {
+        "" => config.site.author.as_deref().unwrap_or_default().trim(),
+        k => k,
+    };
+    if key.is_empty() {
+        return AuthorLink::default();
+    }
+    let author = config.authors.get(key);
+    let slug = heading_anchor(key);
+    let pages = config.author_pages.enabled == Some(true);
+    AuthorLink {
+        key: key.to_string(),
+        name: author.map_or(key, |a| a.name.as_str()).to_string(),
+        email: author.and_then(|a| a.email.clone()).unwrap_or_default(),
+        url: author.and_then(|a| a.url.clone()).unwrap_or_default(),
+        path: if pages { page_path(&config.site, &format!("authors/{}.html", slug)) } else { String::new() },
+        gemini_path: if pages { page_path(&config.site, &format!("authors/{}.gmi", slug)) } else { String::new() },
+        slug,
+    }
+}
+
+// Every author of the posts, sorted by name. Posts without an author are
+// left out.
+pub fn collect(posts: &[Post]) -> Vec<AuthorPosts> {
+    let mut authors: BTreeMap<String, AuthorPosts> = BTreeMap::new();
+    for post in posts.iter().filter(|p| !p.author.slug.is_empty()) {
+        let author = authors.entry(post.author.slug.clone()).or_insert_with(|| AuthorPosts {
+            link: post.author.clone(),
+            count: 0,
+            posts: Vec::new(),
+        });
+        author.posts.push(post.clone());
+        author.count += 1;
+    }
+    let mut authors: Vec<AuthorPosts> = authors.into_values().collect();
+    authors.sort_by_key(|a| a.link.name.to_lowercase());
+    authors
+}
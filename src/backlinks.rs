@@ -0,0 +1,63 @@
+use serde::Serialize;
+
+use crate::config::Site;
+use crate::export::page_path;
+use crate::gemtext::{GemtextToken, TokenKind};
+
+// A post or topic linking to a topic.
+#[derive(Clone, Debug, Serialize)]
+pub struct Backlink {
+    pub title: String,
+    pub output_path: String,
+}
+
+// The output paths of this site's pages that the links in tokens point at.
+// from_path is the output path of the page the tokens are from, relative
+// links are resolved against it.
+pub fn link_targets(tokens: &[GemtextToken], from_path: &str, site: &Site) -> Vec<String> {
+    tokens.iter()
+        .filter(|t| matches!(t.kind, TokenKind::Link | TokenKind::Reference))
+        .filter_map(|t| link_target(&t.data, from_path, site))
+        .collect()
+}
+
+fn link_target(url: &str, from_path: &str, site: &Site) -> Option<String> {
+    let path_end = url.find(['?', '#']).unwrap_or(url.len());
+    let mut path = &url[..path_end];
+
+    // Absolute URLs only count when they're on the site's own host.
+    if let Some((_, rest)) = path.split_once("://") {
+        let host = site.url.trim_end_matches('/');
+        path = rest.strip_prefix(host).filter(|r| r.starts_with('/'))?;
+    }
+    if path.is_empty() {
+        return None;
+    }
+    let home = page_path(site, "");
+    let components: Vec<&str> = match path.strip_prefix(&home) {
+        Some(p) => p.split('/').collect(),
+        None if path.starts_with('/') => return None,
+        None => {
+            // Relative to the directory of the linking page.
+            let mut base: Vec<&str> = from_path.split('/').collect();
+            base.pop();
+            base.extend(path.split('/'));
+            base
+        },
+    };
+
+    let mut resolved: Vec<&str> = Vec::new();
+    for c in components {
+        match c {
+            "" | "." => {},
+            ".." => {
+                resolved.pop()?;
+            },
+            _ => resolved.push(c),
+        }
+    }
+    let resolved = resolved.join("/");
+    resolved.strip_suffix(".gmi")
+        .or_else(|| resolved.strip_suffix(".html"))
+        .map(String::from)
+}
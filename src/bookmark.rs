@@ -0,0 +1,84 @@
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+
+use crate::html::escape_text;
+use crate::timezone::SiteTimezone;
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Bookmark {
+    pub url: String,
+    pub title: String,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    pub date: String,
+    #[serde(default)]
+    pub comment: String,
+    #[serde(skip_deserializing)]
+    pub rfc_date: String,
+}
+
+#[derive(Deserialize)]
+struct BookmarksFile {
+    #[serde(default)]
+    bookmark: Vec<Bookmark>,
+}
+
+// One page of the bookmarks section. prev and next are the names of the
+// neighbouring pages without extension, empty at either end.
+#[derive(Clone, Debug, Serialize)]
+pub struct BookmarkPage {
+    pub number: usize,
+    pub filename: String,
+    pub bookmarks: Vec<Bookmark>,
+    pub prev: String,
+    pub next: String,
+}
+
+// Parse a bookmarks.toml made of [[bookmark]] tables, newest first. Returns
+// the offending date if one isn't YYYY-MM-DD.
+pub fn parse(contents: &str, timezone: SiteTimezone) -> Result<Vec<Bookmark>, String> {
+    let file: BookmarksFile = toml::from_str(contents).map_err(|e| e.to_string())?;
+    let mut bookmarks = file.bookmark;
+    for b in bookmarks.iter_mut() {
+        let date = NaiveDate::parse_from_str(&b.date, "%Y-%m-%d")
+            .map_err(|_| format!("date \"{}\" should look like 2022-01-31", b.date))?;
+        b.rfc_date = timezone.resolve(&date.and_hms(0, 0, 0)).to_rfc3339();
+    }
+    // Dates sort as text, the stable sort keeps the file order within a day.
+    bookmarks.sort_by(|a, b| b.date.cmp(&a.date));
+    Ok(bookmarks)
+}
+
+pub fn paginate(bookmarks: &[Bookmark], per_page: usize) -> Vec<BookmarkPage> {
+    let chunks: Vec<&[Bookmark]> = bookmarks.chunks(per_page.max(1)).collect();
+    let count = chunks.len();
+    chunks.into_iter()
+        .enumerate()
+        .map(|(i, chunk)| BookmarkPage {
+            number: i + 1,
+            filename: page_filename(i + 1),
+            bookmarks: chunk.to_vec(),
+            prev: if i > 0 { page_filename(i) } else { String::new() },
+            next: if i + 1 < count { page_filename(i + 2) } else { String::new() },
+        })
+        .collect()
+}
+
+fn page_filename(number: usize) -> String {
+    if number == 1 {
+        "index".to_string()
+    } else {
+        format!("page-{}", number)
+    }
+}
+
+// A copy safe to put in HTML or XML.
+pub fn escaped(bookmark: &Bookmark) -> Bookmark {
+    Bookmark {
+        url: escape_text(&bookmark.url),
+        title: escape_text(&bookmark.title),
+        tags: bookmark.tags.iter().map(|t| escape_text(t)).collect(),
+        comment: escape_text(&bookmark.comment),
+        ..bookmark.clone()
+    }
+}
@@ -0,0 +1,78 @@
+use std::fs::{self, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use crate::notify::warning;
+
+// A lock older than this is left over from a build that never finished.
+const STALE_AFTER: Duration = Duration::from_secs(60 * 60);
+
+// How often a waiting build looks at the lock again.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+// Held while a build writes its outputs so two builds of the same site, like
+// overlapping cron jobs, don't interleave their writes. The file holds the id
+// of the process and is removed when the lock is dropped.
+pub struct BuildLock {
+    path: PathBuf,
+}
+
+pub enum LockError {
+    // Another build holds the lock, with the contents of its lock file.
+    Held(String),
+    Io(std::io::Error),
+}
+
+impl Drop for BuildLock {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+// Take the lock at path. A lock left by a process that isn't running anymore
+// or older than an hour is taken over. With wait a held lock is waited for
+// instead of failing.
+pub fn acquire(path: &Path, wait: bool) -> Result<BuildLock, LockError> {
+    loop {
+        match OpenOptions::new().write(true).create_new(true).open(path) {
+            Ok(mut f) => {
+                writeln!(f, "{}", std::process::id()).map_err(LockError::Io)?;
+                return Ok(BuildLock { path: path.to_path_buf() });
+            },
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => {},
+            Err(e) => return Err(LockError::Io(e)),
+        }
+
+        let holder = fs::read_to_string(path).unwrap_or_default();
+        if is_stale(path, &holder) {
+            warning!("Removing stale build lock {}", path.to_string_lossy());
+            match fs::remove_file(path) {
+                Ok(_) => continue,
+                Err(e) if e.kind() == ErrorKind::NotFound => continue,
+                Err(e) => return Err(LockError::Io(e)),
+            }
+        }
+        if !wait {
+            return Err(LockError::Held(holder.trim().to_string()));
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+fn is_stale(path: &Path, holder: &str) -> bool {
+    let age = fs::metadata(path)
+        .and_then(|m| m.modified())
+        .ok()
+        .and_then(|m| SystemTime::now().duration_since(m).ok());
+    if age.map(|a| a > STALE_AFTER).unwrap_or(false) {
+        return true;
+    }
+    // Where running processes are listed in /proc a dead holder is noticed
+    // right away, which covers builds that exited on an error.
+    match holder.trim().parse::<u32>() {
+        Ok(pid) if Path::new("/proc/self").exists() => !Path::new("/proc").join(pid.to_string()).exists(),
+        _ => false,
+    }
+}
@@ -0,0 +1,198 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+use serde::Serialize;
+
+use crate::config::Config;
+use crate::gemtext::split_frontmatter;
+use crate::lock;
+use crate::post::Post;
+
+// A post that's new or changed since the last build.
+#[derive(Clone, Debug, Serialize)]
+pub struct ChangedPost {
+    pub title: String,
+    pub output_path: String,
+    pub url: String,
+    pub gemini_url: String,
+}
+
+// The posts a build publishes compared to the last build, so steps like
+// crossposting only run for what's actually new.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct ChangeSet {
+    pub new: Vec<ChangedPost>,
+    pub changed: Vec<ChangedPost>,
+    // Output paths of posts that were published before and aren't anymore.
+    pub removed: Vec<String>,
+}
+
+impl ChangeSet {
+    // Compare posts to the hashes of the last build by output path.
+    pub fn new<'a>(previous: &BTreeMap<String, String>, posts: impl Iterator<Item = &'a Post>) -> ChangeSet {
+        let mut changes = ChangeSet::default();
+        let mut seen = Vec::new();
+        for post in posts {
+            seen.push(post.output_path.as_str());
+            let changed = ChangedPost {
+                title: post.title.clone(),
+                output_path: post.output_path.clone(),
+                url: post.url.clone(),
+                gemini_url: post.gemini_url.clone(),
+            };
+            match previous.get(&post.output_path) {
+                None => changes.new.push(changed),
+                Some(hash) if *hash != post.source_hash => changes.changed.push(changed),
+                Some(_) => {},
+            }
+        }
+        changes.removed = previous.keys()
+            .filter(|path| !seen.contains(&path.as_str()))
+            .cloned()
+            .collect();
+        changes
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.new.is_empty() && self.changed.is_empty() && self.removed.is_empty()
+    }
+
+    pub fn summary(&self) -> String {
+        let mut text = String::new();
+        for post in &self.new {
+            text.push_str(&format!("New post {}\n", post.output_path));
+        }
+        for post in &self.changed {
+            text.push_str(&format!("Changed post {}\n", post.output_path));
+        }
+        for path in &self.removed {
+            text.push_str(&format!("Removed post {}\n", path));
+        }
+        text
+    }
+}
+
+// The hashes of the posts published by the last build, by output path, in
+// the "{hash}  {path}" lines of a lock file.
+pub fn read_cache(path: &Path) -> std::io::Result<BTreeMap<String, String>> {
+    lock::read(path)
+}
+
+pub fn write_cache<'a>(path: &Path, posts: impl Iterator<Item = &'a Post>) -> std::io::Result<()> {
+    let mut contents = String::from("# Generated by crosspub, the posts published by the last build.\n");
+    for post in posts {
+        contents.push_str(&format!("{}  {}\n", post.source_hash, post.output_path));
+    }
+    fs::write(path, contents)
+}
+
+// A source added, modified or deleted between two git refs.
+#[derive(Clone, Debug, Serialize)]
+pub struct ContentChange {
+    // "added", "modified" or "deleted".
+    pub status: String,
+    // The kind of its content directory, "post", "topic" or "page".
+    pub kind: String,
+    pub path: String,
+    // Where a renamed source was before.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub previous_path: Option<String>,
+    pub title: String,
+}
+
+// The sources in the content directories that changed between two refs, for
+// release notes and digests.
+#[derive(Clone, Debug, Serialize)]
+pub struct ContentChanges {
+    pub since: String,
+    pub until: String,
+    pub changes: Vec<ContentChange>,
+}
+
+impl ContentChanges {
+    pub fn summary(&self) -> String {
+        if self.changes.is_empty() {
+            return format!("No changes from {} to {}\n", self.since, self.until);
+        }
+        let mut text = format!("Changes from {} to {}:\n", self.since, self.until);
+        for change in &self.changes {
+            let status = format!("{}{}", change.status[..1].to_uppercase(), &change.status[1..]);
+            text.push_str(&format!("{} {} {}: {}\n", status, change.kind, change.path, change.title));
+        }
+        text
+    }
+}
+
+// Ask git which sources in the content directories of the site, or pages at
+// its root, changed between two refs. Paths are relative to the site
+// directory, titles are read from the source as of until, or since for
+// deleted ones.
+pub fn between(config: &Config, dir: &Path, since: &str, until: &str) -> Result<ContentChanges, String> {
+    let content_dirs = config.content_dirs();
+    let output = Command::new("git")
+        .arg("-C").arg(dir)
+        .args(["diff", "--name-status", "-z", "-M", "--relative", since, until, "--"])
+        .args(content_dirs.iter().map(|d| &d.path))
+        // Gemtext next to the content directories is loaded as pages too.
+        .arg(":(glob)*.gmi")
+        .output()
+        .map_err(|e| format!("could not run git: {}", e))?;
+    if !output.status.success() {
+        return Err(format!("git could not compare {} to {}: {}", since, until,
+            String::from_utf8_lossy(&output.stderr).trim()));
+    }
+
+    // Each change is a status followed by its path, or by the old and new
+    // paths for renames and copies.
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let mut fields = stdout.split('\0').filter(|f| !f.is_empty());
+    let mut changes = Vec::new();
+    while let Some(status) = fields.next() {
+        let previous_path = if status.starts_with(['R', 'C']) { fields.next() } else { None };
+        let path = match fields.next() {
+            Some(p) => p,
+            None => break,
+        };
+        let kind = match content_dirs.iter().find(|d| Path::new(path).starts_with(&d.path)) {
+            Some(d) if path.ends_with(".gmi") => d.kind.clone(),
+            None if path.ends_with(".gmi") && !path.contains('/') => "page".to_string(),
+            _ => continue,
+        };
+        let (status, at) = match status.chars().next() {
+            Some('A') | Some('C') => ("added", until),
+            Some('D') => ("deleted", since),
+            _ => ("modified", until),
+        };
+        changes.push(ContentChange {
+            status: status.to_string(),
+            kind,
+            title: title_at(dir, at, path),
+            path: path.to_string(),
+            previous_path: previous_path.filter(|_| status == "modified").map(String::from),
+        });
+    }
+    Ok(ContentChanges {
+        since: since.to_string(),
+        until: until.to_string(),
+        changes,
+    })
+}
+
+// The title of a source at a ref, from its frontmatter or first heading, or
+// its filename when it has neither.
+fn title_at(dir: &Path, at: &str, path: &str) -> String {
+    let stem = Path::new(path).file_stem().unwrap_or_default().to_string_lossy().into_owned();
+    let contents = match Command::new("git").arg("-C").arg(dir).arg("show").arg(format!("{}:./{}", at, path)).output() {
+        Ok(o) if o.status.success() => String::from_utf8_lossy(&o.stdout).into_owned(),
+        _ => return stem,
+    };
+    let lines: Vec<String> = contents.lines().map(String::from).collect();
+    let (frontmatter, body) = split_frontmatter(&lines);
+    let title = toml::from_str::<toml::Value>(&frontmatter.join("\n")).ok()
+        .and_then(|v| v.get("title").and_then(|t| t.as_str()).map(String::from));
+    title
+        .or_else(|| body.iter().find_map(|l| l.strip_prefix("# ")).map(|t| t.trim().to_string()))
+        .unwrap_or(stem)
+}
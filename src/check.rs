@@ -0,0 +1,136 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::notify::warning;
+
+// Something wrong with the built site, found after it's written.
+#[derive(Clone, Debug)]
+pub struct Finding {
+    pub file: PathBuf,
+    pub message: String,
+}
+
+// One <img> tag of a page, with the attributes the checks care about.
+struct Image {
+    src: String,
+    has_dimensions: bool,
+}
+
+// Look at every image of every HTML page under html_root. Images that are
+// missing, bigger than budget bytes or without a width and height are
+// reported. Absolute paths are resolved against html_root when they start
+// with url_prefix, other sites aren't checked.
+pub fn images(html_root: &Path, url_prefix: &str, budget: u64) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    for page in html_files(html_root) {
+        let contents = match fs::read_to_string(&page) {
+            Ok(c) => c,
+            Err(_) => continue,
+        };
+        let relative = page.strip_prefix(html_root).unwrap_or(&page).to_path_buf();
+        for image in img_tags(&contents) {
+            let path = match image_path(&image.src, &page, html_root, url_prefix) {
+                Some(p) => p,
+                None => continue,
+            };
+            let finding = |message: String| Finding { file: relative.clone(), message };
+            match fs::metadata(&path) {
+                Ok(m) if m.len() > budget => findings.push(finding(format!(
+                    "image {} is {} bytes, over the budget of {}", image.src, m.len(), budget))),
+                Ok(_) => {},
+                Err(_) => findings.push(finding(format!("image {} is missing", image.src))),
+            }
+            if !image.has_dimensions {
+                findings.push(finding(format!("image {} has no width and height", image.src)));
+            }
+        }
+    }
+    findings
+}
+
+pub fn report(findings: &[Finding]) {
+    if findings.is_empty() {
+        return;
+    }
+    eprintln!("Check report, {} problem(s) found:", findings.len());
+    for f in findings {
+        warning!("{}: {}", f.file.to_string_lossy(), f.message);
+    }
+}
+
+fn html_files(dir: &Path) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    let entries = match fs::read_dir(dir) {
+        Ok(e) => e,
+        Err(_) => return files,
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            files.append(&mut html_files(&path));
+        } else if path.extension().map(|e| e == "html").unwrap_or(false) {
+            files.push(path);
+        }
+    }
+    files.sort();
+    files
+}
+
+fn img_tags(html: &str) -> Vec<Image> {
+    let lower = html.to_ascii_lowercase();
+    let mut images = Vec::new();
+    let mut rest = 0;
+    while let Some(start) = lower[rest..].find("<img") {
+        let start = rest + start + "<img".len();
+        let end = match lower[start..].find('>') {
+            Some(e) => start + e,
+            None => break,
+        };
+        let tag = &html[start..end];
+        if let Some(src) = attribute(tag, "src") {
+            images.push(Image {
+                src,
+                has_dimensions: attribute(tag, "width").is_some()
+                    && attribute(tag, "height").is_some(),
+            });
+        }
+        rest = end;
+    }
+    images
+}
+
+// The value of a quoted attribute in the inside of a tag.
+fn attribute(tag: &str, name: &str) -> Option<String> {
+    let lower = tag.to_ascii_lowercase();
+    let mut rest = 0;
+    while let Some(found) = lower[rest..].find(name) {
+        let at = rest + found;
+        rest = at + name.len();
+        let starts_word = at == 0 || lower[..at].ends_with(char::is_whitespace);
+        let after = lower[rest..].trim_start();
+        if !starts_word || !after.starts_with('=') {
+            continue;
+        }
+        let value = tag[tag.len() - after.len() + 1..].trim_start();
+        let quote = value.chars().next()?;
+        if quote != '"' && quote != '\'' {
+            continue;
+        }
+        return value[1..].split(quote).next().map(String::from);
+    }
+    None
+}
+
+fn image_path(src: &str, page: &Path, html_root: &Path, url_prefix: &str) -> Option<PathBuf> {
+    let path_end = src.find(['?', '#']).unwrap_or(src.len());
+    let path = &src[..path_end];
+    let has_scheme = path.split('/').next().map(|s| s.contains(':')).unwrap_or(false);
+    if path.is_empty() || has_scheme || path.starts_with("//") {
+        return None;
+    }
+    if let Some(absolute) = path.strip_prefix('/') {
+        let within = absolute.strip_prefix(url_prefix.trim_start_matches('/'))?;
+        return Some(html_root.join(within.trim_start_matches('/')));
+    }
+    Some(page.parent()?.join(path))
+}
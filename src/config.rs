@@ -1,22 +1,373 @@
+use std::collections::BTreeMap;
+
+use schemars::JsonSchema;
 use serde::{Serialize, Deserialize};
 
-#[derive(Clone, Default, Serialize, Deserialize)]
+#[derive(Clone, Default, Serialize, Deserialize, JsonSchema)]
 pub struct Config {
     pub site: Site,
     pub homepage: Homepage,
+    #[serde(default)]
+    pub archive: Archive,
+    #[serde(default)]
+    pub glossary: Glossary,
+    #[serde(default)]
+    pub wikilinks: Wikilinks,
+    #[serde(default)]
+    pub link_titles: LinkTitles,
+    #[serde(default)]
+    pub feeds: Feeds,
+    #[serde(default)]
+    pub fragments: Fragments,
+    #[serde(default)]
+    pub print: Print,
+    #[serde(default)]
+    pub attachments: Attachments,
+    #[serde(default)]
+    pub sqlite: Sqlite,
+    #[serde(default)]
+    pub search: Search,
+    #[serde(default)]
+    pub build: Build,
+    #[serde(default)]
+    pub html: Html,
+    #[serde(default)]
+    pub check: Check,
+    #[serde(default)]
+    pub lint: Lint,
+    #[serde(default)]
+    pub websub: WebSub,
+    #[serde(default)]
+    pub notify: Notify,
+    #[serde(default)]
+    pub changes: Changes,
+    #[serde(default)]
+    pub nostr: Nostr,
+    #[serde(default)]
+    pub s3: S3,
+    #[serde(default)]
+    pub meta: MetaFiles,
+    #[serde(default)]
+    pub stats: StatsPage,
+    #[serde(default)]
+    pub tags: Tags,
+    #[serde(default)]
+    pub authors: BTreeMap<String, Author>,
+    #[serde(default)]
+    pub author_pages: AuthorPages,
+    #[serde(default)]
+    pub link_log: LinkLog,
+    #[serde(default)]
+    pub bookmarks: Bookmarks,
+    #[serde(default)]
+    pub digest: Digest,
+    #[serde(default)]
+    pub profile: BTreeMap<String, Profile>,
+    #[serde(default)]
+    pub standalone: Vec<Standalone>,
 }
 
-#[derive(Clone, Default, Serialize, Deserialize)]
+impl Config {
+    // The directories content is loaded from, posts/, topics/ and pages/
+    // unless the config says otherwise.
+    pub fn content_dirs(&self) -> Vec<ContentDir> {
+        match &self.build.content_dirs {
+            Some(dirs) => dirs.clone(),
+            None => vec![
+                ContentDir { path: "posts".to_string(), kind: "post".to_string() },
+                ContentDir { path: "topics".to_string(), kind: "topic".to_string() },
+                ContentDir { path: "pages".to_string(), kind: "page".to_string() },
+            ],
+        }
+    }
+
+    // Override the config with the profile of the given name. Returns false if
+    // there is no such profile.
+    pub fn apply_profile(&mut self, name: &str) -> bool {
+        let profile = match self.profile.get(name) {
+            Some(p) => p.clone(),
+            None => return false,
+        };
+        if let Some(n) = profile.name {
+            self.site.name = n;
+        }
+        if let Some(u) = profile.url {
+            self.site.url = u;
+        }
+        if let Some(u) = profile.username {
+            self.site.username = u;
+        }
+        if let Some(h) = profile.html_root {
+            self.site.html_root = h;
+        }
+        if let Some(g) = profile.gemini_root {
+            self.site.gemini_root = g;
+        }
+        if let Some(t) = profile.theme {
+            self.site.theme = t;
+        }
+        if profile.only.is_some() {
+            self.build.only = profile.only;
+        }
+        if profile.drafts.is_some() {
+            self.build.drafts = profile.drafts;
+        }
+        true
+    }
+}
+
+#[derive(Clone, Default, Serialize, Deserialize, JsonSchema)]
 pub struct Site {
     pub name: String,
     pub url: String,
     pub username: String,
+    #[serde(default)]
     pub html_root: String,
+    #[serde(default)]
     pub gemini_root: String,
+    #[serde(default)]
+    pub theme: String,
+    pub timezone: Option<String>,
+    // The key in [authors] of whoever wrote the posts that don't say.
+    pub author: Option<String>,
 }
 
-#[derive(Clone, Default, Serialize, Deserialize)]
+#[derive(Clone, Default, Serialize, Deserialize, JsonSchema)]
 pub struct Homepage {
     pub post_list: Option<bool>,
+    pub post_list_per_page: Option<usize>,
     pub use_about_page: Option<bool>,
+    pub recent_posts: Option<usize>,
+    pub highlights: Option<usize>,
+}
+
+#[derive(Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct Archive {
+    pub enabled: Option<bool>,
+    pub rate_limit: Option<u64>,
+    pub cache: Option<String>,
+}
+
+#[derive(Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct Glossary {
+    pub enabled: Option<bool>,
+    pub topic: Option<String>,
+}
+
+#[derive(Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct Wikilinks {
+    pub enabled: Option<bool>,
+    pub strict: Option<bool>,
+}
+
+#[derive(Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct LinkTitles {
+    pub enabled: Option<bool>,
+    pub cache: Option<String>,
+    pub offline: Option<bool>,
+}
+
+#[derive(Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct Feeds {
+    pub html: Option<bool>,
+    pub gemini: Option<bool>,
+    pub main_kinds: Option<Vec<String>>,
+    pub per_kind: Option<bool>,
+    pub per_lang: Option<bool>,
+    pub summary_length: Option<usize>,
+}
+
+#[derive(Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct WebSub {
+    pub hub: Option<String>,
+}
+
+#[derive(Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct Changes {
+    pub enabled: Option<bool>,
+    pub cache: Option<String>,
+    pub manifest: Option<String>,
+}
+
+#[derive(Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct Notify {
+    pub webhook: Option<String>,
+    pub email: Option<String>,
+    pub sendmail: Option<String>,
+}
+
+#[derive(Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct Nostr {
+    pub secret_key: Option<String>,
+    pub cache: Option<String>,
+}
+
+#[derive(Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct S3 {
+    pub enabled: Option<bool>,
+    pub bucket: Option<String>,
+    pub endpoint: Option<String>,
+    pub region: Option<String>,
+    pub prefix: Option<String>,
+    pub cache_control: Option<String>,
+    pub page_cache_control: Option<String>,
+    #[serde(default)]
+    pub content_types: BTreeMap<String, String>,
+    pub delete_removed: Option<bool>,
+    pub manifest: Option<String>,
+}
+
+#[derive(Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct MetaFiles {
+    pub enabled: Option<bool>,
+    pub lang: Option<String>,
+}
+
+#[derive(Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct StatsPage {
+    pub enabled: Option<bool>,
+}
+
+#[derive(Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct Tags {
+    pub enabled: Option<bool>,
+}
+
+// Someone writing on the site, posts name them by their key in [authors].
+#[derive(Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct Author {
+    pub name: String,
+    pub email: Option<String>,
+    pub url: Option<String>,
+}
+
+#[derive(Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct AuthorPages {
+    pub enabled: Option<bool>,
+}
+
+#[derive(Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct LinkLog {
+    pub enabled: Option<bool>,
+    pub filename: Option<String>,
+}
+
+#[derive(Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct Bookmarks {
+    pub per_page: Option<usize>,
+    pub feed: Option<bool>,
+}
+
+#[derive(Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct Digest {
+    pub period: Option<String>,
+    pub count: Option<usize>,
+}
+
+#[derive(Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct Fragments {
+    pub enabled: Option<bool>,
+}
+
+#[derive(Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct Attachments {
+    pub enabled: Option<bool>,
+    pub extensions: Option<Vec<String>>,
+}
+
+#[derive(Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct Print {
+    pub enabled: Option<bool>,
+}
+
+#[derive(Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct Sqlite {
+    pub enabled: Option<bool>,
+    pub path: Option<String>,
+}
+
+#[derive(Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct Search {
+    pub enabled: Option<bool>,
+    pub cgi_path: Option<String>,
+    pub index: Option<String>,
+}
+
+#[derive(Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct Build {
+    pub only: Option<String>,
+    pub drafts: Option<bool>,
+    pub metadata: Option<bool>,
+    pub content_dirs: Option<Vec<ContentDir>>,
+    pub follow_symlinks: Option<bool>,
+    pub includes_dir: Option<String>,
+    pub raw_html: Option<bool>,
+    pub rewrite_links: Option<bool>,
+    pub post_date_prefix: Option<String>,
+    pub old_post_date_prefix: Option<String>,
+}
+
+#[derive(Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct Html {
+    pub inline_images: Option<bool>,
+    pub figures: Option<bool>,
+    pub ordered_lists: Option<bool>,
+    pub inline_formatting: Option<bool>,
+    pub heading_ids: Option<bool>,
+    pub heading_links: Option<bool>,
+    pub gemini_proxy: Option<String>,
+    pub gopher_proxy: Option<String>,
+    pub scheme_badges: Option<bool>,
+    pub external_rel: Option<String>,
+    pub external_target: Option<String>,
+    pub smart_typography: Option<bool>,
+    pub reflow: Option<bool>,
+    pub lede: Option<bool>,
+    pub sections: Option<bool>,
+}
+
+#[derive(Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct Check {
+    pub images: Option<bool>,
+    pub image_budget: Option<u64>,
+}
+
+// Optional rules for crosspub lint, each "off", "warn" or "error".
+#[derive(Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct Lint {
+    #[schemars(extend("enum" = ["off", "warn", "error"]))]
+    pub preformatted_alt: Option<String>,
+    #[schemars(extend("enum" = ["off", "warn", "error"]))]
+    pub image_description: Option<String>,
+}
+
+// A directory of posts, topics or pages, relative to the site directory. Page
+// directories are optional.
+#[derive(Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct ContentDir {
+    pub path: String,
+    pub kind: String,
+}
+
+// A page rendered with the about templates.
+#[derive(Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct Standalone {
+    pub source: String,
+    pub output: String,
+    pub html_source: Option<String>,
+    pub gemini_source: Option<String>,
+}
+
+// Settings that replace the ones in [site] and [build] when building with
+// --profile.
+#[derive(Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct Profile {
+    pub name: Option<String>,
+    pub url: Option<String>,
+    pub username: Option<String>,
+    pub html_root: Option<String>,
+    pub gemini_root: Option<String>,
+    pub theme: Option<String>,
+    pub only: Option<String>,
+    pub drafts: Option<bool>,
 }
@@ -1,51 +1,240 @@
 use serde::Serialize;
 
 use crate::about::About;
+use crate::authors::AuthorPosts;
+use crate::backlinks::Backlink;
+use crate::bookmark::{Bookmark, BookmarkPage};
+use crate::digest::Digest;
+use crate::outbound::OutboundLink;
+use crate::page::{Page, PageLink};
 use crate::post::Post;
+use crate::stats::Stats;
+use crate::tags::{Tag, TagLink};
+use crate::toc::Toc;
 use crate::topic::Topic;
 use crate::config::Site;
 
+// When and with what the site was built.
+#[derive(Clone, Serialize)]
+pub struct BuildInfo {
+    pub time: String,
+    pub version: String,
+    pub emit_metadata: bool,
+}
+
 #[derive(Serialize)]
 pub struct PostContext {
     pub site: Site,
+    pub output_target: String,
+    pub build: BuildInfo,
     pub post: Post,
+    pub toc: Toc,
+    // Links to the pages of the post's tags, empty without tag pages.
+    pub tags: Vec<TagLink>,
+    pub pagination: Pagination,
     pub has_about: bool,
+    pub pages: Vec<PageLink>,
+    pub print_css: bool,
+}
+
+// The pages of a post split by paginate_by_heading, with the ones before and
+// after the current page. Empty for posts on a single page.
+#[derive(Clone, Default, Serialize)]
+pub struct Pagination {
+    pub pages: Vec<PaginationLink>,
+    pub previous: String,
+    pub next: String,
+}
+
+#[derive(Clone, Serialize)]
+pub struct PaginationLink {
+    pub number: usize,
+    pub title: String,
+    pub path: String,
+    pub current: bool,
 }
 
 #[derive(Serialize)]
 pub struct TopicContext {
     pub site: Site,
+    pub output_target: String,
+    pub build: BuildInfo,
     pub topic: Topic,
+    pub backlinks: Vec<Backlink>,
+    pub has_about: bool,
+    pub pages: Vec<PageLink>,
+    pub recent_posts: Vec<Post>,
+}
+
+#[derive(Serialize)]
+pub struct PageContext {
+    pub site: Site,
+    pub output_target: String,
+    pub build: BuildInfo,
+    pub page: Page,
     pub has_about: bool,
+    pub pages: Vec<PageLink>,
+    pub recent_posts: Vec<Post>,
 }
 
 #[derive(Serialize)]
 pub struct IndexContext {
     pub site: Site,
+    pub output_target: String,
+    pub build: BuildInfo,
     pub posts: Vec<Post>,
+    pub notes: Vec<Post>,
+    pub has_notes: bool,
     pub latest_post: Post,
+    pub highlights: Vec<Post>,
+    pub has_highlights: bool,
     pub topics: Vec<Topic>,
     pub has_topics: bool,
     pub has_about: bool,
+    pub pages: Vec<PageLink>,
+    pub listing: ListingPage,
+}
+
+// Where a page of the post listing is among the others, with the paths of the
+// pages before and after it in the same output. Empty for the index and
+// listings on a single page.
+#[derive(Clone, Default, Serialize)]
+pub struct ListingPage {
+    pub number: usize,
+    pub count: usize,
+    pub prev: String,
+    pub next: String,
+}
+
+#[derive(Serialize)]
+pub struct StatsContext {
+    pub site: Site,
+    pub output_target: String,
+    pub build: BuildInfo,
+    pub stats: Stats,
+    pub has_about: bool,
+    pub pages: Vec<PageLink>,
+}
+
+#[derive(Serialize)]
+pub struct TagContext {
+    pub site: Site,
+    pub output_target: String,
+    pub build: BuildInfo,
+    pub tag: Tag,
+    pub has_about: bool,
+    pub pages: Vec<PageLink>,
+}
+
+#[derive(Serialize)]
+pub struct TagsContext {
+    pub site: Site,
+    pub output_target: String,
+    pub build: BuildInfo,
+    pub tags: Vec<Tag>,
+    pub has_about: bool,
+    pub pages: Vec<PageLink>,
+}
+
+#[derive(Serialize)]
+pub struct AuthorContext {
+    pub site: Site,
+    pub output_target: String,
+    pub build: BuildInfo,
+    pub author: AuthorPosts,
+    pub has_about: bool,
+    pub pages: Vec<PageLink>,
+}
+
+#[derive(Serialize)]
+pub struct AuthorsContext {
+    pub site: Site,
+    pub output_target: String,
+    pub build: BuildInfo,
+    pub authors: Vec<AuthorPosts>,
+    pub has_about: bool,
+    pub pages: Vec<PageLink>,
+}
+
+#[derive(Serialize)]
+pub struct LinkLogContext {
+    pub site: Site,
+    pub output_target: String,
+    pub build: BuildInfo,
+    pub links: Vec<OutboundLink>,
+    pub has_about: bool,
+    pub pages: Vec<PageLink>,
+}
+
+#[derive(Serialize)]
+pub struct BookmarksContext {
+    pub site: Site,
+    pub output_target: String,
+    pub build: BuildInfo,
+    pub page: BookmarkPage,
+    pub has_about: bool,
+    pub pages: Vec<PageLink>,
+}
+
+#[derive(Serialize)]
+pub struct BookmarkFeedContext {
+    pub site: Site,
+    pub output_target: String,
+    pub build: BuildInfo,
+    pub last_updated: String,
+    pub feed_url: String,
+    pub bookmarks: Vec<Bookmark>,
+}
+
+#[derive(Serialize)]
+pub struct DigestContext {
+    pub site: Site,
+    pub output_target: String,
+    pub build: BuildInfo,
+    pub digests: Vec<Digest>,
+    pub has_about: bool,
+    pub pages: Vec<PageLink>,
+}
+
+#[derive(Serialize)]
+pub struct DigestFeedContext {
+    pub site: Site,
+    pub output_target: String,
+    pub build: BuildInfo,
+    pub last_updated: String,
+    pub feed_url: String,
+    pub digests: Vec<Digest>,
 }
 
 #[derive(Serialize)]
 pub struct AboutContext {
     pub site: Site,
+    pub output_target: String,
+    pub build: BuildInfo,
     pub about: About,
     pub has_about: bool,
+    pub pages: Vec<PageLink>,
+    pub recent_posts: Vec<Post>,
 }
 
 #[derive(Serialize)]
 pub struct AtomFeedContext {
     pub site: Site,
+    pub output_target: String,
+    pub build: BuildInfo,
     pub last_updated: String,
     pub entries: Vec<String>,
+    pub feed_url: String,
+    pub hub: String,
 }
 
 #[derive(Serialize)]
 pub struct AtomEntryContext {
     pub site: Site,
+    pub output_target: String,
+    pub build: BuildInfo,
     pub post: Post,
     pub rfc_date: String,
+    pub rfc_updated: String,
+    pub summary: String,
 }
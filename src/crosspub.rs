@@ -1,23 +1,57 @@
+use std::collections::{BTreeMap, HashSet};
 use std::io::Read;
 use std::io::Write as IoWrite;
 use std::fmt::Write;
 use std::fs::{self, OpenOptions, read_dir};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::process::exit;
+use std::rc::Rc;
+use std::time::Instant;
 
-use clap::Parser;
-use chrono::{
-    DateTime,
-    offset::{Local, TimeZone},
-    NaiveDate,
-};
+use clap::{ArgEnum, Parser, Subcommand};
+use chrono::{DateTime, FixedOffset, NaiveDate};
+use serde::Serialize;
 use serde_json::Value;
+use ignore::WalkBuilder;
 use tinytemplate::TinyTemplate;
 
 use crate::about::About;
+use crate::archive;
+use crate::asset;
+use crate::authors;
+use crate::backlinks::{self, Backlink};
+use crate::bookmark::{self, Bookmark, BookmarkPage};
+use crate::changes::{self, ChangeSet};
+use crate::check;
 use crate::contexts::*;
-use crate::post::Post;
+use crate::digest::{self, Period};
+use crate::export::{page_path, page_url, Export};
+use crate::s3::S3Store;
+use crate::search::{self, SearchEntry};
+use crate::store::{ContentStore, LocalStore, MemoryStore};
+use crate::selfcheck;
+use crate::sqlite;
+use crate::stats;
+use crate::tags::{self, TagLink};
+use crate::gemtext::GemtextToken;
+use crate::glossary;
+use crate::linktitle;
+use crate::lock;
+use crate::markdown::generate_markdown_from_tokens;
+use crate::meta;
+use crate::nostr::Event;
+use crate::notify::{self, warning};
+use crate::html::{generate_html_from_tokens, prepare_tokens, render_tokens, HtmlOptions};
+use crate::outbound;
+use crate::page::{Page, PageLink};
+use crate::post::{self, Post, PostKind, DEFAULT_DATE_PREFIX};
+use crate::redirect::{self, Redirect};
+use crate::timezone::SiteTimezone;
+use crate::timings::Timings;
+use crate::toc;
 use crate::topic::Topic;
+use crate::websub;
+use crate::wikilink;
 use crate::config::Config;
 
 #[derive(Clone, Default, Parser)]
@@ -32,39 +66,317 @@ pub struct Args {
     #[clap(parse(from_os_str))]
     pub dir: Option<std::path::PathBuf>,
 
+    /// Look for templates and about files in this directory before ~/.local/share/crosspub
+    #[clap(long, parse(from_os_str))]
+    pub data_dir: Option<std::path::PathBuf>,
+
     /// Initialize a directory for crosspub
     #[clap(long)]
     pub init: bool,
+
+    /// Only generate one kind of output
+    #[clap(long, arg_enum)]
+    pub only: Option<Output>,
+
+    /// Build with the settings of a [profile.NAME] section of the config
+    #[clap(long)]
+    pub profile: Option<String>,
+
+    /// Only publish the posts recorded in crosspub.lock
+    #[clap(long)]
+    pub locked: bool,
+
+    /// Don't access the network while building
+    #[clap(long)]
+    pub offline: bool,
+
+    /// Wait for another build of the site to finish instead of failing
+    #[clap(long)]
+    pub wait: bool,
+
+    /// Check that every post converts between gemtext and HTML without loss
+    #[clap(long)]
+    pub self_check: bool,
+
+    /// Build without writing anything and list the output files that would change
+    #[clap(long)]
+    pub dry_run: bool,
+
+    /// Report the time spent in each phase of the build and on the N slowest pages
+    #[clap(long, value_name = "N", min_values = 0, max_values = 1, require_equals = true, default_missing_value = "10")]
+    pub timings: Option<usize>,
+
+    /// Only render the pages of a template, given by its path under templates/ like html/post.html
+    #[clap(long, value_name = "TEMPLATE")]
+    pub rerender: Vec<String>,
+
+    // The time of the build in seconds since the epoch, taking precedence over
+    // SOURCE_DATE_EPOCH. Set by crosspub::testing, there's no flag for it.
+    #[clap(skip)]
+    pub source_date_epoch: Option<i64>,
+
+    #[clap(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Clone, ArgEnum)]
+pub enum Output {
+    Html,
+    Gemini,
+}
+
+#[derive(Clone, Subcommand)]
+pub enum Command {
+    /// Print all parsed posts and topics in a machine readable format
+    Export {
+        #[clap(arg_enum)]
+        format: ExportFormat,
+    },
+    /// Record the current posts in crosspub.lock for later --locked builds
+    Lock,
+    /// Check that the config, templates and directories a build needs exist
+    Doctor {
+        /// Install missing default templates and config, and create missing output directories
+        #[clap(long)]
+        repair: bool,
+    },
+    /// Parse every source strictly and report problems with their file and line
+    Lint,
+    /// List the posts, topics and pages git saw added, modified or deleted between two refs
+    Changes {
+        /// The ref to compare from, like the tag of the last release
+        #[clap(long)]
+        since: String,
+        /// The ref to compare to
+        #[clap(long, default_value = "HEAD")]
+        until: String,
+        /// Print the changes as JSON instead of a summary
+        #[clap(long)]
+        json: bool,
+    },
+    /// Print a JSON Schema of config.toml or a kind of frontmatter for editors
+    Schema {
+        #[clap(arg_enum)]
+        kind: SchemaKind,
+    },
+    /// Serve the HTML output locally, rebuilding and reloading it when sources or templates change
+    Serve {
+        /// The port to listen on, on localhost
+        #[clap(long, default_value = "8000")]
+        port: u16,
+    },
+    /// Parse gemtext to JSON tokens and render them back, for other tools
+    Tokens {
+        #[clap(subcommand)]
+        command: TokensCommand,
+    },
+    /// Work on templates without building the site
+    Template {
+        #[clap(subcommand)]
+        command: TemplateCommand,
+    },
+    /// Answer a Gemini CGI search request, used by the generated search script
+    #[clap(hide = true)]
+    SearchCgi {
+        #[clap(long, parse(from_os_str))]
+        index: PathBuf,
+    },
+}
+
+#[derive(Clone, Subcommand)]
+pub enum TemplateCommand {
+    /// Render one template with a sample post and print the result
+    Test {
+        /// A template file, or a path like html/post.html in the templates directory
+        template: String,
+        /// The gemtext source shown as the post, topic or page of the template
+        #[clap(long = "with", parse(from_os_str))]
+        sample: PathBuf,
+    },
+}
+
+#[derive(Clone, Subcommand)]
+pub enum TokensCommand {
+    /// Print the tokens of a gemtext file, or - for standard input, as JSON
+    Parse {
+        #[clap(parse(from_os_str))]
+        file: PathBuf,
+        /// The output whose lines are parsed, which decides the html-only and gemini-only sections kept
+        #[clap(long, arg_enum, default_value = "html")]
+        output: Output,
+    },
+    /// Render JSON tokens from `tokens parse`, or - for standard input, with the site's settings
+    Render {
+        #[clap(parse(from_os_str))]
+        file: PathBuf,
+        #[clap(long, arg_enum, default_value = "html")]
+        output: Output,
+    },
+}
+
+#[derive(Clone, ArgEnum)]
+pub enum ExportFormat {
+    Json,
+    Nostr,
+}
+
+#[derive(Clone, ArgEnum)]
+pub enum SchemaKind {
+    Config,
+    Post,
+    Topic,
+    Page,
+    About,
 }
 
+// Attachments copied when the config doesn't list any extensions.
+const DEFAULT_ATTACHMENT_EXTENSIONS: [&str; 9] = ["png", "jpg", "jpeg", "gif", "webp", "svg", "pdf", "txt", "mp3"];
+
+// The default size limit of a single image, in bytes.
+const DEFAULT_IMAGE_BUDGET: u64 = 100_000;
+
+// Generated pages at the root of the site that a standalone page can't replace.
+const RESERVED_PAGE_NAMES: [&str; 2] = ["index", "about"];
+
 pub struct CrossPub {
     config: Config,
+    dir: PathBuf,
     latest_post: Post,
+    // Posts picked by featured_weight for the index templates.
+    highlights: Vec<Post>,
+    // The posts and topics linking to each topic, by its output path.
+    backlinks: BTreeMap<String, Vec<Backlink>>,
+    // When the build started, for the build report.
+    started: Instant,
+    // The posts new or changed since the last build, when changes are tracked.
+    changes: Option<ChangeSet>,
     posts: Vec<Post>,
+    notes: Vec<Post>,
     topics: Vec<Topic>,
-    about: About,
+    pages: Vec<Page>,
+    // The about page and any other pages using the about templates.
+    standalone: Vec<About>,
     xdg_dirs: xdg::BaseDirectories,
+    // Searched for data files before the XDG data directories.
+    data_dir: Option<PathBuf>,
     post_listing: bool,
     has_about: bool,
+    archive_links: bool,
+    main_feed_kinds: Vec<PostKind>,
+    per_kind_feeds: bool,
+    digest_period: Option<Period>,
+    html_fragments: bool,
+    print_css: bool,
+    html_output: bool,
+    gemini_output: bool,
+    offline: bool,
+    timezone: SiteTimezone,
+    // Posts allowed in a --locked build, and the hashes of the posts loaded.
+    locked: Option<BTreeMap<String, String>>,
+    post_hashes: BTreeMap<String, String>,
+    build_info: BuildInfo,
+    // How the date in front of post filenames is formatted.
+    date_prefix: String,
+    // Files copied to the output roots, with their path relative to a root.
+    attachments: Vec<(PathBuf, PathBuf)>,
+    // Where outputs are written, and the same store when it's a dry run so
+    // what it holds can be listed afterwards.
+    store: Rc<dyn ContentStore>,
+    dry_run: Option<Rc<MemoryStore>>,
+    timings: Timings,
+    // How many of the slowest pages to report, when reporting timings.
+    slowest_pages: Option<usize>,
+    // A fixed time for the build, when it isn't taken from the clock.
+    source_date_epoch: Option<i64>,
 }
 
 impl CrossPub {
     pub fn new(c: &Config, a: &Args) -> CrossPub {
+        let timezone = match SiteTimezone::parse(c.site.timezone.as_deref()) {
+            Ok(t) => t,
+            Err(_) => {
+                eprintln!("Error: Unknown timezone \"{}\" in config.toml, use a name like \"Europe/Berlin\"",
+                    c.site.timezone.as_deref().unwrap_or_default());
+                exit(1);
+            }
+        };
+        // A dry run is offline too, so nothing leaves the machine.
+        let dry_run = a.dry_run.then(|| Rc::new(MemoryStore::default()));
+        let store: Rc<dyn ContentStore> = match &dry_run {
+            Some(m) => m.clone(),
+            None if c.s3.enabled == Some(true) => Rc::new(s3_store(c)),
+            None => Rc::new(LocalStore),
+        };
         let mut cp = CrossPub {
             config: c.clone(),
+            dir: a.dir.clone().unwrap_or_else(|| PathBuf::from(".")),
             latest_post: Post::default(),
+            highlights: Vec::new(),
+            backlinks: BTreeMap::new(),
+            started: Instant::now(),
+            timings: Timings::default(),
+            slowest_pages: a.timings,
+            source_date_epoch: a.source_date_epoch,
+            changes: None,
             posts: Vec::new(),
+            notes: Vec::new(),
             topics: Vec::new(),
-            about: About::default(),
+            pages: Vec::new(),
+            standalone: Vec::new(),
             xdg_dirs: xdg::BaseDirectories::with_prefix("crosspub").unwrap(),
+            data_dir: a.data_dir.clone(),
             post_listing: false,
             has_about: false,
+            archive_links: false,
+            main_feed_kinds: vec![
+                PostKind::Article,
+                PostKind::Link,
+                PostKind::Recipe,
+                PostKind::Review,
+            ],
+            per_kind_feeds: false,
+            digest_period: None,
+            html_fragments: false,
+            print_css: false,
+            html_output: true,
+            gemini_output: true,
+            offline: a.offline || a.dry_run,
+            timezone,
+            locked: None,
+            post_hashes: BTreeMap::new(),
+            build_info: BuildInfo {
+                time: timezone.now(a.source_date_epoch).to_rfc3339(),
+                version: env!("CARGO_PKG_VERSION").to_string(),
+                emit_metadata: c.build.metadata.unwrap_or(false),
+            },
+            date_prefix: c.build.post_date_prefix.clone().unwrap_or_else(|| DEFAULT_DATE_PREFIX.to_string()),
+            attachments: Vec::new(),
+            store,
+            dry_run,
         };
+        let loading = cp.timings.start();
+
+        for prefix in [Some(&cp.date_prefix), c.build.old_post_date_prefix.as_ref()].into_iter().flatten() {
+            if !post::valid_date_prefix(prefix) {
+                eprintln!("Error: Date prefix \"{}\" in config.toml isn't a valid date format like \"%Y%m%d\"", prefix);
+                exit(1);
+            }
+        }
+
+        if a.locked {
+            match lock::read(&cp.lock_path()) {
+                Ok(l) => cp.locked = Some(l),
+                Err(_) => {
+                    eprintln!("Error: --locked needs a crosspub.lock, create one with crosspub lock");
+                    exit(1);
+                }
+            }
+        }
         
-        if let Some(d) = &a.dir {
-            cp.load_dir(d.to_path_buf());
-        } else {
-            cp.load_dir(PathBuf::from("."));
+        cp.load_dir(cp.dir.clone());
+
+        if a.self_check {
+            cp.self_check();
         }
 
         if cp.posts.is_empty() {
@@ -80,98 +392,1336 @@ impl CrossPub {
             cp.has_about = a;
         }
 
+        if let Some(a) = c.archive.enabled {
+            cp.archive_links = a && !cp.offline;
+        }
+
+        if let Some(kinds) = &c.feeds.main_kinds {
+            cp.main_feed_kinds = kinds.iter()
+                .map(|k| match PostKind::from_name(k) {
+                    Some(kind) => kind,
+                    None => {
+                        eprintln!("Error: Unknown post kind \"{}\" in feeds.main_kinds", k);
+                        exit(1);
+                    }
+                })
+                .collect();
+        }
+
+        if let Some(pk) = c.feeds.per_kind {
+            cp.per_kind_feeds = pk;
+        }
+
+        if let Some(p) = &c.digest.period {
+            match Period::from_name(p) {
+                Some(period) => cp.digest_period = Some(period),
+                None => {
+                    eprintln!("Error: Unknown digest period \"{}\", expected \"weekly\" or \"monthly\"", p);
+                    exit(1);
+                }
+            }
+        }
+
+        // The command line wins over the config.
+        let only = match (&a.only, c.build.only.as_deref()) {
+            (Some(o), _) => Some(o.clone()),
+            (None, Some("html")) => Some(Output::Html),
+            (None, Some("gemini")) => Some(Output::Gemini),
+            (None, None) => None,
+            (None, Some(o)) => {
+                eprintln!("Error: Unknown output \"{}\" in build.only, expected \"html\" or \"gemini\"", o);
+                exit(1);
+            }
+        };
+        match only {
+            Some(Output::Html) => cp.gemini_output = false,
+            Some(Output::Gemini) => cp.html_output = false,
+            None => {},
+        }
+        if cp.html_output && c.site.html_root.is_empty() {
+            eprintln!("Error: html_root is not set in config.toml");
+            exit(1);
+        }
+        if cp.gemini_output && c.site.gemini_root.is_empty() {
+            eprintln!("Error: gemini_root is not set in config.toml");
+            exit(1);
+        }
+
+        if let Some(f) = c.fragments.enabled {
+            cp.html_fragments = f;
+        }
+
+        if let Some(p) = c.print.enabled {
+            cp.print_css = p;
+        }
+
+        cp.resolve_assets();
+        cp.check_includes();
+
+        if let Some(true) = c.link_titles.enabled {
+            cp.name_bare_links();
+        }
+
+        cp.set_urls();
+        cp.set_summaries();
+        cp.set_authors();
+        cp.link_pages();
+        cp.collect_backlinks();
+
         cp.latest_post = cp.posts[0].clone();
+        cp.highlights = cp.pick_highlights();
 
+        let html_options = cp.html_options();
         if cp.has_about {
-            let about_source_path = match cp.xdg_dirs.find_data_file("about.gmi") {
-                Some(a) => a,
+            // about-html.gmi and about-gemini.gmi replace about.gmi for one
+            // protocol.
+            let about_source_path = cp.find_data_file("about.gmi");
+            let html_source_path = cp.find_data_file("about-html.gmi")
+                .or_else(|| about_source_path.clone());
+            let gemini_source_path = cp.find_data_file("about-gemini.gmi")
+                .or(about_source_path);
+            let about = match (html_source_path, gemini_source_path) {
+                (Some(h), Some(g)) => About::from_sources(h, g, "about", "About", html_options.clone()),
                 _ => {
                     eprintln!("Error: Could not find about.gmi file in ~/.local/share/crosspub");
                     exit(1);
                 }
             };
-            cp.about = About::from_source(about_source_path);
+            cp.standalone.push(about);
+        }
+
+        for s in &c.standalone {
+            let source_path = cp.data_source(&s.source);
+            let html_source_path = s.html_source.as_ref().map(|h| cp.data_source(h))
+                .unwrap_or_else(|| source_path.clone());
+            let gemini_source_path = s.gemini_source.as_ref().map(|g| cp.data_source(g))
+                .unwrap_or(source_path);
+            let taken = s.output == "index"
+                || cp.generated_page_names().contains(&s.output)
+                || cp.standalone.iter().any(|a| a.filename == s.output)
+                || cp.pages.iter().any(|p| p.output_path == s.output)
+                || cp.topics.iter().any(|t| t.output_path == s.output);
+            if taken {
+                eprintln!("Error: Standalone page output {} is already used by another page", s.output);
+                exit(1);
+            }
+            cp.standalone.push(About::from_sources(html_source_path, gemini_source_path, &s.output, &s.output, html_options.clone()));
         }
 
+        cp.check_output_paths();
+
+        if let Some(true) = c.changes.enabled {
+            cp.changes = Some(cp.find_changes());
+        }
+
+        cp.timings.phase("load", loading);
         cp
     }
 
+    // Output paths from frontmatter can put a page anywhere, make sure they
+    // stay inside the output roots and don't overwrite each other or the
+    // pages crosspub generates.
+    fn check_output_paths(&self) {
+        let mut taken: HashSet<String> = ["index", "posts/notes"].iter()
+            .map(|p| p.to_string())
+            .chain(self.post_listing_output_paths())
+            .chain(self.generated_page_names())
+            .chain(self.standalone.iter().map(|a| a.filename.clone()))
+            .collect();
+        // Paginated posts take a path for each of their pages.
+        let paths = self.posts.iter().chain(&self.notes).flat_map(|p| p.html_page_output_paths())
+            .chain(self.topics.iter().map(|t| t.output_path.clone()))
+            .chain(self.pages.iter().map(|p| p.output_path.clone()));
+        for path in paths {
+            let inside = !path.is_empty()
+                && Path::new(&path).components().all(|c| matches!(c, std::path::Component::Normal(_)));
+            if !inside {
+                eprintln!("Error: Output path {} has to be a path inside the output roots", path);
+                exit(1);
+            }
+            if !taken.insert(path.clone()) {
+                eprintln!("Error: Output path {} is already used by another page", path);
+                exit(1);
+            }
+        }
+    }
+
     fn load_dir(&mut self, path: PathBuf) {
-        match read_dir(&path) {
+        let root_dir = match read_dir(&path) {
             Ok(d) => d,
             Err(_) => {
                 eprintln!("Error: Given path is not a directory.");
                 exit(1);
             }
         };
-        let posts_path: PathBuf = [&path.to_str().unwrap(), "posts"].iter().collect();
-        let posts_dir = match read_dir(posts_path) {
-            Ok(pd) => pd,
+
+        for content_dir in self.config.content_dirs() {
+            let dir_path: PathBuf = [path.to_str().unwrap(), &content_dir.path].iter().collect();
+            if content_dir.kind == "page" && !dir_path.is_dir() {
+                continue;
+            }
+            if !dir_path.is_dir() {
+                eprintln!("Error: No {}/ directory.", content_dir.path);
+                exit(1);
+            }
+            let output_dir = match content_dir.kind.as_str() {
+                "post" => {
+                    self.load_posts(&dir_path, &content_dir.path);
+                    "posts"
+                },
+                "topic" => {
+                    self.load_topics(&dir_path);
+                    ""
+                },
+                "page" => {
+                    self.load_pages(&dir_path);
+                    ""
+                },
+                k => {
+                    eprintln!("Error: Unknown content kind \"{}\" for {}/, expected \"post\", \"topic\" or \"page\"", k, content_dir.path);
+                    exit(1);
+                }
+            };
+            if let Some(true) = self.config.attachments.enabled {
+                self.load_attachments(&dir_path, output_dir);
+            }
+        }
+        self.posts.sort_by_key(|p| std::cmp::Reverse(p.published));
+        self.notes.sort_by_key(|p| std::cmp::Reverse(p.published));
+        self.topics.sort_by(|a, b| a.title.partial_cmp(&b.title).unwrap());
+
+        // Any other gemtext next to posts/ and topics/ is a standalone page.
+        for entry in root_dir {
+            let entry = entry.unwrap();
+            let p = entry.path();
+            if !p.is_file() || p.extension() != Some(std::ffi::OsStr::new("gmi")) {
+                continue;
+            }
+            self.load_page(&p);
+        }
+        self.pages.sort_by(|a, b| a.filename.cmp(&b.filename));
+    }
+
+    // Pages in a page directory are published at the root of the site like
+    // the ones next to it, subfolders only organize the sources.
+    fn load_pages(&mut self, dir: &Path) {
+        for (p, _) in self.content_files(dir, &["gmi"]) {
+            self.load_page(&p);
+        }
+    }
+
+    fn load_page(&mut self, p: &Path) {
+        let stem = p.file_stem().unwrap_or_default().to_string_lossy().into_owned();
+        if RESERVED_PAGE_NAMES.contains(&stem.as_str()) || self.generated_page_names().contains(&stem) {
+            warning!("Skipping {}, {} is used by crosspub", &p.to_string_lossy(), stem);
+            return;
+        }
+
+        let parsing = self.timings.start();
+        let mut page = Page::from_source(p.to_path_buf(), self.html_options());
+        self.timings.page(&page.output_path, self.timings.phase("parse", parsing));
+        page.source_hash = lock::hash_file(p).unwrap_or_default();
+        self.pages.push(page);
+    }
+
+    // Write an output of the build to the store.
+    fn write_file(&self, path: &Path, contents: &str) {
+        let writing = self.timings.start();
+        if let Err(e) = self.store.write(path, contents.as_bytes()) {
+            eprintln!("Error: Could not write to {}: {}", &path.to_string_lossy(), e);
+            exit(1);
+        }
+        self.timings.phase("IO", writing);
+    }
+
+    fn content_files(&self, dir: &Path, extensions: &[&str]) -> Vec<(PathBuf, String)> {
+        content_files(dir, extensions, self.config.build.follow_symlinks.unwrap_or(true))
+    }
+
+    fn load_posts(&mut self, dir: &Path, dir_name: &str) {
+        for (p, relative) in self.content_files(dir, &["gmi"]) {
+            // Lock entries are keyed by the path relative to the site.
+            let name = format!("{}/{}", dir_name, relative);
+            let hash = match lock::hash_file(&p) {
+                Ok(h) => h,
+                Err(_) => {
+                    eprintln!("Error: Could not open file {}", &p.to_string_lossy());
+                    exit(1);
+                }
+            };
+            if let Some(locked) = &self.locked {
+                match locked.get(&name) {
+                    Some(h) if *h == hash => {},
+                    Some(_) => {
+                        eprintln!("Error: {} changed since it was locked, run crosspub lock to publish the change", name);
+                        exit(1);
+                    },
+                    None => {
+                        println!("Skipping {} since it isn't in crosspub.lock", name);
+                        continue;
+                    },
+                }
+            }
+
+            let parsing = self.timings.start();
+            let mut post = Post::from_source(p.clone(), self.html_options(), self.timezone, &self.date_prefix);
+            self.timings.page(&post.output_path, self.timings.phase("parse", parsing));
+            post.source_hash = hash.clone();
+            if post.draft && self.config.build.drafts != Some(true) {
+                continue;
+            }
+            self.post_hashes.insert(name, hash);
+            if post.kind == PostKind::Note {
+                self.notes.push(post);
+            } else {
+                self.posts.push(post);
+            }
+        }
+    }
+
+    // Other files in a content directory are published next to the pages made
+    // from it, so relative links from a post to its images keep working.
+    fn load_attachments(&mut self, dir: &Path, output_dir: &str) {
+        let extensions: Vec<String> = match &self.config.attachments.extensions {
+            Some(e) => e.iter().map(|e| e.trim_start_matches('.').to_lowercase()).collect(),
+            None => DEFAULT_ATTACHMENT_EXTENSIONS.iter().map(|e| e.to_string()).collect(),
+        };
+        let extensions: Vec<&str> = extensions.iter()
+            .map(|e| e.as_str())
+            .filter(|e| *e != "gmi")
+            .collect();
+
+        for (source, _) in self.content_files(dir, &extensions) {
+            let name = source.file_name().unwrap_or_default().to_string_lossy().into_owned();
+            let output: PathBuf = [output_dir, &name].iter().collect();
+            if self.attachments.iter().any(|(_, o)| *o == output) {
+                warning!("Skipping attachment {}, another file is already published as {}",
+                    &source.to_string_lossy(), &output.to_string_lossy());
+                continue;
+            }
+            self.attachments.push((source, output));
+        }
+    }
+
+    fn copy_attachments(&self) {
+        let mut roots = Vec::new();
+        if self.html_output {
+            roots.push(&self.config.site.html_root);
+        }
+        if self.gemini_output {
+            roots.push(&self.config.site.gemini_root);
+        }
+
+        for root in roots {
+            for (source, output) in &self.attachments {
+                let dest = Path::new(root).join(output);
+                println!("Copying attachment {}", &dest.to_string_lossy());
+                if self.store.copy_from(source, &dest).is_err() {
+                    eprintln!("Error: Could not copy {} to {}", &source.to_string_lossy(), &dest.to_string_lossy());
+                    exit(1);
+                }
+            }
+        }
+    }
+
+    fn load_topics(&mut self, dir: &Path) {
+        for (t, _) in self.content_files(dir, &["gmi"]) {
+            let parsing = self.timings.start();
+            let mut topic = Topic::from_source(t.clone(), self.html_options());
+            self.timings.page(&topic.output_path, self.timings.phase("parse", parsing));
+            topic.source_hash = lock::hash_file(&t).unwrap_or_default();
+            self.topics.push(topic);
+        }
+    }
+
+    // Run the conversion contract over everything loaded and stop the build
+    // if any source breaks it.
+    fn self_check(&self) {
+        let sources = self.posts.iter().chain(&self.notes).map(|p| (&p.filename, &p.tokens))
+            .chain(self.topics.iter().map(|t| (&t.filename, &t.tokens)))
+            .chain(self.pages.iter().map(|p| (&p.filename, &p.tokens)));
+        let mut failed = false;
+        for (filename, tokens) in sources {
+            if let Err(e) = selfcheck::check(tokens) {
+                eprintln!("Error: Self check failed for {}: {}", filename, e);
+                failed = true;
+            }
+        }
+        if failed {
+            exit(1);
+        }
+    }
+
+    // Links to asset:name are pointed at the file assets.toml maps the name
+    // to, in both outputs.
+    fn resolve_assets(&mut self) {
+        let manifest_path: PathBuf = [self.dir.to_str().unwrap(), "assets.toml"].iter().collect();
+        let contents = match fs::read_to_string(&manifest_path) {
+            Ok(c) => c,
+            Err(_) => return,
+        };
+        let manifest = match asset::parse(&contents) {
+            Ok(m) => m,
             Err(_) => {
-                eprintln!("Error: No posts/ directory.");
+                eprintln!("Error: assets.toml formatted incorrectly");
+                exit(1);
+            }
+        };
+        let urls = asset::urls(&manifest, &self.config.site);
+
+        let missing = |name: String, filename: &str| -> ! {
+            eprintln!("Error: No asset named \"{}\" in assets.toml, used by {}", name, filename);
+            exit(1);
+        };
+        for post in self.posts.iter_mut().chain(self.notes.iter_mut()) {
+            post.tokens = asset::resolve_tokens(&post.tokens, &urls)
+                .unwrap_or_else(|n| missing(n, &post.filename));
+            post.gemini_content = asset::resolve_gemtext(&post.gemini_content, &urls)
+                .unwrap_or_else(|n| missing(n, &post.filename));
+            post.render_html(generate_html_from_tokens);
+        }
+        for topic in self.topics.iter_mut() {
+            topic.tokens = asset::resolve_tokens(&topic.tokens, &urls)
+                .unwrap_or_else(|n| missing(n, &topic.filename));
+            topic.gemini_content = asset::resolve_gemtext(&topic.gemini_content, &urls)
+                .unwrap_or_else(|n| missing(n, &topic.filename));
+            topic.html_content = generate_html_from_tokens(&topic.tokens, &topic.html_options);
+        }
+        for page in self.pages.iter_mut() {
+            page.tokens = asset::resolve_tokens(&page.tokens, &urls)
+                .unwrap_or_else(|n| missing(n, &page.filename));
+            page.gemini_content = asset::resolve_gemtext(&page.gemini_content, &urls)
+                .unwrap_or_else(|n| missing(n, &page.filename));
+            page.html_content = generate_html_from_tokens(&page.tokens, &page.html_options);
+        }
+    }
+
+    fn includes_dir(&self) -> PathBuf {
+        let dir = self.config.build.includes_dir.as_deref().unwrap_or("includes");
+        [self.dir.to_str().unwrap(), dir].iter().collect()
+    }
+
+    // Every file of the includes directory a post or topic asks for.
+    fn includes(&self) -> Vec<String> {
+        let posts = self.posts.iter().chain(&self.notes).flat_map(|p| p.styles.iter().chain(&p.scripts));
+        let topics = self.topics.iter().flat_map(|t| t.styles.iter().chain(&t.scripts));
+        let mut includes: Vec<String> = posts.chain(topics).cloned().collect();
+        includes.sort();
+        includes.dedup();
+        includes
+    }
+
+    // Styles and scripts are given relative to the includes directory and
+    // have to exist there.
+    fn check_includes(&self) {
+        let dir = self.includes_dir();
+        for include in self.includes() {
+            let path = Path::new(&include);
+            let inside = path.components().all(|c| matches!(c, std::path::Component::Normal(_)));
+            if !inside {
+                eprintln!("Error: Include {} has to be a path inside the includes directory", include);
+                exit(1);
+            }
+            if !dir.join(path).is_file() {
+                eprintln!("Error: Could not find include {} in {}", include, &dir.to_string_lossy());
+                exit(1);
+            }
+        }
+    }
+
+    fn copy_includes(&self) {
+        let dir = self.includes_dir();
+        for include in self.includes() {
+            let dest: PathBuf = [&self.config.site.html_root, "includes", &include].iter().collect();
+            println!("Copying include {}", &dest.to_string_lossy());
+            if self.store.copy_from(&dir.join(&include), &dest).is_err() {
+                eprintln!("Error: Could not copy include {} to {}", include, &dest.to_string_lossy());
+                exit(1);
+            }
+        }
+    }
+
+    // Name links that have no name with the title of the page they point to,
+    // from the cache or fetched unless the build is offline.
+    fn name_bare_links(&mut self) {
+        let cache_path = match &self.config.link_titles.cache {
+            Some(c) => PathBuf::from(c),
+            None => match self.xdg_dirs.place_cache_file("link-titles.txt") {
+                Ok(p) => p,
+                Err(_) => {
+                    warning!("Could not create crosspub cache directory, skipping link titles");
+                    return;
+                }
+            },
+        };
+        let mut cache = linktitle::TitleCache::load(cache_path);
+        let offline = self.offline || self.config.link_titles.offline.unwrap_or(false);
+        let agent = if offline { None } else { Some(linktitle::agent()) };
+
+        let urls: Vec<String> = self.posts.iter().chain(&self.notes).map(|p| &p.tokens)
+            .chain(self.topics.iter().map(|t| &t.tokens))
+            .chain(self.pages.iter().map(|p| &p.tokens))
+            .flat_map(|tokens| linktitle::bare_links(tokens))
+            .collect();
+        let mut titles = BTreeMap::new();
+        for url in urls {
+            if titles.contains_key(&url) {
+                continue;
+            }
+            if let Some(title) = cache.title(&url, agent.as_ref()) {
+                titles.insert(url, title);
+            }
+        }
+        cache.save();
+        if titles.is_empty() {
+            return;
+        }
+
+        for post in self.posts.iter_mut().chain(self.notes.iter_mut()) {
+            post.tokens = linktitle::name_links(&post.tokens, &titles);
+            post.render_html(generate_html_from_tokens);
+        }
+        for topic in self.topics.iter_mut() {
+            topic.tokens = linktitle::name_links(&topic.tokens, &titles);
+            topic.html_content = generate_html_from_tokens(&topic.tokens, &topic.html_options);
+        }
+        for page in self.pages.iter_mut() {
+            page.tokens = linktitle::name_links(&page.tokens, &titles);
+            page.html_content = generate_html_from_tokens(&page.tokens, &page.html_options);
+        }
+    }
+
+    // Wikilinks and glossary terms link pages to each other, so they're added
+    // once everything is loaded.
+    fn link_pages(&mut self) {
+        let wikilinks = self.config.wikilinks.enabled.unwrap_or(true);
+        let glossary = match self.config.glossary.enabled {
+            Some(true) => Some(self.anchor_glossary()),
+            _ => None,
+        };
+        if !wikilinks && glossary.is_none() {
+            return;
+        }
+        let targets = wikilink::targets(self.topics.iter()
+            .map(|t| (t.title.as_str(), t.filename.as_str(), t.output_path.as_str())));
+        let url_prefix = page_path(&self.config.site, "");
+        // Links are markup, so they're added after escaping.
+        let link_html = |tokens: &[GemtextToken], options: &HtmlOptions, link_terms: bool, missing: &mut Vec<String>| {
+            let mut tokens = prepare_tokens(tokens, options);
+            if wikilinks {
+                tokens = wikilink::link_html(&tokens, &targets, &url_prefix, missing);
+            }
+            if let (Some((terms, glossary_url, _)), true) = (&glossary, link_terms) {
+                tokens = glossary::link_terms(&tokens, terms, glossary_url);
+            }
+            render_tokens(&tokens, options)
+        };
+
+        let mut missing: Vec<(String, String)> = Vec::new();
+        for post in self.posts.iter_mut().chain(self.notes.iter_mut()) {
+            let mut targets_missing = Vec::new();
+            post.render_html(|tokens, options| link_html(tokens, options, true, &mut targets_missing));
+            if wikilinks {
+                post.gemini_content = wikilink::link_gemini(&post.gemini_content, &targets, &url_prefix, &mut targets_missing);
+            }
+            missing.extend(targets_missing.into_iter().map(|t| (post.filename.clone(), t)));
+        }
+        for topic in self.topics.iter_mut() {
+            let mut targets_missing = Vec::new();
+            let is_glossary = glossary.as_ref().map(|(_, _, slug)| *slug == topic.filename).unwrap_or(false);
+            topic.html_content = link_html(&topic.tokens, &topic.html_options, !is_glossary, &mut targets_missing);
+            if wikilinks {
+                topic.gemini_content = wikilink::link_gemini(&topic.gemini_content, &targets, &url_prefix, &mut targets_missing);
+            }
+            missing.extend(targets_missing.into_iter().map(|t| (topic.filename.clone(), t)));
+        }
+
+        missing.sort();
+        missing.dedup();
+        let strict = self.config.wikilinks.strict.unwrap_or(false);
+        for (filename, target) in &missing {
+            if strict {
+                eprintln!("Error: No topic named \"{}\" for the wikilink in {}", target, filename);
+            } else {
+                warning!("No topic named \"{}\" for the wikilink in {}", target, filename);
+            }
+        }
+        if strict && !missing.is_empty() {
+            exit(1);
+        }
+    }
+
+    // Give posts and topics their URLs once the site is known.
+    fn set_urls(&mut self) {
+        let site = &self.config.site;
+        for post in self.posts.iter_mut().chain(self.notes.iter_mut()) {
+            post.set_urls(site);
+        }
+        for topic in self.topics.iter_mut() {
+            topic.set_urls(site);
+        }
+    }
+
+    fn set_summaries(&mut self) {
+        let summary_length = self.config.feeds.summary_length.unwrap_or(280);
+        for post in self.posts.iter_mut().chain(self.notes.iter_mut()) {
+            post.set_summary(summary_length);
+        }
+    }
+
+    fn set_authors(&mut self) {
+        for post in self.posts.iter_mut().chain(self.notes.iter_mut()) {
+            post.author = authors::link(&post.author.key, &self.config);
+        }
+    }
+
+    // Find the links to each topic, from links and wikilinks, once every page
+    // is loaded.
+    fn collect_backlinks(&mut self) {
+        let targets = wikilink::targets(self.topics.iter()
+            .map(|t| (t.title.as_str(), t.filename.as_str(), t.output_path.as_str())));
+        let wikilinks = self.config.wikilinks.enabled.unwrap_or(true);
+        let topic_paths: HashSet<&str> = self.topics.iter().map(|t| t.output_path.as_str()).collect();
+        // Notes have no title, name them by their date instead.
+        let sources = self.posts.iter().chain(&self.notes)
+            .map(|p| {
+                let title = if p.title.is_empty() { p.date.format("%B %e, %Y").to_string() } else { p.title.clone() };
+                (title, &p.output_path, &p.tokens)
+            })
+            .chain(self.topics.iter().map(|t| (t.title.clone(), &t.output_path, &t.tokens)));
+
+        let mut backlinks: BTreeMap<String, Vec<Backlink>> = BTreeMap::new();
+        for (title, output_path, tokens) in sources {
+            let mut linked = backlinks::link_targets(tokens, output_path, &self.config.site);
+            if wikilinks {
+                linked.extend(wikilink::linked(tokens, &targets));
+            }
+            let linked: HashSet<String> = linked.into_iter()
+                .filter(|l| l != output_path && topic_paths.contains(l.as_str()))
+                .collect();
+            for target in linked {
+                backlinks.entry(target).or_default().push(Backlink {
+                    title: title.clone(),
+                    output_path: output_path.clone(),
+                });
+            }
+        }
+        self.backlinks = backlinks;
+    }
+
+    // Give the terms of the glossary topic anchors, returns the terms, the
+    // URL of the glossary and its slug.
+    fn anchor_glossary(&mut self) -> (Vec<glossary::Term>, String, String) {
+        let slug = self.config.glossary.topic.clone().unwrap_or_else(|| "glossary".to_string());
+        let glossary_topic = match self.topics.iter_mut().find(|t| t.filename == slug) {
+            Some(t) => t,
+            None => {
+                eprintln!("Error: Could not find glossary topic \"{}\".", slug);
                 exit(1);
             }
         };
-        let topics_path: PathBuf = [&path.to_str().unwrap(), "topics"].iter().collect();
-        let topics_dir = match read_dir(topics_path) {
-            Ok(td) => td,
+        glossary_topic.tokens = glossary::anchor_terms(&glossary_topic.tokens);
+        glossary_topic.html_content = generate_html_from_tokens(&glossary_topic.tokens, &glossary_topic.html_options);
+        if !glossary_topic.toc.html.is_empty() {
+            glossary_topic.toc = toc::render(&toc::entries(&glossary_topic.tokens));
+        }
+
+        let terms = glossary::terms(&glossary_topic.tokens);
+        let glossary_url = page_path(&self.config.site, &format!("{}.html", glossary_topic.output_path));
+        (terms, glossary_url, slug)
+    }
+
+    pub fn export(&self, format: &ExportFormat) -> String {
+        let posts: Vec<Post> = self.posts.iter().chain(&self.notes).cloned().collect();
+        let export = Export::new(&self.config.site, &posts, &self.topics);
+        match format {
+            ExportFormat::Json => export.to_json(),
+            ExportFormat::Nostr => self.export_nostr(),
+        }
+    }
+
+    // One NIP-23 event per line for each post that hasn't been exported
+    // before. Exported posts are remembered in a cache file.
+    fn export_nostr(&self) -> String {
+        let cache_path = match &self.config.nostr.cache {
+            Some(c) => PathBuf::from(c),
+            None => match self.xdg_dirs.place_cache_file("nostr-exported.txt") {
+                Ok(p) => p,
+                Err(_) => {
+                    eprintln!("Error: Could not create crosspub cache directory");
+                    exit(1);
+                }
+            },
+        };
+        let exported: HashSet<String> = match fs::read_to_string(&cache_path) {
+            Ok(c) => c.lines().map(String::from).collect(),
+            Err(_) => HashSet::new(),
+        };
+
+        let created_at = self.timezone.now(self.source_date_epoch).timestamp();
+        let mut events: Vec<String> = Vec::new();
+        let mut new_posts: Vec<&str> = Vec::new();
+        for post in self.posts.iter().rev().filter(|p| !exported.contains(&p.filename)) {
+            let mut tags = vec![
+                vec!["d".to_string(), post.filename.clone()],
+                vec!["title".to_string(), post.title.clone()],
+                vec!["published_at".to_string(), post.published.timestamp().to_string()],
+            ];
+            if !post.summary.is_empty() {
+                tags.push(vec!["summary".to_string(), post.summary.clone()]);
+            }
+            tags.push(vec![
+                "proxy".to_string(),
+                post.url.clone(),
+                "web".to_string(),
+            ]);
+
+            let mut event = Event::long_form(created_at, tags, generate_markdown_from_tokens(&post.tokens));
+            if let Some(key) = &self.config.nostr.secret_key {
+                if event.sign(key).is_err() {
+                    eprintln!("Error: nostr secret_key in config.toml isn't a valid hex secret key.");
+                    exit(1);
+                }
+            }
+            events.push(event.to_json());
+            new_posts.push(&post.filename);
+        }
+
+        if !new_posts.is_empty() {
+            let cache = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&cache_path);
+            let written = cache.and_then(|mut c| {
+                new_posts.iter().try_for_each(|f| writeln!(c, "{}", f))
+            });
+            if written.is_err() {
+                warning!("Could not update nostr export cache {}",
+                    &cache_path.to_string_lossy());
+            }
+        }
+        events.join("\n")
+    }
+
+    pub fn write(&self) {
+        let before = if self.notify_enabled() && self.dry_run.is_none() {
+            Some(notify::snapshot(&self.output_roots()))
+        } else {
+            None
+        };
+        let building = self.timings.start();
+        if self.html_output {
+            let rendering = self.timings.start();
+            self.write_html_posts();
+            self.write_html_topics();
+            self.generate_index_html();
+            self.write_html_pages();
+            self.copy_css();
+            self.copy_includes();
+            self.timings.phase("render HTML", rendering);
+        }
+        if self.gemini_output {
+            let rendering = self.timings.start();
+            self.write_gemini_posts();
+            self.write_gemini_topics();
+            self.generate_index_gmi();
+            self.write_gemini_pages();
+            if let Some(true) = self.config.meta.enabled {
+                self.write_gemini_meta();
+            }
+            self.timings.phase("render Gemini", rendering);
+        }
+
+        let main_feed = self.posts_of_kinds(&self.main_feed_kinds);
+        if !main_feed.is_empty() {
+            self.generate_atom_feeds(&main_feed, "index.xml");
+        }
+
+        if !self.notes.is_empty() {
+            if self.html_output {
+                self.generate_note_listing_html();
+            }
+            if self.gemini_output {
+                self.generate_note_listing_gmi();
+            }
+            self.generate_atom_feeds(&self.notes, "notes.xml");
+        }
+
+        if let Some(true) = self.config.feeds.per_lang {
+            for (lang, posts) in posts_by_lang(&main_feed) {
+                self.generate_atom_feeds(&posts, &format!("index-{}.xml", lang));
+            }
+        }
+
+        if self.per_kind_feeds {
+            for kind in PostKind::ALL.iter().filter(|k| **k != PostKind::Note) {
+                let posts = self.posts_of_kinds(&[*kind]);
+                if posts.is_empty() {
+                    continue;
+                }
+                let filename = format!("{}s.xml", kind.name());
+                self.generate_atom_feeds(&posts, &filename);
+            }
+        }
+
+        if !self.standalone.is_empty() && self.html_output {
+            self.generate_about_html();
+        }
+        if !self.standalone.is_empty() && self.gemini_output {
+            self.generate_about_gmi();
+        }
+
+        if self.post_listing && self.html_output {
+            self.generate_post_listing_html();
+        }
+        if self.post_listing && self.gemini_output {
+            self.generate_post_listing_gmi();
+        }
+
+        if let Some(true) = self.config.stats.enabled {
+            self.generate_stats();
+        }
+
+        if let Some(true) = self.config.link_log.enabled {
+            self.generate_link_log();
+        }
+
+        if let Some(true) = self.config.tags.enabled {
+            self.generate_tag_pages();
+        }
+
+        if let Some(true) = self.config.author_pages.enabled {
+            self.generate_author_pages();
+        }
+
+        self.generate_bookmarks();
+
+        if let Some(period) = self.digest_period {
+            self.generate_digest(period);
+        }
+
+        if self.html_fragments && self.html_output {
+            self.write_html_fragments();
+        }
+
+        if self.html_output {
+            self.write_redirects();
+        }
+
+        if !self.attachments.is_empty() {
+            self.copy_attachments();
+        }
+
+        if self.dry_run.is_some() {
+            self.report_dry_run();
+            self.timings.phase("other", building);
+            return;
+        }
+        let finishing = self.timings.start();
+        if let Err(e) = self.store.finish() {
+            eprintln!("Error: Could not finish publishing: {}", e);
+            exit(1);
+        }
+        self.timings.phase("IO", finishing);
+
+        if let (Some(true), true) = (self.config.search.enabled, self.gemini_output) {
+            self.write_search();
+        }
+
+        if let Some(true) = self.config.sqlite.enabled {
+            self.write_sqlite_index();
+        }
+
+        if self.archive_links {
+            self.archive_external_links();
+        }
+
+        if self.html_output && self.config.check.images.unwrap_or(true) {
+            self.check_images();
+        }
+
+        if let Some(changes) = &self.changes {
+            self.record_changes(changes);
+        }
+
+        if let Some(before) = before {
+            self.send_report(&before);
+        }
+        self.timings.phase("other", building);
+    }
+
+    // Print where the build spent its time, when asked to with --timings.
+    pub fn print_timings(&self) {
+        if let Some(slowest) = self.slowest_pages {
+            print!("{}", self.timings.report(self.started.elapsed(), slowest));
+        }
+    }
+
+    // Render only the pages made from the given templates, named by their
+    // path under templates/ like html/post.html, so crosspub serve can pick up
+    // a template change without a full build. Templates of the other output
+    // are skipped. Feed templates and the like, dry runs and S3 deploys, which
+    // need to see every file, build everything.
+    pub fn write_templates(&self, templates: &[String]) {
+        if self.dry_run.is_some() || self.config.s3.enabled == Some(true) {
+            return self.write();
+        }
+        let mut writers: Vec<TemplateWriter> = Vec::new();
+        for template in templates {
+            let enabled = match template.split('/').next() {
+                Some("html") => self.html_output,
+                Some("gemini") => self.gemini_output,
+                _ => false,
+            };
+            if !enabled {
+                continue;
+            }
+            match template_writer(template) {
+                Some(w) if !writers.iter().any(|(name, _)| *name == w.0) => writers.push(w),
+                Some(_) => {},
+                None => return self.write(),
+            }
+        }
+        for (_, writer) in writers {
+            writer(self);
+        }
+    }
+
+    // What a dry run wrote, by path, with removed files as None. Empty for
+    // other builds, which write to their store as they go.
+    pub fn outputs(&self) -> BTreeMap<PathBuf, Option<Vec<u8>>> {
+        self.dry_run.as_ref().map(|m| m.files()).unwrap_or_default()
+    }
+
+    // List the files a dry run would have written that differ from the ones
+    // on disk. The search and SQLite indexes and everything after them in a
+    // build aren't part of a dry run.
+    fn report_dry_run(&self) {
+        let mut changed = 0;
+        for (path, contents) in self.outputs() {
+            let on_disk = fs::read(&path).ok();
+            let action = match (&contents, &on_disk) {
+                (Some(_), None) => "create",
+                (Some(c), Some(d)) if c != d => "change",
+                (None, Some(_)) => "remove",
+                _ => continue,
+            };
+            println!("Would {} {}", action, &path.to_string_lossy());
+            changed += 1;
+        }
+        println!("Dry run, {} file(s) would change", changed);
+    }
+
+    fn changes_cache_path(&self) -> Option<PathBuf> {
+        match &self.config.changes.cache {
+            Some(c) => Some(PathBuf::from(c)),
+            None => self.xdg_dirs.place_cache_file("published.txt").ok(),
+        }
+    }
+
+    // Compare the posts to the ones the last build published. Without a record
+    // of the last build every post counts as published already, so turning
+    // this on doesn't announce the whole archive.
+    fn find_changes(&self) -> ChangeSet {
+        let previous = match self.changes_cache_path().map(|p| changes::read_cache(&p)) {
+            Some(Ok(p)) => p,
+            _ => {
+                println!("No record of the last build, taking the current posts as published");
+                return ChangeSet::default();
+            },
+        };
+        ChangeSet::new(&previous, self.posts.iter().chain(self.notes.iter()))
+    }
+
+    // Print what changed, write the manifest and remember the published posts
+    // for the next build.
+    fn record_changes(&self, changes: &ChangeSet) {
+        print!("{}", changes.summary());
+        if let Some(manifest) = &self.config.changes.manifest {
+            let manifest_path = self.dir.join(manifest);
+            println!("Writing changes to {}", &manifest_path.to_string_lossy());
+            let json = serde_json::to_string_pretty(changes).unwrap_or_default();
+            if fs::write(&manifest_path, json + "\n").is_err() {
+                warning!("Could not write changes manifest {}", &manifest_path.to_string_lossy());
+            }
+        }
+        let written = match self.changes_cache_path() {
+            Some(p) => changes::write_cache(&p, self.posts.iter().chain(self.notes.iter())).is_ok(),
+            None => false,
+        };
+        if !written {
+            warning!("Could not record the published posts, the next build will report changes again");
+        }
+    }
+
+    fn notify_enabled(&self) -> bool {
+        self.config.notify.webhook.is_some() || self.config.notify.email.is_some()
+    }
+
+    fn output_roots(&self) -> Vec<&Path> {
+        let mut roots = Vec::new();
+        if self.html_output {
+            roots.push(Path::new(&self.config.site.html_root));
+        }
+        if self.gemini_output {
+            roots.push(Path::new(&self.config.site.gemini_root));
+        }
+        roots
+    }
+
+    // Tell the webhook or email address in the config what the build did.
+    // A failed notification doesn't fail the build.
+    fn send_report(&self, before: &notify::Snapshot) {
+        let after = notify::snapshot(&self.output_roots());
+        let report = notify::Report::new(&self.config.site.name, self.started.elapsed(), before, &after, self.changes.clone());
+        if let Some(url) = &self.config.notify.webhook {
+            if self.offline {
+                println!("Offline, not sending the build report to {}", url);
+            } else {
+                println!("Sending build report to {}", url);
+                if let Err(e) = notify::post_webhook(url, &report) {
+                    warning!("Could not send build report to {}: {}", url, e);
+                }
+            }
+        }
+        if let Some(to) = &self.config.notify.email {
+            let sendmail = self.config.notify.sendmail.as_deref().unwrap_or("sendmail");
+            println!("Emailing build report to {}", to);
+            if let Err(e) = notify::send_email(sendmail, to, &report) {
+                warning!("Could not email build report to {}: {}", to, e);
+            }
+        }
+    }
+
+    // Report images of the HTML output that are missing, too big or without
+    // dimensions.
+    fn check_images(&self) {
+        let budget = self.config.check.image_budget.unwrap_or(DEFAULT_IMAGE_BUDGET);
+        let url_prefix = page_path(&self.config.site, "");
+        let findings = check::images(Path::new(&self.config.site.html_root), &url_prefix, budget);
+        check::report(&findings);
+    }
+
+    // A .meta sidecar next to the Gemini posts with the language, MIME type
+    // and cache hints from their frontmatter.
+    fn write_gemini_meta(&self) {
+        let default_lang = self.config.meta.lang.as_deref();
+        let entries: Vec<(String, String)> = self.posts.iter()
+            .chain(&self.notes)
+            .filter_map(|p| {
+                // The sidecar only covers posts/, posts written elsewhere are
+                // left out.
+                let name = p.output_path.strip_prefix("posts/").filter(|n| !n.contains('/'))?;
+                p.meta.value(default_lang).map(|v| (format!("{}.gmi", name), v))
+            })
+            .collect();
+
+        let meta_path: PathBuf = [&self.config.site.gemini_root, "posts", ".meta"].iter().collect();
+        if entries.is_empty() {
+            // Don't leave stale metadata behind from an earlier build.
+            let generated = self.store.read_to_string(&meta_path)
+                .map(|c| c.starts_with(meta::SIDECAR_HEADER))
+                .unwrap_or(false);
+            if generated {
+                let _ = self.store.remove(&meta_path);
+            }
+            return;
+        }
+        println!("Writing Gemini metadata to {}", &meta_path.to_str().unwrap());
+        self.write_file(&meta_path, &meta::sidecar(&entries));
+    }
+
+    // Old URLs listed in redirects.toml and posts moved by a new date prefix
+    // get a redirect page pointing to their new location, plus rewrite maps
+    // for nginx and Caddy next to redirects.toml.
+    fn write_redirects(&self) {
+        let redirects_path: PathBuf = [self.dir.to_str().unwrap(), "redirects.toml"].iter().collect();
+        let map = match fs::read_to_string(&redirects_path).map(|c| redirect::parse(&c)) {
+            Ok(Ok(m)) => m,
+            Ok(Err(_)) => {
+                eprintln!("Error: redirects.toml formatted incorrectly");
+                exit(1);
+            },
+            Err(_) => BTreeMap::new(),
+        };
+
+        let username = &self.config.site.username;
+        let mut redirects = self.date_prefix_redirects();
+        for (url, slug) in &map {
+            let to = match self.slug_path(slug) {
+                Some(t) => t,
+                None => {
+                    eprintln!("Error: No post, topic or page with slug \"{}\" in redirects.toml", slug);
+                    exit(1);
+                }
+            };
+            redirects.push(Redirect {
+                from: redirect::old_path(url, username),
+                to,
+            });
+        }
+        if redirects.is_empty() {
+            return;
+        }
+
+        for r in &redirects {
+            let redirect_path: PathBuf = [
+                &self.config.site.html_root,
+                &redirect::page_file(&r.from),
+            ].iter().collect();
+            println!("Writing redirect {}", &redirect_path.to_string_lossy());
+            self.write_file(&redirect_path, &redirect::redirect_page(&page_path(&self.config.site, &r.to)));
+        }
+
+        let nginx_path: PathBuf = [self.dir.to_str().unwrap(), "redirects.nginx"].iter().collect();
+        self.write_file(&nginx_path, &redirect::nginx_map(&redirects, &self.config.site));
+        let caddy_path: PathBuf = [self.dir.to_str().unwrap(), "redirects.caddy"].iter().collect();
+        self.write_file(&caddy_path, &redirect::caddy_map(&redirects, &self.config.site));
+    }
+
+    // Posts at the default path that had another filename under the old date
+    // prefix.
+    fn date_prefix_redirects(&self) -> Vec<Redirect> {
+        let old_prefix = match &self.config.build.old_post_date_prefix {
+            Some(p) => p,
+            None => return Vec::new(),
+        };
+        self.posts.iter()
+            .chain(&self.notes)
+            .filter(|p| !p.slug.is_empty() && p.output_path == format!("posts/{}", p.filename))
+            .map(|p| (post::post_filename(&p.date, &p.slug, old_prefix), p))
+            .filter(|(old, p)| *old != p.filename)
+            .map(|(old, p)| Redirect {
+                from: format!("posts/{}.html", old),
+                to: format!("{}.html", p.output_path),
+            })
+            .collect()
+    }
+
+    fn lock_path(&self) -> PathBuf {
+        [self.dir.to_str().unwrap(), "crosspub.lock"].iter().collect()
+    }
+
+    // Record every post of this build so later --locked builds publish
+    // exactly these.
+    pub fn write_lock(&self) {
+        let lock_path = self.lock_path();
+        if lock::write(&lock_path, &self.post_hashes).is_err() {
+            eprintln!("Error: Could not write to {}", &lock_path.to_string_lossy());
+            exit(1);
+        }
+        println!("Locked {} posts in {}", self.post_hashes.len(), &lock_path.to_string_lossy());
+    }
+
+    // Where the post, topic or page with the given slug is written, relative
+    // to the HTML root.
+    fn slug_path(&self, slug: &str) -> Option<String> {
+        let post = self.posts.iter()
+            .chain(&self.notes)
+            .find(|p| p.filename == slug || p.slug == slug);
+        if let Some(p) = post {
+            return Some(format!("{}.html", p.output_path));
+        }
+        let topic = self.topics.iter().map(|t| (&t.filename, &t.output_path))
+            .chain(self.pages.iter().map(|p| (&p.filename, &p.output_path)))
+            .find(|(f, _)| f.as_str() == slug);
+        topic.map(|(_, path)| format!("{}.html", path))
+    }
+
+    fn write_search(&self) {
+        let index_path = match &self.config.search.index {
+            Some(p) => PathBuf::from(p),
+            None => match self.xdg_dirs.place_data_file("search-index.json") {
+                Ok(p) => p,
+                Err(_) => {
+                    eprintln!("Error: Could not create crosspub data directory");
+                    exit(1);
+                }
+            },
+        };
+        let site = &self.config.site;
+        let mut entries: Vec<SearchEntry> = self.posts.iter()
+            .chain(&self.notes)
+            .map(|p| SearchEntry {
+                path: page_path(site, &format!("{}.gmi", p.output_path)),
+                title: if p.title.is_empty() { p.date.format("%Y-%m-%d").to_string() } else { p.title.clone() },
+                text: p.gemini_content.clone(),
+            })
+            .collect();
+        entries.extend(self.topics.iter().map(|t| SearchEntry {
+            path: page_path(site, &format!("{}.gmi", t.output_path)),
+            title: t.title.clone(),
+            text: t.gemini_content.clone(),
+        }));
+
+        println!("Writing search index to {}", &index_path.to_string_lossy());
+        if search::write_index(&index_path, &entries).is_err() {
+            eprintln!("Error: Could not write search index {}", &index_path.to_string_lossy());
+            exit(1);
+        }
+
+        let cgi_path: PathBuf = [
+            &self.config.site.gemini_root,
+            self.config.search.cgi_path.as_deref().unwrap_or("cgi-bin/search"),
+        ].iter().collect();
+        if let Some(parent) = cgi_path.parent() {
+            if !parent.exists() && fs::create_dir_all(parent).is_err() {
+                eprintln!("Error: Could not create directory at {}", &parent.to_string_lossy());
+                exit(1);
+            }
+        }
+        let crosspub = match std::env::current_exe() {
+            Ok(e) => e,
             Err(_) => {
-                eprintln!("Error: No topics/ directory.");
+                eprintln!("Error: Could not find the crosspub executable for the search script");
+                exit(1);
+            }
+        };
+
+        println!("Writing search script to {}", &cgi_path.to_string_lossy());
+        if search::write_cgi_script(&cgi_path, &crosspub, &index_path).is_err() {
+            eprintln!("Error: Could not write search script {}", &cgi_path.to_string_lossy());
+            exit(1);
+        }
+    }
+
+    fn write_sqlite_index(&self) {
+        let db_path = match &self.config.sqlite.path {
+            Some(p) => PathBuf::from(p),
+            None => self.dir.join("crosspub.db"),
+        };
+        let posts: Vec<Post> = self.posts.iter().chain(&self.notes).cloned().collect();
+
+        println!("Writing SQLite index to {}", &db_path.to_string_lossy());
+        if let Err(e) = sqlite::write_index(&db_path, &posts, &self.topics) {
+            eprintln!("Error: Could not write SQLite index {}: {}", &db_path.to_string_lossy(), e);
+            exit(1);
+        }
+    }
+
+    // Write just the rendered body of each post, without any template, for
+    // embedding in other pages.
+    fn write_html_fragments(&self) {
+        let fragments_dir: PathBuf = [
+            &self.config.site.html_root,
+            "fragments",
+        ].iter().collect();
+
+        for post in self.posts.iter().chain(&self.notes) {
+            let mut fragment_path = fragments_dir.clone();
+            fragment_path.push(&post.filename);
+            fragment_path.set_extension("html");
+
+            println!("Writing fragment {}", &fragment_path.to_string_lossy());
+            self.write_file(&fragment_path, &format!("{}{}", post.structured_html, post.html_content));
+        }
+    }
+
+    // Write a feed to each root that has feeds turned on.
+    fn generate_atom_feeds(&self, posts: &[Post], filename: &str) {
+        let generating = self.timings.start();
+        if self.html_output && self.config.feeds.html.unwrap_or(true) {
+            self.generate_html_atom_feed(posts, filename);
+        }
+        if self.gemini_output && self.config.feeds.gemini.unwrap_or(true) {
+            self.generate_gemini_atom_feed(posts, filename);
+        }
+        self.timings.phase("feeds", generating);
+    }
+
+    // The site wide defaults for rendering HTML.
+    fn html_options(&self) -> HtmlOptions {
+        html_options(&self.config)
+    }
+
+    // A template or other data file, from --data-dir if it has one and the
+    // XDG data directories otherwise.
+    fn find_data_file<P: AsRef<Path>>(&self, path: P) -> Option<PathBuf> {
+        self.data_dir.as_ref()
+            .map(|d| d.join(&path))
+            .filter(|p| p.exists())
+            .or_else(|| self.xdg_dirs.find_data_file(path))
+    }
+
+    // Standalone sources are found like about.gmi unless given as an absolute
+    // path.
+    fn data_source(&self, source: &str) -> PathBuf {
+        let path = PathBuf::from(source);
+        if path.is_absolute() {
+            return path;
+        }
+        match self.find_data_file(source) {
+            Some(p) => p,
+            None => {
+                eprintln!("Error: Could not find {} in ~/.local/share/crosspub", source);
                 exit(1);
             }
-        };
-        
-        for entry in posts_dir {
-            let entry = entry.unwrap();
-            let p = entry.path();
-            if p.extension() != Some(std::ffi::OsStr::new("gmi")) {
-                continue;
-            }
-
-            let post = Post::from_source(entry.path());
-            self.posts.push(post);
         }
-        self.posts.sort_by(|a, b| b.date.partial_cmp(&a.date).unwrap());
+    }
 
-        for entry in topics_dir {
-            let entry = entry.unwrap();
-            let t = entry.path();
-            if t.extension() != Some(std::ffi::OsStr::new("gmi")) {
-                continue;
-            }
+    // The standalone pages, for navigation menus.
+    fn page_links(&self) -> Vec<PageLink> {
+        self.pages.iter()
+            .map(|p| PageLink {
+                title: p.title.clone(),
+                path: page_path(&self.config.site, &format!("{}.html", p.output_path)),
+                gemini_path: page_path(&self.config.site, &format!("{}.gmi", p.output_path)),
+            })
+            .collect()
+    }
 
-            let topic = Topic::from_source(entry.path());
-            self.topics.push(topic);
-        }
-        self.topics.sort_by(|a, b| a.title.partial_cmp(&b.title).unwrap());
+    // The newest posts for templates other than the index.
+    fn recent_posts(&self) -> Vec<Post> {
+        let n = self.config.homepage.recent_posts.unwrap_or(5);
+        self.posts.iter().take(n).cloned().collect()
     }
 
-    pub fn write(&self) {
-        self.write_html_posts();
-        self.write_gemini_posts();
-        self.write_html_topics();
-        self.write_gemini_topics();
-        self.generate_index_html();
-        self.generate_index_gmi();
-        self.copy_css();
-        self.generate_html_atom_feed();
-        self.generate_gemini_atom_feed();
-
-        if self.has_about {
-            self.generate_about_html();
-            self.generate_about_gmi();
-        }
+    // Posts with a featured_weight, heaviest first and newest first among
+    // equals, as many as the config asks for.
+    fn pick_highlights(&self) -> Vec<Post> {
+        let n = self.config.homepage.highlights.unwrap_or(0);
+        let mut featured: Vec<Post> = self.posts.iter()
+            .filter(|p| p.featured_weight > 0)
+            .cloned()
+            .collect();
+        // The sort is stable and posts are already newest first.
+        featured.sort_by_key(|p| std::cmp::Reverse(p.featured_weight));
+        featured.truncate(n);
+        featured
+    }
 
-        if self.post_listing {
-            self.generate_post_listing_html();
-            self.generate_post_listing_gmi();
-        }
+    // All posts and notes of the given kinds, newest first.
+    fn posts_of_kinds(&self, kinds: &[PostKind]) -> Vec<Post> {
+        let mut posts: Vec<Post> = self.posts.iter()
+            .chain(&self.notes)
+            .filter(|p| kinds.contains(&p.kind))
+            .cloned()
+            .collect();
+        posts.sort_by_key(|p| std::cmp::Reverse(p.published));
+        posts
+    }
+
+    fn archive_external_links(&self) {
+        let cache_path = match &self.config.archive.cache {
+            Some(c) => PathBuf::from(c),
+            None => match self.xdg_dirs.place_cache_file("archived.txt") {
+                Ok(p) => p,
+                Err(_) => {
+                    warning!("Could not create crosspub cache directory, skipping link archival");
+                    return;
+                }
+            },
+        };
+        let rate_limit = self.config.archive.rate_limit.unwrap_or(10);
+
+        let posts: Vec<Post> = self.posts.iter().chain(&self.notes).cloned().collect();
+        let links = archive::external_links(&posts);
+        archive::submit_links(&links, &cache_path, rate_limit);
     }
 
     fn generate_index_html(&self) {
         // Open index template
-        let template_file;
-        let index_template_path = self.xdg_dirs.find_data_file("templates/html/index.html");
+        let index_template_path = self.find_data_file("templates/html/index.html");
         let index_template_path = match index_template_path {
             Some(p) => p,
             _ => {
@@ -180,7 +1730,7 @@ impl CrossPub {
             }
         };
 
-        template_file = OpenOptions::new()
+        let template_file = OpenOptions::new()
             .read(true)
             .open(index_template_path);
         let mut template_file = match template_file {
@@ -210,12 +1760,20 @@ impl CrossPub {
         }
 
         let context = IndexContext {
+            output_target: "html".to_string(),
+            build: self.build_info.clone(),
             site: self.config.site.clone(),
             latest_post: self.posts[0].clone(),
+            highlights: self.highlights.clone(),
+            has_highlights: !self.highlights.is_empty(),
             posts: self.posts.clone(),
+            notes: self.notes.clone(),
+            has_notes: !self.notes.is_empty(),
             topics: self.topics.clone(),
             has_topics: !self.topics.is_empty(),
             has_about: self.has_about,
+            pages: self.page_links(),
+            listing: ListingPage::default(),
         };
 
         println!("Writing index.html");
@@ -225,182 +1783,677 @@ impl CrossPub {
             "index.html",
         ].iter().collect();
 
-        let output = OpenOptions::new()
-            .write(true)
-            .create(true)
-            .truncate(true)
-            .open(&index_path);
-        let mut output = match output {
-            Ok(o) => o,
-            Err(_) => {
-                eprintln!("Error: Could not open {} for writing", &index_path.to_string_lossy());
-                exit(1);
-            }
-        };
-
         let rendered = tt.render("html", &context).unwrap();
-        match output.write_all(rendered.as_bytes()) {
-            Ok(_) => {}
+        self.write_file(&index_path, &rendered);
+    }
+
+    fn generate_post_listing_html(&self) {
+        let template_buffer = self.read_template("templates/html/postlist.html", "HTML postlist");
+        let mut tt = TinyTemplate::new();
+        tt.set_default_formatter(&tinytemplate::format_unescaped);
+        match tt.add_template("html", &template_buffer) {
+            Ok(_) => {},
             Err(_) => {
-                eprintln!("Error: Could not write to {}", &index_path.to_string_lossy());
+                eprintln!("Error Could not parse HTML postlist template file");
                 exit(1);
             }
         }
+
+        let has_topics = !self.topics.is_empty();
+
+        for (output_path, posts, listing) in self.post_listing_pages("html") {
+            let context = IndexContext {
+                output_target: "html".to_string(),
+                build: self.build_info.clone(),
+                site: self.config.site.clone(),
+                latest_post: self.posts[0].clone(),
+                highlights: self.highlights.clone(),
+                has_highlights: !self.highlights.is_empty(),
+                posts,
+                notes: self.notes.clone(),
+                has_notes: !self.notes.is_empty(),
+                topics: self.topics.clone(),
+                has_topics,
+                has_about: self.has_about,
+                pages: self.page_links(),
+                listing,
+            };
+
+            let postlist_path = output_file(&self.config.site.html_root, &output_path, "html");
+            println!("Writing postlist {}", &postlist_path.to_string_lossy());
+
+            let rendered = tt.render("html", &context).unwrap();
+            self.write_file(&postlist_path, &rendered);
+        }
     }
 
-    fn generate_post_listing_html(&self) {
-        // Open post listing template
-        let template_file;
-        let postlist_template_path: PathBuf = [
-            self.xdg_dirs.get_data_home(),
-            PathBuf::from("templates/html/postlist.html"),
-        ].iter().collect();
+    // The post listing split into pages of post_list_per_page posts, each with
+    // where it's written: posts/posts, then posts/page/2 and so on. Without a
+    // page size the listing is a single page.
+    fn post_listing_pages(&self, extension: &str) -> Vec<(String, Vec<Post>, ListingPage)> {
+        let per_page = match self.config.homepage.post_list_per_page {
+            Some(n) if n > 0 => n,
+            _ => return vec![("posts/posts".to_string(), self.posts.clone(), ListingPage::default())],
+        };
+        let chunks: Vec<&[Post]> = self.posts.chunks(per_page).collect();
+        let output_paths = self.post_listing_output_paths();
+        let path = |i: usize| page_path(&self.config.site, &format!("{}.{}", output_paths[i], extension));
+        chunks.iter().enumerate()
+            .map(|(i, chunk)| {
+                let listing = ListingPage {
+                    number: i + 1,
+                    count: chunks.len(),
+                    prev: if i > 0 { path(i - 1) } else { String::new() },
+                    next: if i + 1 < chunks.len() { path(i + 1) } else { String::new() },
+                };
+                (output_paths[i].clone(), chunk.to_vec(), listing)
+            })
+            .collect()
+    }
 
-        template_file = OpenOptions::new()
-            .read(true)
-            .open(postlist_template_path);
-        let mut template_file = match template_file {
-            Ok(t) => t,
-            Err(_) => {
-                eprintln!("Error: Could not open HTML postlist template");
-                exit(1);
-            }
+    // Where the pages of the post listing are written, without an extension.
+    fn post_listing_output_paths(&self) -> Vec<String> {
+        let pages = match self.config.homepage.post_list_per_page {
+            Some(n) if n > 0 => self.posts.len().div_ceil(n).max(1),
+            _ => 1,
         };
-        // Read template to String and load into parser.
-        let mut template_buffer = String::new();
-        match template_file.read_to_string(&mut template_buffer) {
-            Ok(_) => {},
-            Err(_) => {
-                eprintln!("Error: Could not read from HTML template");
-                exit(1)
-            }
-        }
+        (1..=pages)
+            .map(|n| if n == 1 { "posts/posts".to_string() } else { format!("posts/page/{}", n) })
+            .collect()
+    }
+
+    fn generate_post_listing_gmi(&self) {
+        let template_buffer = self.read_template("templates/gemini/postlist.gmi", "Gemini postlist");
         let mut tt = TinyTemplate::new();
         tt.set_default_formatter(&tinytemplate::format_unescaped);
-        match tt.add_template("html", &template_buffer) {
+        match tt.add_template("gemini", &template_buffer) {
             Ok(_) => {},
             Err(_) => {
-                eprintln!("Error Could not parse HTML postlist template file");
+                eprintln!("Error Could not parse Gemini postlist template file");
                 exit(1);
             }
         }
 
         let has_topics = !self.topics.is_empty();
 
+        for (output_path, posts, listing) in self.post_listing_pages("gmi") {
+            let context = IndexContext {
+                output_target: "gemini".to_string(),
+                build: self.build_info.clone(),
+                site: self.config.site.clone(),
+                latest_post: self.posts[0].clone(),
+                highlights: self.highlights.clone(),
+                has_highlights: !self.highlights.is_empty(),
+                posts,
+                notes: self.notes.clone(),
+                has_notes: !self.notes.is_empty(),
+                topics: self.topics.clone(),
+                has_topics,
+                has_about: self.has_about,
+                pages: self.page_links(),
+                listing,
+            };
+
+            let postlist_path = output_file(&self.config.site.gemini_root, &output_path, "gmi");
+            println!("Writing postlist {}", &postlist_path.to_string_lossy());
+
+            let rendered = tt.render("gemini", &context).unwrap();
+            self.write_file(&postlist_path, &rendered);
+        }
+    }
+
+    fn generate_note_listing_html(&self) {
+        let template = self.read_template("templates/html/notelist.html", "HTML notelist");
+        let mut tt = TinyTemplate::new();
+        tt.set_default_formatter(&tinytemplate::format_unescaped);
+        tt.add_formatter("long_date_formatter", long_date_formatter);
+        if tt.add_template("html", &template).is_err() {
+            eprintln!("Error: Could not parse HTML notelist template file");
+            exit(1);
+        }
+
         let context = IndexContext {
+            output_target: "html".to_string(),
+            build: self.build_info.clone(),
             site: self.config.site.clone(),
             latest_post: self.posts[0].clone(),
+            highlights: self.highlights.clone(),
+            has_highlights: !self.highlights.is_empty(),
             posts: self.posts.clone(),
+            notes: self.notes.clone(),
+            has_notes: !self.notes.is_empty(),
             topics: self.topics.clone(),
-            has_topics,
+            has_topics: !self.topics.is_empty(),
             has_about: self.has_about,
+            pages: self.page_links(),
+            listing: ListingPage::default(),
         };
 
-        println!("Writing postlist.html");
+        println!("Writing notes.html");
 
-        let postlist_path: PathBuf = [
+        let notelist_path: PathBuf = [
             &self.config.site.html_root,
             "posts",
-            "posts.html",
+            "notes.html",
         ].iter().collect();
+        self.write_file(&notelist_path, &tt.render("html", &context).unwrap());
+    }
 
-        let output = OpenOptions::new()
-            .write(true)
-            .create(true)
-            .truncate(true)
-            .open(&postlist_path);
-        let mut output = match output {
-            Ok(o) => o,
-            Err(_) => {
-                eprintln!("Error: Could not open {} for writing", &postlist_path.to_string_lossy());
+    fn generate_note_listing_gmi(&self) {
+        let template = self.read_template("templates/gemini/notelist.gmi", "Gemini notelist");
+        let mut tt = TinyTemplate::new();
+        tt.set_default_formatter(&tinytemplate::format_unescaped);
+        tt.add_formatter("long_date_formatter", long_date_formatter);
+        if tt.add_template("gemini", &template).is_err() {
+            eprintln!("Error: Could not parse Gemini notelist template file");
+            exit(1);
+        }
+
+        let context = IndexContext {
+            output_target: "gemini".to_string(),
+            build: self.build_info.clone(),
+            site: self.config.site.clone(),
+            latest_post: self.posts[0].clone(),
+            highlights: self.highlights.clone(),
+            has_highlights: !self.highlights.is_empty(),
+            posts: self.posts.clone(),
+            notes: self.notes.clone(),
+            has_notes: !self.notes.is_empty(),
+            topics: self.topics.clone(),
+            has_topics: !self.topics.is_empty(),
+            has_about: self.has_about,
+            pages: self.page_links(),
+            listing: ListingPage::default(),
+        };
+
+        println!("Writing notes.gmi");
+
+        let notelist_path: PathBuf = [
+            &self.config.site.gemini_root,
+            "posts",
+            "notes.gmi",
+        ].iter().collect();
+        self.write_file(&notelist_path, &tt.render("gemini", &context).unwrap());
+    }
+
+    // stats.html and stats.gmi, with post counts by year, a histogram of
+    // topic tags and a word count.
+    fn generate_stats(&self) {
+        let stats = stats::collect(&self.posts, &self.notes, &self.topics);
+        self.write_generated_page("stats", "stats", |target| StatsContext {
+            output_target: target.to_string(),
+            build: self.build_info.clone(),
+            site: self.config.site.clone(),
+            stats: stats.clone(),
+            has_about: self.has_about,
+            pages: self.page_links(),
+        });
+    }
+
+    // A page per tag under tags/ listing the posts using it, and an index of
+    // the tags at tags/index.
+    fn generate_tag_pages(&self) {
+        let tags = tags::collect(&self.posts_of_kinds(&PostKind::ALL), &self.config.site);
+        for tag in &tags {
+            self.write_generated_page("tag", &format!("tags/{}", tag.link.slug), |target| TagContext {
+                output_target: target.to_string(),
+                build: self.build_info.clone(),
+                site: self.config.site.clone(),
+                tag: tag.clone(),
+                has_about: self.has_about,
+                pages: self.page_links(),
+            });
+        }
+        self.write_generated_page("tags", "tags/index", |target| TagsContext {
+            output_target: target.to_string(),
+            build: self.build_info.clone(),
+            site: self.config.site.clone(),
+            tags: tags.clone(),
+            has_about: self.has_about,
+            pages: self.page_links(),
+        });
+    }
+
+    // A page per author under authors/ listing their posts, and an index of
+    // the authors at authors/index.
+    fn generate_author_pages(&self) {
+        let authors = authors::collect(&self.posts_of_kinds(&PostKind::ALL));
+        for author in &authors {
+            self.write_generated_page("author", &format!("authors/{}", author.link.slug), |target| AuthorContext {
+                output_target: target.to_string(),
+                build: self.build_info.clone(),
+                site: self.config.site.clone(),
+                author: author.clone(),
+                has_about: self.has_about,
+                pages: self.page_links(),
+            });
+        }
+        self.write_generated_page("authors", "authors/index", |target| AuthorsContext {
+            output_target: target.to_string(),
+            build: self.build_info.clone(),
+            site: self.config.site.clone(),
+            authors: authors.clone(),
+            has_about: self.has_about,
+            pages: self.page_links(),
+        });
+    }
+
+    fn tag_links(&self, post: &Post) -> Vec<TagLink> {
+        if self.config.tags.enabled != Some(true) {
+            return Vec::new();
+        }
+        post.tags.iter().map(|t| tags::link(t, &self.config.site)).collect()
+    }
+
+    // The outbound links page, every external URL linked from a post along
+    // with the posts linking to it.
+    fn generate_link_log(&self) {
+        let posts = self.posts_of_kinds(&PostKind::ALL);
+        let links = outbound::collect(&posts, &self.config.site);
+        let filename = self.link_log_filename();
+        self.write_generated_page("links", &filename, |target| LinkLogContext {
+            output_target: target.to_string(),
+            build: self.build_info.clone(),
+            site: self.config.site.clone(),
+            links: links.clone(),
+            has_about: self.has_about,
+            pages: self.page_links(),
+        });
+    }
+
+    // bookmarks.toml next to the site's content becomes a paginated
+    // bookmarks/ section with its own Atom feed.
+    fn generate_bookmarks(&self) {
+        let bookmarks_path: PathBuf = [self.dir.to_str().unwrap(), "bookmarks.toml"].iter().collect();
+        let contents = match fs::read_to_string(&bookmarks_path) {
+            Ok(c) => c,
+            Err(_) => return,
+        };
+        let bookmarks = match bookmark::parse(&contents, self.timezone) {
+            Ok(b) => b,
+            Err(e) => {
+                eprintln!("Error: bookmarks.toml formatted incorrectly, {}", e);
                 exit(1);
             }
         };
+        if bookmarks.is_empty() {
+            return;
+        }
 
-        let rendered = tt.render("html", &context).unwrap();
-        match output.write_all(rendered.as_bytes()) {
-            Ok(_) => {}
-            Err(_) => {
-                eprintln!("Error: Could not write to {}", &postlist_path.to_string_lossy());
+        let pages = bookmark::paginate(&bookmarks, self.config.bookmarks.per_page.unwrap_or(50));
+        for page in &pages {
+            self.write_generated_page("bookmarks", &format!("bookmarks/{}", page.filename), |target| BookmarksContext {
+                output_target: target.to_string(),
+                build: self.build_info.clone(),
+                site: self.config.site.clone(),
+                page: if target == "html" {
+                    BookmarkPage {
+                        bookmarks: page.bookmarks.iter().map(bookmark::escaped).collect(),
+                        ..page.clone()
+                    }
+                } else {
+                    page.clone()
+                },
+                has_about: self.has_about,
+                pages: self.page_links(),
+            });
+        }
+
+        if self.config.bookmarks.feed.unwrap_or(true) {
+            self.generate_bookmark_feeds(&pages[0].bookmarks);
+        }
+    }
+
+    // Both feeds hold the bookmarks on the first page.
+    fn generate_bookmark_feeds(&self, bookmarks: &[Bookmark]) {
+        for (target, name, scheme, root, enabled) in [
+            ("html", "HTML", "http", &self.config.site.html_root,
+                self.html_output && self.config.feeds.html.unwrap_or(true)),
+            ("gemini", "Gemini", "gemini", &self.config.site.gemini_root,
+                self.gemini_output && self.config.feeds.gemini.unwrap_or(true)),
+        ] {
+            if !enabled {
+                continue;
+            }
+            let template = self.read_template(&format!("templates/{}/bookmarks-feed.xml", target),
+                &format!("{} bookmarks feed", name));
+            let mut tt = TinyTemplate::new();
+            tt.set_default_formatter(&tinytemplate::format_unescaped);
+            if tt.add_template("feed", &template).is_err() {
+                eprintln!("Error: Could not parse {} bookmarks feed template file", name);
                 exit(1);
             }
+
+            let context = BookmarkFeedContext {
+                output_target: target.to_string(),
+                build: self.build_info.clone(),
+                site: self.config.site.clone(),
+                last_updated: bookmarks[0].rfc_date.clone(),
+                feed_url: page_url(&self.config.site, scheme, "bookmarks/atom.xml"),
+                bookmarks: bookmarks.iter().map(bookmark::escaped).collect(),
+            };
+
+            let feed_path: PathBuf = [root.as_str(), "bookmarks", "atom.xml"].iter().collect();
+            println!("Writing {} bookmarks feed {}", name, &feed_path.to_string_lossy());
+            self.write_file(&feed_path, &tt.render("feed", &context).unwrap());
         }
     }
 
-    fn generate_post_listing_gmi(&self) {
-        // Open post listing template
-        let template_file;
-        let postlist_template_path: PathBuf = [
-            self.xdg_dirs.get_data_home(),
-            PathBuf::from("templates/gemini/postlist.gmi"),
-        ].iter().collect();
+    // A page of what was posted or updated each period, and a feed with an
+    // entry per period for readers who don't want every post.
+    fn generate_digest(&self, period: Period) {
+        let posts = self.posts_of_kinds(&PostKind::ALL);
+        let count = self.config.digest.count.unwrap_or(12);
+        let digests = digest::collect(&posts, &self.topics, period, count, self.timezone);
+        if digests.is_empty() {
+            return;
+        }
+        let escaped: Vec<_> = digests.iter().map(digest::escaped).collect();
 
-        template_file = OpenOptions::new()
-            .read(true)
-            .open(postlist_template_path);
-        let mut template_file = match template_file {
-            Ok(t) => t,
-            Err(_) => {
-                eprintln!("Error: Could not open Gemini postlist template");
-                exit(1);
+        self.write_generated_page("digest", "digest", |target| DigestContext {
+            output_target: target.to_string(),
+            build: self.build_info.clone(),
+            site: self.config.site.clone(),
+            digests: if target == "html" { escaped.clone() } else { digests.clone() },
+            has_about: self.has_about,
+            pages: self.page_links(),
+        });
+
+        for (target, name, scheme, root, enabled) in [
+            ("html", "HTML", "http", &self.config.site.html_root,
+                self.html_output && self.config.feeds.html.unwrap_or(true)),
+            ("gemini", "Gemini", "gemini", &self.config.site.gemini_root,
+                self.gemini_output && self.config.feeds.gemini.unwrap_or(true)),
+        ] {
+            if !enabled {
+                continue;
             }
-        };
-        // Read template to String and load into parser.
-        let mut template_buffer = String::new();
-        match template_file.read_to_string(&mut template_buffer) {
-            Ok(_) => {},
-            Err(_) => {
-                eprintln!("Error: Could not read from Gemini template");
-                exit(1)
+            let template = self.read_template(&format!("templates/{}/digest-feed.xml", target),
+                &format!("{} digest feed", name));
+            let mut tt = TinyTemplate::new();
+            tt.set_default_formatter(&tinytemplate::format_unescaped);
+            if tt.add_template("feed", &template).is_err() {
+                eprintln!("Error: Could not parse {} digest feed template file", name);
+                exit(1);
             }
+
+            let context = DigestFeedContext {
+                output_target: target.to_string(),
+                build: self.build_info.clone(),
+                site: self.config.site.clone(),
+                last_updated: escaped[0].rfc_date.clone(),
+                feed_url: page_url(&self.config.site, scheme, "digest.xml"),
+                digests: escaped.clone(),
+            };
+
+            let feed_path: PathBuf = [root.as_str(), "digest.xml"].iter().collect();
+            println!("Writing {} digest feed {}", name, &feed_path.to_string_lossy());
+            self.write_file(&feed_path, &tt.render("feed", &context).unwrap());
         }
+    }
+
+    fn link_log_filename(&self) -> String {
+        self.config.link_log.filename.clone().unwrap_or_else(|| "outbound".to_string())
+    }
+
+    // Render one template with the sample standing in for the content it
+    // shows, so a theme can be tried out without building the whole site.
+    // The rest of the context comes from the site.
+    pub fn test_template(&self, template: &str, sample: &Path) -> String {
+        let template_path = PathBuf::from(template);
+        let template_buffer = if template_path.is_file() {
+            match fs::read_to_string(&template_path) {
+                Ok(t) => t,
+                Err(_) => {
+                    eprintln!("Error: Could not read from template {}", template);
+                    exit(1);
+                }
+            }
+        } else {
+            self.read_template(&format!("templates/{}", template), template)
+        };
+        let target = match template_path.extension().and_then(|e| e.to_str()) {
+            Some("gmi") => "gemini",
+            _ => "html",
+        };
         let mut tt = TinyTemplate::new();
         tt.set_default_formatter(&tinytemplate::format_unescaped);
-        match tt.add_template("gemini", &template_buffer) {
-            Ok(_) => {},
-            Err(_) => {
-                eprintln!("Error Could not parse Gemini postlist template file");
+        tt.add_formatter("long_date_formatter", long_date_formatter);
+        if let Err(e) = tt.add_template(target, &template_buffer) {
+            eprintln!("Error: Could not parse template {}: {}", template, e);
+            exit(1);
+        }
+
+        let html_options = self.html_options();
+        let stem = template_path.file_stem().and_then(|s| s.to_str()).unwrap_or_default();
+        let rendered = match stem {
+            // Kind templates like post-note.html render posts too.
+            _ if stem == "post" || stem.starts_with("post-") => {
+                let mut post = Post::from_source(sample.to_path_buf(), html_options, self.timezone, &self.date_prefix);
+                post.set_urls(&self.config.site);
+                post.set_summary(self.config.feeds.summary_length.unwrap_or(280));
+                post.author = authors::link(&post.author.key, &self.config);
+                tt.render(target, &PostContext {
+                    output_target: target.to_string(),
+                    build: self.build_info.clone(),
+                    site: self.config.site.clone(),
+                    toc: post.toc.clone(),
+                    tags: self.tag_links(&post),
+                    post,
+                    pagination: Pagination::default(),
+                    has_about: self.has_about,
+                    pages: self.page_links(),
+                    print_css: self.print_css,
+                })
+            },
+            "topic" => {
+                let mut topic = Topic::from_source(sample.to_path_buf(), html_options);
+                topic.set_urls(&self.config.site);
+                tt.render(target, &TopicContext {
+                    output_target: target.to_string(),
+                    build: self.build_info.clone(),
+                    site: self.config.site.clone(),
+                    topic,
+                    backlinks: Vec::new(),
+                    has_about: self.has_about,
+                    pages: self.page_links(),
+                    recent_posts: self.recent_posts(),
+                })
+            },
+            "page" => tt.render(target, &PageContext {
+                output_target: target.to_string(),
+                build: self.build_info.clone(),
+                site: self.config.site.clone(),
+                page: Page::from_source(sample.to_path_buf(), html_options),
+                has_about: self.has_about,
+                pages: self.page_links(),
+                recent_posts: self.recent_posts(),
+            }),
+            "about" => tt.render(target, &AboutContext {
+                output_target: target.to_string(),
+                build: self.build_info.clone(),
+                site: self.config.site.clone(),
+                about: About::from_sources(sample.to_path_buf(), sample.to_path_buf(), "about", "About", html_options),
+                has_about: self.has_about,
+                pages: self.page_links(),
+                recent_posts: self.recent_posts(),
+            }),
+            // Listings show the sample as the newest post or note.
+            "index" | "postlist" | "notelist" => {
+                let mut post = Post::from_source(sample.to_path_buf(), html_options, self.timezone, &self.date_prefix);
+                post.set_urls(&self.config.site);
+                post.set_summary(self.config.feeds.summary_length.unwrap_or(280));
+                post.author = authors::link(&post.author.key, &self.config);
+                let mut posts = self.posts.clone();
+                let mut notes = self.notes.clone();
+                if stem == "notelist" {
+                    notes.insert(0, post);
+                } else {
+                    posts.insert(0, post);
+                }
+                tt.render(target, &IndexContext {
+                    output_target: target.to_string(),
+                    build: self.build_info.clone(),
+                    site: self.config.site.clone(),
+                    latest_post: posts[0].clone(),
+                    highlights: self.highlights.clone(),
+                    has_highlights: !self.highlights.is_empty(),
+                    posts,
+                    has_notes: !notes.is_empty(),
+                    notes,
+                    topics: self.topics.clone(),
+                    has_topics: !self.topics.is_empty(),
+                    has_about: self.has_about,
+                    pages: self.page_links(),
+                    listing: ListingPage::default(),
+                })
+            },
+            _ => {
+                eprintln!("Error: No sample context for template {}, expected one of post, topic, page, about, index, postlist or notelist",
+                    template);
+                exit(1);
+            },
+        };
+        match rendered {
+            Ok(r) => r,
+            Err(e) => {
+                eprintln!("Error: Could not render template {}: {}", template, e);
                 exit(1);
             }
         }
+    }
 
-        let has_topics = !self.topics.is_empty();
+    // Names of the pages crosspub generates at the root of the site besides
+    // the index, which content can't use.
+    fn generated_page_names(&self) -> Vec<String> {
+        let mut names = Vec::new();
+        if let Some(true) = self.config.stats.enabled {
+            names.push("stats".to_string());
+        }
+        if let Some(true) = self.config.link_log.enabled {
+            names.push(self.link_log_filename());
+        }
+        if self.config.digest.period.is_some() {
+            names.push("digest".to_string());
+        }
+        names
+    }
 
-        let context = IndexContext {
-            site: self.config.site.clone(),
-            latest_post: self.posts[0].clone(),
-            posts: self.posts.clone(),
-            topics: self.topics.clone(),
-            has_topics,
-            has_about: self.has_about,
-        };
+    // Render a page at the root of both outputs from the {template}.html and
+    // {template}.gmi templates.
+    fn write_generated_page<C: Serialize>(&self, template: &str, filename: &str, context: impl Fn(&str) -> C) {
+        for (target, name, root, extension, enabled) in [
+            ("html", "HTML", &self.config.site.html_root, "html", self.html_output),
+            ("gemini", "Gemini", &self.config.site.gemini_root, "gmi", self.gemini_output),
+        ] {
+            if !enabled {
+                continue;
+            }
+            let template_buffer = self.read_template(&format!("templates/{}/{}.{}", target, template, extension),
+                &format!("{} {}", name, template));
+            let mut tt = TinyTemplate::new();
+            tt.set_default_formatter(&tinytemplate::format_unescaped);
+            if tt.add_template(target, &template_buffer).is_err() {
+                eprintln!("Error: Could not parse {} {} template file", name, template);
+                exit(1);
+            }
 
-        println!("Writing postlist.gmi");
+            let page_path: PathBuf = [root.as_str(), &format!("{}.{}", filename, extension)].iter().collect();
+            println!("Writing {}", &page_path.to_string_lossy());
+            self.write_file(&page_path, &tt.render(target, &context(target)).unwrap());
+        }
+    }
 
-        let postlist_path: PathBuf = [
-            &self.config.site.gemini_root,
-            "posts",
-            "posts.gmi",
-        ].iter().collect();
+    fn write_html_pages(&self) {
+        if self.pages.is_empty() {
+            return;
+        }
+        let template = self.read_template("templates/html/page.html", "HTML page");
+        let mut tt = TinyTemplate::new();
+        tt.set_default_formatter(&tinytemplate::format_unescaped);
+        if tt.add_template("html", &template).is_err() {
+            eprintln!("Error: Could not parse HTML page template file");
+            exit(1);
+        }
+
+        for page in &self.pages {
+            let rendering = Instant::now();
+            let context = PageContext {
+                output_target: "html".to_string(),
+                build: self.build_info.clone(),
+                site: self.config.site.clone(),
+                page: page.clone(),
+                has_about: self.has_about,
+                pages: self.page_links(),
+                recent_posts: self.recent_posts(),
+            };
+            let page_path = output_file(&self.config.site.html_root, &page.output_path, "html");
+
+            println!("Writing \"{}\" to {}", &page.title, &page_path.to_string_lossy());
+            self.write_file(&page_path, &tt.render("html", &context).unwrap());
+            self.timings.page(&page.output_path, rendering.elapsed());
+        }
+    }
+
+    fn write_gemini_pages(&self) {
+        if self.pages.is_empty() {
+            return;
+        }
+        let template = self.read_template("templates/gemini/page.gmi", "Gemini page");
+        let mut tt = TinyTemplate::new();
+        tt.set_default_formatter(&tinytemplate::format_unescaped);
+        if tt.add_template("gemini", &template).is_err() {
+            eprintln!("Error: Could not parse Gemini page template file");
+            exit(1);
+        }
+
+        for page in &self.pages {
+            let rendering = Instant::now();
+            let context = PageContext {
+                output_target: "gemini".to_string(),
+                build: self.build_info.clone(),
+                site: self.config.site.clone(),
+                page: page.clone(),
+                has_about: self.has_about,
+                pages: self.page_links(),
+                recent_posts: self.recent_posts(),
+            };
+            let page_path = output_file(&self.config.site.gemini_root, &page.output_path, "gmi");
+
+            println!("Writing \"{}\" to {}", &page.title, &page_path.to_string_lossy());
+            self.write_file(&page_path, &tt.render("gemini", &context).unwrap());
+            self.timings.page(&page.output_path, rendering.elapsed());
+        }
+    }
+
+    // Per-kind post templates like post-note.html are optional, return the ones
+    // that exist keyed by their template name.
+    fn read_kind_templates(&self, dir: &str, extension: &str) -> Vec<(String, String)> {
+        let mut templates = Vec::new();
+        for kind in PostKind::ALL {
+            let path = format!("templates/{}/post-{}.{}", dir, kind.name(), extension);
+            if self.find_data_file(&path).is_some() {
+                let description = format!("{} {} post", dir, kind.name());
+                templates.push((format!("{}-{}", dir, kind.name()), self.read_template(&path, &description)));
+            }
+        }
+        templates
+    }
 
-        let output = OpenOptions::new()
-            .write(true)
-            .create(true)
-            .truncate(true)
-            .open(&postlist_path);
-        let mut output = match output {
-            Ok(o) => o,
-            Err(_) => {
-                eprintln!("Error: Could not open {} for writing", &postlist_path.to_string_lossy());
+    // Find a template in the user's data directory, falling back to the system
+    // wide defaults, and read it into a String.
+    fn read_template(&self, path: &str, description: &str) -> String {
+        let template_path = match self.find_data_file(path) {
+            Some(p) => p,
+            None => {
+                eprintln!("Error: Could not find {} template.", description);
                 exit(1);
             }
         };
-
-        let rendered = tt.render("gemini", &context).unwrap();
-        match output.write_all(rendered.as_bytes()) {
-            Ok(_) => {}
+        match fs::read_to_string(&template_path) {
+            Ok(t) => t,
             Err(_) => {
-                eprintln!("Error: Could not write to {}", &postlist_path.to_string_lossy());
+                eprintln!("Error: Could not read from {} template", description);
                 exit(1);
             }
         }
@@ -408,8 +2461,7 @@ impl CrossPub {
 
     fn generate_index_gmi(&self) {
         // Open index template
-        let template_file;
-        let index_template_path = self.xdg_dirs.find_data_file("templates/gemini/index.gmi");
+        let index_template_path = self.find_data_file("templates/gemini/index.gmi");
         let index_template_path = match index_template_path {
             Some(p) => p,
             _ => {
@@ -418,7 +2470,7 @@ impl CrossPub {
             }
         };
 
-        template_file = OpenOptions::new()
+        let template_file = OpenOptions::new()
             .read(true)
             .open(index_template_path);
         let mut template_file = match template_file {
@@ -450,12 +2502,20 @@ impl CrossPub {
         let has_topics = !self.topics.is_empty();
 
         let context = IndexContext {
+            output_target: "gemini".to_string(),
+            build: self.build_info.clone(),
             site: self.config.site.clone(),
             latest_post: self.posts[0].clone(),
+            highlights: self.highlights.clone(),
+            has_highlights: !self.highlights.is_empty(),
             posts: self.posts.clone(),
+            notes: self.notes.clone(),
+            has_notes: !self.notes.is_empty(),
             topics: self.topics.clone(),
             has_topics,
             has_about: self.has_about,
+            pages: self.page_links(),
+            listing: ListingPage::default(),
         };
 
         println!("Writing index.gmi");
@@ -465,31 +2525,12 @@ impl CrossPub {
             "index.gmi",
         ].iter().collect();
 
-        let output = OpenOptions::new()
-            .write(true)
-            .create(true)
-            .truncate(true)
-            .open(&index_path);
-        let mut output = match output {
-            Ok(o) => o,
-            Err(_) => {
-                eprintln!("Error: Could not open {} for writing", &index_path.to_string_lossy());
-                exit(1);
-            }
-        };
-
         let rendered = tt.render("gemini", &context).unwrap();
-        match output.write_all(rendered.as_bytes()) {
-            Ok(_) => {}
-            Err(_) => {
-                eprintln!("Error: Could not write to {}", &index_path.to_string_lossy());
-                exit(1);
-            }
-        }
+        self.write_file(&index_path, &rendered);
     }
 
     fn copy_css(&self) {
-        let css_source_path = match self.xdg_dirs.find_data_file("templates/html/style.css") {
+        let css_source_path = match self.find_data_file("templates/html/style.css") {
             Some(t) => t,
             _ => {
                 eprintln!("Error: Could not find source CSS file.");
@@ -501,60 +2542,70 @@ impl CrossPub {
             &self.config.site.html_root,
             "css",
         ].iter().collect();
-        if !css_dir_path.exists() {
-            match fs::create_dir(&css_dir_path) {
-                Ok(_) => {},
-                Err(_) => {
-                    eprintln!("Error: Could not create directory at {}",
-                        &css_dir_path.to_string_lossy());
-                    exit(1);
-                }
-            }
-        }
-        
+
         let css_dest_path: PathBuf = [
             &css_dir_path.to_string_lossy(),
             "style.css",
         ].iter().collect();
-        match fs::copy(css_source_path, css_dest_path) {
+        match self.store.copy_from(&css_source_path, &css_dest_path) {
             Ok(_) => {},
             Err(_) => {
                 eprintln!("Error: Could not copy CSS file");
                 exit(1);
             }
         }
-    }
 
-    fn generate_about_html(&self) {
-        let about_template_path = match self.xdg_dirs.find_data_file("templates/html/about.html") {
-            Some(t) => t,
-            _ => {
-                eprintln!("Error: Could not find HTML post template.");
-                exit(1);
-            }
-        };
-        let template_file = OpenOptions::new()
-            .read(true)
-            .open(about_template_path);
-        let mut template_file = match template_file {
-            Ok(t) => t,
-            Err(_) => {
-                eprintln!("Error: Could not open HTML about template");
-                exit(1);
+        // The theme is copied as theme.css and loaded after style.css.
+        let theme = &self.config.site.theme;
+        if !theme.is_empty() {
+            let theme_source_path = match self.find_data_file(
+                format!("templates/html/themes/{}.css", theme)) {
+                Some(t) => t,
+                _ => {
+                    eprintln!("Error: Could not find CSS theme {}.", theme);
+                    exit(1);
+                }
+            };
+            let theme_dest_path: PathBuf = [
+                &css_dir_path.to_string_lossy(),
+                "theme.css",
+            ].iter().collect();
+            match self.store.copy_from(&theme_source_path, &theme_dest_path) {
+                Ok(_) => {},
+                Err(_) => {
+                    eprintln!("Error: Could not copy CSS theme {}", theme);
+                    exit(1);
+                }
             }
-        };
+        }
 
-        // Read template to String and load into parser.
-        let mut template_buffer = String::new();
-        match template_file.read_to_string(&mut template_buffer) {
-            Ok(_) => {},
-            Err(_) => {
-                eprintln!("Error: Could not read from HTML template");
-                exit(1)
+        if self.print_css {
+            let print_source_path = match self.find_data_file("templates/html/print.css") {
+                Some(t) => t,
+                _ => {
+                    eprintln!("Error: Could not find source print CSS file.");
+                    exit(1);
+                }
+            };
+            let print_dest_path: PathBuf = [
+                &css_dir_path.to_string_lossy(),
+                "print.css",
+            ].iter().collect();
+            match self.store.copy_from(&print_source_path, &print_dest_path) {
+                Ok(_) => {},
+                Err(_) => {
+                    eprintln!("Error: Could not copy print CSS file");
+                    exit(1);
+                }
             }
         }
+    }
+
+    fn generate_about_html(&self) {
+        let template_buffer = self.read_template("templates/html/about.html", "HTML about");
         let mut tt = TinyTemplate::new();
         tt.set_default_formatter(&tinytemplate::format_unescaped);
+        tt.add_formatter("long_date_formatter", long_date_formatter);
         match tt.add_template("html", &template_buffer) {
             Ok(_) => {},
             Err(_) => {
@@ -563,70 +2614,32 @@ impl CrossPub {
             }
         }
 
-        let context = AboutContext {
-            site: self.config.site.clone(),
-            about: self.about.clone(),
-            has_about: self.has_about,
-        };
-        let about_path: PathBuf = [
-            &self.config.site.html_root,
-            "about.html"
-        ].iter().collect();
-
-        println!("Writing about.html to {}", &about_path.to_string_lossy());
+        for about in &self.standalone {
+            let context = AboutContext {
+                output_target: "html".to_string(),
+                build: self.build_info.clone(),
+                site: self.config.site.clone(),
+                about: about.clone(),
+                has_about: self.has_about,
+                pages: self.page_links(),
+                recent_posts: self.recent_posts(),
+            };
+            let mut about_path: PathBuf = [
+                &self.config.site.html_root,
+                &about.filename,
+            ].iter().collect();
+            about_path.set_extension("html");
 
-        let output = OpenOptions::new()
-            .write(true)
-            .create(true)
-            .truncate(true)
-            .open(&about_path);
-        let mut output = match output {
-            Ok(o) => o,
-            Err(_) => {
-                eprintln!("Error: Could not open {} for writing", &about_path.to_string_lossy());
-                exit(1);
-            }
-        };
-        let rendered = tt.render("html", &context).unwrap();
-        match output.write_all(rendered.as_bytes()) {
-            Ok(_) => {},
-            Err(_) => {
-                eprintln!("Error: Could not write to {}", &about_path.to_str().unwrap());
-                exit(1);
-            }
+            println!("Writing {}.html to {}", &about.filename, &about_path.to_string_lossy());
+            self.write_file(&about_path, &tt.render("html", &context).unwrap());
         }
     }
 
     fn generate_about_gmi(&self) {
-        let about_template_path = match self.xdg_dirs.find_data_file("templates/gemini/about.gmi") {
-            Some(t) => t,
-            _ => {
-                eprintln!("Error: Could not find Gemini post template.");
-                exit(1);
-            }
-        };
-        let template_file = OpenOptions::new()
-            .read(true)
-            .open(about_template_path);
-        let mut template_file = match template_file {
-            Ok(t) => t,
-            Err(_) => {
-                eprintln!("Error: Could not open Gemini about template");
-                exit(1);
-            }
-        };
-
-        // Read template to String and load into parser.
-        let mut template_buffer = String::new();
-        match template_file.read_to_string(&mut template_buffer) {
-            Ok(_) => {},
-            Err(_) => {
-                eprintln!("Error: Could not read from Gemini template");
-                exit(1)
-            }
-        }
+        let template_buffer = self.read_template("templates/gemini/about.gmi", "Gemini about");
         let mut tt = TinyTemplate::new();
         tt.set_default_formatter(&tinytemplate::format_unescaped);
+        tt.add_formatter("long_date_formatter", long_date_formatter);
         match tt.add_template("gemini", &template_buffer) {
             Ok(_) => {},
             Err(_) => {
@@ -635,51 +2648,37 @@ impl CrossPub {
             }
         }
 
-        let context = AboutContext {
-            site: self.config.site.clone(),
-            about: self.about.clone(),
-            has_about: self.has_about,
-        };
-        let about_path: PathBuf = [
-            &self.config.site.gemini_root,
-            "about.gmi"
-        ].iter().collect();
-
-        println!("Writing about.gmi to {}", &about_path.to_string_lossy());
+        for about in &self.standalone {
+            let context = AboutContext {
+                output_target: "gemini".to_string(),
+                build: self.build_info.clone(),
+                site: self.config.site.clone(),
+                about: about.clone(),
+                has_about: self.has_about,
+                pages: self.page_links(),
+                recent_posts: self.recent_posts(),
+            };
+            let mut about_path: PathBuf = [
+                &self.config.site.gemini_root,
+                &about.filename,
+            ].iter().collect();
+            about_path.set_extension("gmi");
 
-        let output = OpenOptions::new()
-            .write(true)
-            .create(true)
-            .truncate(true)
-            .open(&about_path);
-        let mut output = match output {
-            Ok(o) => o,
-            Err(_) => {
-                eprintln!("Error: Could not open {} for writing", &about_path.to_string_lossy());
-                exit(1);
-            }
-        };
-        let rendered = tt.render("gemini", &context).unwrap();
-        match output.write_all(rendered.as_bytes()) {
-            Ok(_) => {},
-            Err(_) => {
-                eprintln!("Error: Could not write to {}", &about_path.to_str().unwrap());
-                exit(1);
-            }
+            println!("Writing {}.gmi to {}", &about.filename, &about_path.to_string_lossy());
+            self.write_file(&about_path, &tt.render("gemini", &context).unwrap());
         }
     }
 
     fn write_html_posts(&self) {
         // Open post template
-        let template_file;
-        let post_template_path = match self.xdg_dirs.find_data_file("templates/html/post.html") {
+        let post_template_path = match self.find_data_file("templates/html/post.html") {
             Some(t) => t,
             _ => {
                 eprintln!("Error: Could not find HTML post template.");
                 exit(1);
             }
         };
-        template_file = OpenOptions::new()
+        let template_file = OpenOptions::new()
             .read(true)
             .open(post_template_path);
         let mut template_file = match template_file {
@@ -709,60 +2708,91 @@ impl CrossPub {
                 exit(1)
             }
         }
+        let kind_templates = self.read_kind_templates("html", "html");
+        for (name, template) in &kind_templates {
+            if tt.add_template(name, template).is_err() {
+                eprintln!("Error: Could not parse HTML {} template file", name);
+                exit(1)
+            }
+        }
 
         // Generate posts.
-        for post in &self.posts {
-            let context = PostContext {
-                site: self.config.site.clone(),
-                post: post.clone(),
-                has_about: self.has_about,
-            };
-            let mut post_path: PathBuf = [
-                &self.config.site.html_root,
-                "posts",
-                &post.filename,
-            ].iter().collect();
-            post_path.set_extension("html");
-
-            println!("Writing \"{}\" to {}", &post.title, &post_path.to_string_lossy());
-
-            let output = OpenOptions::new()
-                .write(true)
-                .create(true)
-                .truncate(true)
-                .open(&post_path);
-            let mut output = match output {
-                Ok(o) => o,
-                Err(_) => {
-                    eprintln!("Error: Could not open {} for writing", &post_path.to_string_lossy());
-                    exit(1);
-                }
-            };
+        for post in self.posts.iter().chain(&self.notes) {
+            let rendering = Instant::now();
+            for (output_path, page, pagination) in self.html_post_pages(post) {
+                let context = PostContext {
+                    output_target: "html".to_string(),
+                    build: self.build_info.clone(),
+                    site: self.config.site.clone(),
+                    // The table of contents would link to headings on other
+                    // pages, the pagination stands in for it.
+                    toc: if pagination.pages.is_empty() { post.toc.clone() } else { toc::Toc::default() },
+                    post: page,
+                    tags: self.tag_links(post),
+                    pagination,
+                    has_about: self.has_about,
+                    pages: self.page_links(),
+                    print_css: self.print_css,
+                };
+                let post_path = output_file(&self.config.site.html_root, &output_path, "html");
+
+                println!("Writing \"{}\" to {}", &post.title, &post_path.to_string_lossy());
+
+                // This unwrap is fine, render can only fail given an incorrect
+                // template name.
+                let rendered = tt.render(&kind_template_name(&kind_templates, "html", post), &context).unwrap();
+                self.write_file(&post_path, &rendered);
+            }
+            self.timings.page(&post.output_path, rendering.elapsed());
+        }
+    }
 
-            // This unwrap is fine, render can only fail given an incorrect
-            // template name.
-            let rendered = tt.render("html", &context).unwrap();
-            match output.write_all(rendered.as_bytes()) {
-                Ok(_) => {},
-                Err(_) => {
-                    eprintln!("Error: Could not write to {}", &post_path.to_str().unwrap());
-                    exit(1);
-                }
-            }
+    // The HTML pages of a post with where each is written: the post itself,
+    // or each of its pages with paginate_by_heading, the first at the post's
+    // output path and the rest numbered after it like {output_path}-2.
+    fn html_post_pages(&self, post: &Post) -> Vec<(String, Post, Pagination)> {
+        if post.html_pages.is_empty() {
+            return vec![(post.output_path.clone(), post.clone(), Pagination::default())];
         }
+        let output_paths = post.html_page_output_paths();
+        let paths: Vec<String> = output_paths.iter()
+            .map(|o| page_path(&self.config.site, &format!("{}.html", o)))
+            .collect();
+        post.html_pages.iter().enumerate()
+            .map(|(i, html_page)| {
+                let pagination = Pagination {
+                    pages: post.html_pages.iter().enumerate()
+                        .map(|(j, p)| PaginationLink {
+                            number: j + 1,
+                            title: p.title.clone(),
+                            path: paths[j].clone(),
+                            current: i == j,
+                        })
+                        .collect(),
+                    previous: if i > 0 { paths[i - 1].clone() } else { String::new() },
+                    next: paths.get(i + 1).cloned().unwrap_or_default(),
+                };
+                let mut page = post.clone();
+                page.html_content = html_page.html.clone();
+                // A recipe or review is shown once, at the top of the post.
+                if i > 0 {
+                    page.structured_html = String::new();
+                }
+                (output_paths[i].clone(), page, pagination)
+            })
+            .collect()
     }
 
     fn write_html_topics(&self) {
         // Open topic template
-        let template_file;
-        let topic_template_path = match self.xdg_dirs.find_data_file("templates/html/topic.html") {
+        let topic_template_path = match self.find_data_file("templates/html/topic.html") {
             Some(t) => t,
             _ => {
                 eprintln!("Error: Could not find HTML topic template.");
                 exit(1);
             }
         };
-        template_file = OpenOptions::new()
+        let template_file = OpenOptions::new()
             .read(true)
             .open(topic_template_path);
         let mut template_file = match template_file {
@@ -784,6 +2814,7 @@ impl CrossPub {
         }
         let mut tt = TinyTemplate::new();
         tt.set_default_formatter(&tinytemplate::format_unescaped);
+        tt.add_formatter("long_date_formatter", long_date_formatter);
         match tt.add_template("html", &template_buffer) {
             Ok(_) => {},
             Err(_) => {
@@ -794,56 +2825,39 @@ impl CrossPub {
 
         // Generate topics.
         for topic in &self.topics {
+            let rendering = Instant::now();
             let context = TopicContext {
+                output_target: "html".to_string(),
+                build: self.build_info.clone(),
                 site: self.config.site.clone(),
                 topic: topic.clone(),
+                backlinks: self.backlinks.get(&topic.output_path).cloned().unwrap_or_default(),
                 has_about: self.has_about,
+                pages: self.page_links(),
+                recent_posts: self.recent_posts(),
             };
-            let mut topic_path: PathBuf = [
-                &self.config.site.html_root,
-                &topic.filename
-            ].iter().collect();
-            topic_path.set_extension("html");
+            let topic_path = output_file(&self.config.site.html_root, &topic.output_path, "html");
 
             println!("Writing \"{}\" to {}", &topic.title, &topic_path.to_str().unwrap());
 
-            let output = OpenOptions::new()
-                .write(true)
-                .create(true)
-                .truncate(true)
-                .open(&topic_path);
-            let mut output = match output {
-                Ok(o) => o,
-                Err(_) => {
-                    eprintln!("Error: Could not open {} for writing", &topic_path.to_str().unwrap());
-                    exit(1);
-                }
-            };
-
             // This unwrap is fine, render can only fail given an incorrect
             // template name.
             let rendered = tt.render("html", &context).unwrap();
-            match output.write_all(rendered.as_bytes()) {
-                Ok(_) => {},
-                Err(_) => {
-                    eprintln!("Error: Could not write to {}", &topic_path.to_str().unwrap());
-                    exit(1)
-                }
-            }
+            self.write_file(&topic_path, &rendered);
+            self.timings.page(&topic.output_path, rendering.elapsed());
         }
     }
 
     fn write_gemini_posts(&self) {
         // Open post template
-        let template_file;
-        let post_template_path = match self.xdg_dirs.find_data_file("templates/gemini/post.gmi") {
+        let post_template_path = match self.find_data_file("templates/gemini/post.gmi") {
             Some(t) => t,
             _ => {
                 eprintln!("Error: Could not find Gemini post template.");
                 exit(1);
             }
         };
-        template_file = OpenOptions::new()
+        let template_file = OpenOptions::new()
             .read(true)
             .open(post_template_path);
         let mut template_file = match template_file {
@@ -875,58 +2889,50 @@ impl CrossPub {
                 exit(1)
             }
         }
+        let kind_templates = self.read_kind_templates("gemini", "gmi");
+        for (name, template) in &kind_templates {
+            if tt.add_template(name, template).is_err() {
+                eprintln!("Error: Could not parse gemini {} template file", name);
+                exit(1)
+            }
+        }
 
         // Generate posts.
-        for post in &self.posts {
+        for post in self.posts.iter().chain(&self.notes) {
+            let rendering = Instant::now();
             let context = PostContext {
+                output_target: "gemini".to_string(),
+                build: self.build_info.clone(),
                 site: self.config.site.clone(),
                 post: post.clone(),
+                toc: post.toc.clone(),
+                tags: self.tag_links(post),
+                // Gemini keeps posts in a single document.
+                pagination: Pagination::default(),
                 has_about: self.has_about,
+                pages: self.page_links(),
+                print_css: self.print_css,
             };
-            let mut post_path: PathBuf = [
-                &self.config.site.gemini_root,
-                "posts",
-                &post.filename
-            ].iter().collect();
-            post_path.set_extension("gmi");
+            let post_path = output_file(&self.config.site.gemini_root, &post.output_path, "gmi");
 
             println!("Writing \"{}\" to {}", &post.title, &post_path.to_str().unwrap());
 
-            let output = OpenOptions::new()
-                .write(true)
-                .create(true)
-                .truncate(true)
-                .open(&post_path);
-            let mut output = match output {
-                Ok(o) => o,
-                Err(_) => {
-                    eprintln!("Error: Could not open {} for writing", &post_path.to_str().unwrap());
-                    exit(1);
-                }
-            };
-
-            let rendered = tt.render("gemini", &context).unwrap();
-            match output.write_all(rendered.as_bytes()) {
-                Ok(_) => {},
-                Err(_) => {
-                    eprintln!("Error: Could not write to {}", post_path.to_str().unwrap());
-                    exit(1)
-                }
-            }
+            let rendered = tt.render(&kind_template_name(&kind_templates, "gemini", post), &context).unwrap();
+            self.write_file(&post_path, &rendered);
+            self.timings.page(&post.output_path, rendering.elapsed());
         }
     }
 
     fn write_gemini_topics(&self) {
         // Open topic template
-        let template_file;
-        let topic_template_path = match self.xdg_dirs.find_data_file("templates/gemini/topic.gmi") {
+        let topic_template_path = match self.find_data_file("templates/gemini/topic.gmi") {
             Some(t) => t,
             _ => {
                 eprintln!("Error: Could not find Gemini topic template.");
                 exit(1);
             }
         };
-        template_file = OpenOptions::new()
+        let template_file = OpenOptions::new()
             .read(true)
             .open(topic_template_path);
         let mut template_file = match template_file {
@@ -950,6 +2956,7 @@ impl CrossPub {
 
         let mut tt = TinyTemplate::new();
         tt.set_default_formatter(&tinytemplate::format_unescaped);
+        tt.add_formatter("long_date_formatter", long_date_formatter);
         match tt.add_template("gemini", &template_buffer) {
             Ok(_) => {},
             Err(_) => {
@@ -960,47 +2967,29 @@ impl CrossPub {
 
         // Generate topics.
         for topic in &self.topics {
+            let rendering = Instant::now();
             let context = TopicContext {
+                output_target: "gemini".to_string(),
+                build: self.build_info.clone(),
                 site: self.config.site.clone(),
                 topic: topic.clone(),
+                backlinks: self.backlinks.get(&topic.output_path).cloned().unwrap_or_default(),
                 has_about: self.has_about,
+                pages: self.page_links(),
+                recent_posts: self.recent_posts(),
             };
-            let mut topic_path: PathBuf = [
-                &self.config.site.gemini_root,
-                &topic.filename
-            ].iter().collect();
-            topic_path.set_extension("gmi");
+            let topic_path = output_file(&self.config.site.gemini_root, &topic.output_path, "gmi");
 
             println!("Writing \"{}\" to {}", &topic.title, &topic_path.to_str().unwrap());
 
-            let output = OpenOptions::new()
-                .write(true)
-                .create(true)
-                .truncate(true)
-                .open(&topic_path);
-            let mut output = match output {
-                Ok(o) => o,
-                Err(_) => {
-                    eprintln!("Error: Could not open {} for writing", &topic_path.to_str().unwrap());
-                    exit(1);
-                }
-            };
-
             let rendered = tt.render("gemini", &context).unwrap();
-            match output.write_all(rendered.as_bytes()) {
-                Ok(_) => {},
-                Err(_) => {
-                    eprintln!("Error: Could not write to {}", topic_path.to_str().unwrap());
-                    exit(1)
-                }
-            }
+            self.write_file(&topic_path, &rendered);
+            self.timings.page(&topic.output_path, rendering.elapsed());
         }
     }
 
-    fn generate_gemini_atom_feed(&self) {
-        let feed_template_file;
-        let entry_template_file;
-        let feed_template_path = self.xdg_dirs.find_data_file("templates/gemini/atom-feed.xml");
+    fn generate_gemini_atom_feed(&self, posts: &[Post], filename: &str) {
+        let feed_template_path = self.find_data_file("templates/gemini/atom-feed.xml");
         let feed_template_path = match feed_template_path {
             Some(p) => p,
             _ => {
@@ -1008,7 +2997,7 @@ impl CrossPub {
                 exit(1);
             }
         };
-        let entry_template_path = self.xdg_dirs.find_data_file("templates/gemini/atom-entry.xml");
+        let entry_template_path = self.find_data_file("templates/gemini/atom-entry.xml");
         let entry_template_path = match entry_template_path {
             Some(p) => p,
             _ => {
@@ -1017,7 +3006,7 @@ impl CrossPub {
             }
         };
 
-        feed_template_file = OpenOptions::new()
+        let feed_template_file = OpenOptions::new()
             .read(true)
             .open(feed_template_path);
         let mut feed_template_file = match feed_template_file {
@@ -1027,7 +3016,7 @@ impl CrossPub {
                 exit(1);
             }
         };
-        entry_template_file = OpenOptions::new()
+        let entry_template_file = OpenOptions::new()
             .read(true)
             .open(entry_template_path);
         let mut entry_template_file = match entry_template_file {
@@ -1074,58 +3063,43 @@ impl CrossPub {
 
         // Generate all entry listings and add to a vector which is used in an AtomFeedContext.
         let mut entries: Vec<String> = Vec::new();
-        for post in &self.posts {
-            let dt: DateTime<Local> = Local.from_local_datetime(&post.date).unwrap();
+        for post in posts {
             let entry_context = AtomEntryContext {
+                output_target: "gemini".to_string(),
+                build: self.build_info.clone(),
                 site: self.config.site.clone(),
                 post: post.clone(),
-                rfc_date: dt.to_rfc3339(),
+                rfc_date: post.published.to_rfc3339(),
+                rfc_updated: post.last_changed().to_rfc3339(),
+                summary: post.summary_html.clone(),
             };
             entries.push(tt.render("entry", &entry_context).unwrap());
         }
 
         // Generate feed.
-        let dt: DateTime<Local> = Local.from_local_datetime(&self.posts[0].date).unwrap();
         let feed_context = AtomFeedContext {
+            output_target: "gemini".to_string(),
+            build: self.build_info.clone(),
             site: self.config.site.clone(),
-            last_updated: dt.to_rfc3339(),
-            entries: entries,
+            last_updated: last_changed(posts).to_rfc3339(),
+            entries,
+            feed_url: page_url(&self.config.site, "gemini", filename),
+            hub: String::new(),
         };
         let rendered_feed = tt.render("feed", &feed_context).unwrap();
 
-        println!("Writing gemini Atom feed");
+        println!("Writing gemini Atom feed {}", filename);
 
         let feed_path: PathBuf = [
             &self.config.site.gemini_root,
-            "index.xml",
+            filename,
         ].iter().collect();
 
-        let output = OpenOptions::new()
-            .write(true)
-            .create(true)
-            .truncate(true)
-            .open(&feed_path);
-        let mut output = match output {
-            Ok(o) => o,
-            Err(_) => {
-                eprintln!("Error: Could not open {} for writing", &feed_path.to_string_lossy());
-                exit(1);
-            }
-        };
-
-        match output.write_all(rendered_feed.as_bytes()) {
-            Ok(_) => {}
-            Err(_) => {
-                eprintln!("Error: Could not write to {}", &feed_path.to_string_lossy());
-                exit(1);
-            }
-        }
+        self.write_file(&feed_path, &rendered_feed);
     }
 
-    fn generate_html_atom_feed(&self) {
-        let feed_template_file;
-        let entry_template_file;
-        let feed_template_path = self.xdg_dirs.find_data_file("templates/html/atom-feed.xml");
+    fn generate_html_atom_feed(&self, posts: &[Post], filename: &str) {
+        let feed_template_path = self.find_data_file("templates/html/atom-feed.xml");
         let feed_template_path = match feed_template_path {
             Some(p) => p,
             _ => {
@@ -1133,7 +3107,7 @@ impl CrossPub {
                 exit(1);
             }
         };
-        let entry_template_path = self.xdg_dirs.find_data_file("templates/html/atom-entry.xml");
+        let entry_template_path = self.find_data_file("templates/html/atom-entry.xml");
         let entry_template_path = match entry_template_path {
             Some(p) => p,
             _ => {
@@ -1142,7 +3116,7 @@ impl CrossPub {
             }
         };
 
-        feed_template_file = OpenOptions::new()
+        let feed_template_file = OpenOptions::new()
             .read(true)
             .open(feed_template_path);
         let mut feed_template_file = match feed_template_file {
@@ -1152,7 +3126,7 @@ impl CrossPub {
                 exit(1);
             }
         };
-        entry_template_file = OpenOptions::new()
+        let entry_template_file = OpenOptions::new()
             .read(true)
             .open(entry_template_path);
         let mut entry_template_file = match entry_template_file {
@@ -1199,60 +3173,223 @@ impl CrossPub {
 
         // Generate all entry listings and add to a vector which is used in an AtomFeedContext.
         let mut entries: Vec<String> = Vec::new();
-        for post in &self.posts {
-            let dt: DateTime<Local> = Local.from_local_datetime(&post.date).unwrap();
+        for post in posts {
             let entry_context = AtomEntryContext {
+                output_target: "html".to_string(),
+                build: self.build_info.clone(),
                 site: self.config.site.clone(),
                 post: post.clone(),
-                rfc_date: dt.to_rfc3339(),
+                rfc_date: post.published.to_rfc3339(),
+                rfc_updated: post.last_changed().to_rfc3339(),
+                summary: post.summary_html.clone(),
             };
             entries.push(tt.render("entry", &entry_context).unwrap());
         }
 
         // Generate feed.
-        let dt: DateTime<Local> = Local.from_local_datetime(&self.posts[0].date).unwrap();
+        let feed_url = page_url(&self.config.site, "http", filename);
         let feed_context = AtomFeedContext {
+            output_target: "html".to_string(),
+            build: self.build_info.clone(),
             site: self.config.site.clone(),
-            last_updated: dt.to_rfc3339(),
-            entries: entries,
+            last_updated: last_changed(posts).to_rfc3339(),
+            entries,
+            feed_url: feed_url.clone(),
+            hub: self.config.websub.hub.clone().unwrap_or_default(),
         };
         let rendered_feed = tt.render("feed", &feed_context).unwrap();
 
-        println!("Writing HTML Atom feed");
+        println!("Writing HTML Atom feed {}", filename);
 
         let feed_path: PathBuf = [
             &self.config.site.html_root,
-            "index.xml",
+            filename,
         ].iter().collect();
+        let changed = self.store.read_to_string(&feed_path).map_or(true, |old| old != rendered_feed);
 
-        let output = OpenOptions::new()
-            .write(true)
-            .create(true)
-            .truncate(true)
-            .open(&feed_path);
-        let mut output = match output {
-            Ok(o) => o,
-            Err(_) => {
-                eprintln!("Error: Could not open {} for writing", &feed_path.to_string_lossy());
-                exit(1);
+        self.write_file(&feed_path, &rendered_feed);
+
+        // With changes tracked the hub is only pinged for new or changed posts.
+        let changed = changed && self.changes.as_ref().is_none_or(|c| !c.new.is_empty() || !c.changed.is_empty());
+        if let (Some(hub), true, false) = (&self.config.websub.hub, changed, self.offline) {
+            websub::publish(hub, &feed_url);
+        }
+    }
+}
+
+// Posts with a language grouped by it, keeping their order.
+fn posts_by_lang(posts: &[Post]) -> BTreeMap<String, Vec<Post>> {
+    let mut by_lang: BTreeMap<String, Vec<Post>> = BTreeMap::new();
+    for post in posts.iter().filter(|p| !p.lang.is_empty()) {
+        by_lang.entry(post.lang.clone()).or_default().push(post.clone());
+    }
+    by_lang
+}
+
+// How the HTML output is rendered, from the [build] and [html] sections of
+// the config.
+pub fn html_options(config: &Config) -> HtmlOptions {
+    HtmlOptions {
+        raw_html: config.build.raw_html.unwrap_or(false),
+        rewrite_links: config.build.rewrite_links.unwrap_or(true),
+        inline_images: config.html.inline_images.unwrap_or(false),
+        figures: config.html.figures.unwrap_or(false),
+        ordered_lists: config.html.ordered_lists.unwrap_or(false),
+        inline_formatting: config.html.inline_formatting.unwrap_or(false),
+        heading_ids: config.html.heading_ids.unwrap_or(true),
+        heading_links: config.html.heading_links.unwrap_or(false),
+        gemini_proxy: config.html.gemini_proxy.clone(),
+        gopher_proxy: config.html.gopher_proxy.clone(),
+        scheme_badges: config.html.scheme_badges.unwrap_or(false),
+        site_host: site_host(&config.site.url).to_string(),
+        external_rel: config.html.external_rel.clone(),
+        external_target: config.html.external_target.clone(),
+        smart_typography: config.html.smart_typography.unwrap_or(false),
+        reflow: config.html.reflow.unwrap_or(false),
+        lede: config.html.lede.unwrap_or(false),
+        sections: config.html.sections.unwrap_or(false),
+    }
+}
+
+// The newest change to any of the posts of a feed.
+fn last_changed(posts: &[Post]) -> DateTime<FixedOffset> {
+    posts.iter().map(Post::last_changed).max().unwrap_or(posts[0].published)
+}
+
+// What writes the pages of a template, named so templates writing the same
+// pages only render them once.
+type TemplateWriter = (&'static str, fn(&CrossPub));
+
+// None for templates only a full build can render.
+fn template_writer(template: &str) -> Option<TemplateWriter> {
+    let (dir, file) = template.split_once('/')?;
+    let stem = file.rsplit_once('.').map_or(file, |(s, _)| s);
+    let writer: TemplateWriter = match (dir, stem) {
+        ("html", s) if s == "post" || s.starts_with("post-") => ("html posts", |cp| cp.write_html_posts()),
+        ("gemini", s) if s == "post" || s.starts_with("post-") => ("gemini posts", |cp| cp.write_gemini_posts()),
+        ("html", "topic") => ("html topics", |cp| cp.write_html_topics()),
+        ("gemini", "topic") => ("gemini topics", |cp| cp.write_gemini_topics()),
+        ("html", "index") => ("html index", |cp| cp.generate_index_html()),
+        ("gemini", "index") => ("gemini index", |cp| cp.generate_index_gmi()),
+        ("html", "page") => ("html pages", |cp| cp.write_html_pages()),
+        ("gemini", "page") => ("gemini pages", |cp| cp.write_gemini_pages()),
+        ("html", "about") => ("html about", |cp| if !cp.standalone.is_empty() { cp.generate_about_html() }),
+        ("gemini", "about") => ("gemini about", |cp| if !cp.standalone.is_empty() { cp.generate_about_gmi() }),
+        ("html", "postlist") => ("html postlist", |cp| if cp.post_listing { cp.generate_post_listing_html() }),
+        ("gemini", "postlist") => ("gemini postlist", |cp| if cp.post_listing { cp.generate_post_listing_gmi() }),
+        ("html", "notelist") => ("html notelist", |cp| if !cp.notes.is_empty() { cp.generate_note_listing_html() }),
+        ("gemini", "notelist") => ("gemini notelist", |cp| if !cp.notes.is_empty() { cp.generate_note_listing_gmi() }),
+        ("html", s) if s == "style" || s == "print" || s.starts_with("themes/") => ("css", |cp| cp.copy_css()),
+        // Generated pages write both outputs at once.
+        (_, "stats") => ("stats", |cp| if cp.config.stats.enabled == Some(true) { cp.generate_stats() }),
+        (_, "links") => ("links", |cp| if cp.config.link_log.enabled == Some(true) { cp.generate_link_log() }),
+        (_, "tag" | "tags") => ("tags", |cp| if cp.config.tags.enabled == Some(true) { cp.generate_tag_pages() }),
+        (_, "author" | "authors") => ("authors", |cp| if cp.config.author_pages.enabled == Some(true) { cp.generate_author_pages() }),
+        _ => return None,
+    };
+    Some(writer)
+}
+
+// The host part of site.url, which may come with a scheme and a path.
+fn site_host(url: &str) -> &str {
+    let url = url.split_once("://").map(|(_, rest)| rest).unwrap_or(url);
+    url.split('/').next().unwrap_or(url)
+}
+
+fn kind_template_name(kind_templates: &[(String, String)], base: &str, post: &Post) -> String {
+    let name = format!("{}-{}", base, post.kind.name());
+    if kind_templates.iter().any(|(n, _)| *n == name) {
+        name
+    } else {
+        base.to_string()
+    }
+}
+
+// Every file with one of the given extensions in a content directory and
+// its subdirectories, minus hidden files, editor leftovers and anything
+// matched by a .gitignore or .crosspubignore. Returns each file with its
+// path relative to the directory.
+pub fn content_files(dir: &Path, extensions: &[&str], follow_symlinks: bool) -> Vec<(PathBuf, String)> {
+    // The content directory itself may be a symlink, which is always
+    // followed. Symlinks inside it are only followed with follow_symlinks.
+    let root = match fs::canonicalize(dir) {
+        Ok(r) => r,
+        Err(_) => {
+            eprintln!("Error: Could not resolve {}", &dir.to_string_lossy());
+            exit(1);
+        }
+    };
+    let walker = WalkBuilder::new(&root)
+        .add_custom_ignore_filename(".crosspubignore")
+        .require_git(false)
+        .follow_links(follow_symlinks)
+        .build();
+
+    let mut files = Vec::new();
+    let mut seen = HashSet::new();
+    for entry in walker {
+        // Symlink cycles end up here too.
+        let entry = match entry {
+            Ok(e) => e,
+            Err(e) => {
+                warning!("{}", e);
+                continue;
             }
         };
-
-        match output.write_all(rendered_feed.as_bytes()) {
-            Ok(_) => {}
+        let p = entry.path();
+        let extension = p.extension().unwrap_or_default().to_string_lossy().to_lowercase();
+        if !extensions.contains(&extension.as_str()) || is_temp_file(p) {
+            continue;
+        }
+        if entry.path_is_symlink() && !follow_symlinks {
+            continue;
+        }
+        // A file linked from several places is only loaded once.
+        let target = match fs::canonicalize(p) {
+            Ok(t) if t.is_file() => t,
+            Ok(_) => continue,
             Err(_) => {
-                eprintln!("Error: Could not write to {}", &feed_path.to_string_lossy());
-                exit(1);
+                warning!("Skipping broken symlink {}", &p.to_string_lossy());
+                continue;
             }
+        };
+        if !seen.insert(target) {
+            continue;
         }
+        let relative = p.strip_prefix(&root).unwrap_or(p).to_string_lossy().into_owned();
+        files.push((p.to_path_buf(), relative));
     }
+    files.sort();
+    files
+}
+
+// Lock and backup files left behind by editors, like .#post.gmi or #post.gmi#.
+fn is_temp_file(path: &Path) -> bool {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    name.starts_with(".#") || name.starts_with('#') || name.ends_with('~')
+}
+
+// The store publishing the HTML output to the bucket set up under [s3].
+fn s3_store(c: &Config) -> S3Store {
+    match S3Store::new(&c.s3, Path::new(&c.site.html_root)) {
+        Ok(s) => s,
+        Err(e) => {
+            eprintln!("Error: Could not set up S3 publishing: {}", e);
+            exit(1);
+        }
+    }
+}
+
+// The file a page with the given output path is written to.
+fn output_file(root: &str, output_path: &str, extension: &str) -> PathBuf {
+    [root, &format!("{}.{}", output_path, extension)].iter().collect()
 }
 
 fn long_date_formatter(value: &Value, output: &mut String) -> tinytemplate::error::Result<()> {
     match value {
         Value::Null => Ok(()),
         Value::String(s) => {
-            let date = NaiveDate::parse_from_str(&s, "%Y-%m-%d");
+            let date = NaiveDate::parse_from_str(s, "%Y-%m-%d");
             let date = match date {
                 Ok(d) => d,
                 Err(_) => {
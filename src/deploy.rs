@@ -0,0 +1,83 @@
+use std::collections::BTreeMap;
+
+use sha2::{Digest, Sha256};
+
+// What a deploy backend needs to only transfer what changed: the hashes of
+// the files on the remote end, from the manifest the last deploy left there,
+// compared to the files this build writes. Keys are paths on the remote end.
+#[derive(Default)]
+pub struct Deploy {
+    remote: BTreeMap<String, String>,
+    written: BTreeMap<String, String>,
+    uploaded: Vec<String>,
+    deleted: Vec<String>,
+}
+
+impl Deploy {
+    // Start from a manifest in the "{hash}  {key}" lines of a lock file. With
+    // no manifest, like on a first deploy, every file is transferred.
+    pub fn new(manifest: Option<&str>) -> Deploy {
+        let remote = manifest.unwrap_or_default().lines()
+            .filter(|l| !l.trim().is_empty() && !l.starts_with('#'))
+            .filter_map(|l| l.split_once("  "))
+            .map(|(hash, key)| (key.to_string(), hash.to_string()))
+            .collect();
+        Deploy { remote, ..Default::default() }
+    }
+
+    // Record that the build wrote a file, returning whether it differs from
+    // the remote copy and has to be transferred.
+    pub fn write(&mut self, key: &str, contents: &[u8]) -> bool {
+        let hash: String = Sha256::digest(contents).iter().map(|b| format!("{:02x}", b)).collect();
+        let changed = self.remote.get(key) != Some(&hash);
+        self.written.insert(key.to_string(), hash);
+        changed
+    }
+
+    pub fn uploaded(&mut self, key: &str) {
+        self.uploaded.push(key.to_string());
+    }
+
+    pub fn deleted(&mut self, key: &str) {
+        self.remote.remove(key);
+        self.written.remove(key);
+        self.deleted.push(key.to_string());
+    }
+
+    // Files on the remote end this build didn't write. A build that wrote
+    // nothing, like one skipping the output deployed, has nothing stale.
+    pub fn stale(&self) -> Vec<String> {
+        if self.written.is_empty() {
+            return Vec::new();
+        }
+        self.remote.keys()
+            .filter(|k| !self.written.contains_key(*k))
+            .cloned()
+            .collect()
+    }
+
+    // The manifest for the next deploy, the files written by this build and
+    // whatever is left on the remote end from earlier ones.
+    pub fn manifest(&self) -> String {
+        let mut files = self.remote.clone();
+        files.extend(self.written.clone());
+        let mut contents = String::from("# Generated by crosspub, the files of the last deploy.\n");
+        for (key, hash) in files {
+            contents.push_str(&format!("{}  {}\n", hash, key));
+        }
+        contents
+    }
+
+    pub fn summary(&self) -> String {
+        let unchanged = self.written.len().saturating_sub(self.uploaded.len());
+        let mut text = format!("{} file(s) uploaded, {} deleted, {} unchanged\n",
+            self.uploaded.len(), self.deleted.len(), unchanged);
+        for key in &self.uploaded {
+            text.push_str(&format!("Uploaded {}\n", key));
+        }
+        for key in &self.deleted {
+            text.push_str(&format!("Deleted {}\n", key));
+        }
+        text
+    }
+}
@@ -0,0 +1,139 @@
+use std::collections::BTreeMap;
+
+use chrono::{Datelike, Duration, NaiveDate};
+use serde::Serialize;
+
+use crate::html::escape_text;
+use crate::post::Post;
+use crate::timezone::SiteTimezone;
+use crate::topic::Topic;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Period {
+    Weekly,
+    Monthly,
+}
+
+impl Period {
+    pub fn from_name(name: &str) -> Option<Period> {
+        match name {
+            "weekly" => Some(Period::Weekly),
+            "monthly" => Some(Period::Monthly),
+            _ => None,
+        }
+    }
+
+    // The first day of the period a date falls in, weeks start on Monday.
+    fn start(&self, date: NaiveDate) -> NaiveDate {
+        match self {
+            Period::Weekly => date - Duration::days(date.weekday().num_days_from_monday() as i64),
+            Period::Monthly => date.with_day(1).unwrap(),
+        }
+    }
+
+    fn label(&self, start: NaiveDate) -> String {
+        match self {
+            Period::Weekly => format!("Week of {}", start.format("%B %e, %Y")),
+            Period::Monthly => start.format("%B %Y").to_string(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct DigestEntry {
+    pub title: String,
+    pub output_path: String,
+    pub date: String,
+}
+
+// Everything published or updated in one period. id is the start of the
+// period, rfc_date the date of its newest entry.
+#[derive(Clone, Debug, Serialize)]
+pub struct Digest {
+    pub id: String,
+    pub label: String,
+    pub rfc_date: String,
+    pub summary: String,
+    pub posts: Vec<DigestEntry>,
+    pub topics: Vec<DigestEntry>,
+}
+
+// The newest periods with new posts or updated topics, at most limit of
+// them. A topic counts in the period of its updated date, or of its date if
+// it was never updated.
+pub fn collect(posts: &[Post], topics: &[Topic], period: Period, limit: usize, timezone: SiteTimezone) -> Vec<Digest> {
+    let mut periods: BTreeMap<NaiveDate, (Vec<DigestEntry>, Vec<DigestEntry>)> = BTreeMap::new();
+    for post in posts {
+        let date = post.date.date();
+        // Notes have no title, name them by their date instead.
+        let title = if post.title.is_empty() {
+            post.date.format("%B %e, %Y").to_string()
+        } else {
+            post.title.clone()
+        };
+        periods.entry(period.start(date)).or_default().0.push(DigestEntry {
+            title,
+            output_path: post.output_path.clone(),
+            date: date.to_string(),
+        });
+    }
+    for topic in topics {
+        let date = if topic.updated.is_empty() { &topic.date } else { &topic.updated };
+        let date = match NaiveDate::parse_from_str(date, "%Y-%m-%d") {
+            Ok(d) => d,
+            Err(_) => continue,
+        };
+        periods.entry(period.start(date)).or_default().1.push(DigestEntry {
+            title: topic.title.clone(),
+            output_path: topic.output_path.clone(),
+            date: date.to_string(),
+        });
+    }
+
+    periods.into_iter()
+        .rev()
+        .take(limit)
+        .map(|(start, (mut posts, mut topics))| {
+            posts.sort_by(|a, b| b.date.cmp(&a.date));
+            topics.sort_by(|a, b| b.date.cmp(&a.date));
+            let newest = posts.iter().chain(&topics).map(|e| e.date.as_str()).max().unwrap();
+            let newest = NaiveDate::parse_from_str(newest, "%Y-%m-%d").unwrap();
+            Digest {
+                id: start.to_string(),
+                label: period.label(start),
+                rfc_date: timezone.resolve(&newest.and_hms(0, 0, 0)).to_rfc3339(),
+                summary: summary(&posts, &topics),
+                posts,
+                topics,
+            }
+        })
+        .collect()
+}
+
+fn summary(posts: &[DigestEntry], topics: &[DigestEntry]) -> String {
+    let titles = |entries: &[DigestEntry]| entries.iter()
+        .map(|e| e.title.as_str())
+        .collect::<Vec<&str>>()
+        .join(", ");
+    let mut parts = Vec::new();
+    if !posts.is_empty() {
+        parts.push(format!("New posts: {}.", titles(posts)));
+    }
+    if !topics.is_empty() {
+        parts.push(format!("Updated topics: {}.", titles(topics)));
+    }
+    parts.join(" ")
+}
+
+// A copy safe to put in HTML or XML.
+pub fn escaped(digest: &Digest) -> Digest {
+    let escape_entries = |entries: &[DigestEntry]| entries.iter()
+        .map(|e| DigestEntry { title: escape_text(&e.title), ..e.clone() })
+        .collect();
+    Digest {
+        summary: escape_text(&digest.summary),
+        posts: escape_entries(&digest.posts),
+        topics: escape_entries(&digest.topics),
+        ..digest.clone()
+    }
+}
@@ -0,0 +1,261 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::config::Config;
+
+// The built-in templates and config, compiled in so missing files can be put
+// back without a system wide install in /usr/share/crosspub.
+const TEMPLATES: [(&str, &str); 44] = [
+    ("templates/gemini/about.gmi", include_str!("../templates/gemini/about.gmi")),
+    ("templates/gemini/atom-entry.xml", include_str!("../templates/gemini/atom-entry.xml")),
+    ("templates/gemini/atom-feed.xml", include_str!("../templates/gemini/atom-feed.xml")),
+    ("templates/gemini/author.gmi", include_str!("../templates/gemini/author.gmi")),
+    ("templates/gemini/authors.gmi", include_str!("../templates/gemini/authors.gmi")),
+    ("templates/gemini/bookmarks-feed.xml", include_str!("../templates/gemini/bookmarks-feed.xml")),
+    ("templates/gemini/bookmarks.gmi", include_str!("../templates/gemini/bookmarks.gmi")),
+    ("templates/gemini/digest-feed.xml", include_str!("../templates/gemini/digest-feed.xml")),
+    ("templates/gemini/digest.gmi", include_str!("../templates/gemini/digest.gmi")),
+    ("templates/gemini/index.gmi", include_str!("../templates/gemini/index.gmi")),
+    ("templates/gemini/links.gmi", include_str!("../templates/gemini/links.gmi")),
+    ("templates/gemini/notelist.gmi", include_str!("../templates/gemini/notelist.gmi")),
+    ("templates/gemini/page.gmi", include_str!("../templates/gemini/page.gmi")),
+    ("templates/gemini/post.gmi", include_str!("../templates/gemini/post.gmi")),
+    ("templates/gemini/postlist.gmi", include_str!("../templates/gemini/postlist.gmi")),
+    ("templates/gemini/stats.gmi", include_str!("../templates/gemini/stats.gmi")),
+    ("templates/gemini/tag.gmi", include_str!("../templates/gemini/tag.gmi")),
+    ("templates/gemini/tags.gmi", include_str!("../templates/gemini/tags.gmi")),
+    ("templates/gemini/topic.gmi", include_str!("../templates/gemini/topic.gmi")),
+    ("templates/html/about.html", include_str!("../templates/html/about.html")),
+    ("templates/html/atom-entry.xml", include_str!("../templates/html/atom-entry.xml")),
+    ("templates/html/atom-feed.xml", include_str!("../templates/html/atom-feed.xml")),
+    ("templates/html/author.html", include_str!("../templates/html/author.html")),
+    ("templates/html/authors.html", include_str!("../templates/html/authors.html")),
+    ("templates/html/bookmarks-feed.xml", include_str!("../templates/html/bookmarks-feed.xml")),
+    ("templates/html/bookmarks.html", include_str!("../templates/html/bookmarks.html")),
+    ("templates/html/digest-feed.xml", include_str!("../templates/html/digest-feed.xml")),
+    ("templates/html/digest.html", include_str!("../templates/html/digest.html")),
+    ("templates/html/index.html", include_str!("../templates/html/index.html")),
+    ("templates/html/links.html", include_str!("../templates/html/links.html")),
+    ("templates/html/notelist.html", include_str!("../templates/html/notelist.html")),
+    ("templates/html/page.html", include_str!("../templates/html/page.html")),
+    ("templates/html/post.html", include_str!("../templates/html/post.html")),
+    ("templates/html/postlist.html", include_str!("../templates/html/postlist.html")),
+    ("templates/html/print.css", include_str!("../templates/html/print.css")),
+    ("templates/html/stats.html", include_str!("../templates/html/stats.html")),
+    ("templates/html/tag.html", include_str!("../templates/html/tag.html")),
+    ("templates/html/tags.html", include_str!("../templates/html/tags.html")),
+    ("templates/html/style.css", include_str!("../templates/html/style.css")),
+    ("templates/html/themes/high-contrast.css", include_str!("../templates/html/themes/high-contrast.css")),
+    ("templates/html/themes/sans.css", include_str!("../templates/html/themes/sans.css")),
+    ("templates/html/themes/serif.css", include_str!("../templates/html/themes/serif.css")),
+    ("templates/html/themes/terminal.css", include_str!("../templates/html/themes/terminal.css")),
+    ("templates/html/topic.html", include_str!("../templates/html/topic.html")),
+];
+const DEFAULT_CONFIG: &str = include_str!("../config.toml");
+
+// Templates every build reads, per protocol.
+const CORE_TEMPLATES: [&str; 9] = ["index", "post", "topic", "postlist", "notelist", "page", "about", "atom-feed", "atom-entry"];
+
+// Keys under [site] a config can't do without.
+const SITE_KEYS: [&str; 5] = ["name", "url", "username", "html_root", "gemini_root"];
+
+// Something missing from a setup. Missing templates and config can be
+// installed from the built-in defaults, the rest has to be fixed by hand.
+enum Problem {
+    MissingConfig(PathBuf),
+    Config(String),
+    MissingTemplate(&'static str),
+    Missing(String),
+    MissingDir(PathBuf),
+}
+
+impl Problem {
+    fn describe(&self) -> String {
+        match self {
+            Problem::MissingConfig(p) => format!("No config file at {}", p.to_string_lossy()),
+            Problem::Config(e) => e.clone(),
+            Problem::MissingTemplate(t) => format!("Missing {} in ~/.local/share/crosspub", t),
+            Problem::Missing(m) => m.clone(),
+            Problem::MissingDir(d) => format!("Output directory {} doesn't exist", d.to_string_lossy()),
+        }
+    }
+
+    fn repairable(&self) -> bool {
+        matches!(self, Problem::MissingConfig(_) | Problem::MissingTemplate(_) | Problem::MissingDir(_))
+    }
+}
+
+// Check that the config, templates, CSS, content and output directories a
+// build needs are all there and print what isn't. With repair the missing
+// defaults are installed and output directories created. Returns whether
+// everything is in place afterwards.
+pub fn run(xdg_dirs: &xdg::BaseDirectories, data_dir: Option<&Path>, config_path: Option<PathBuf>, profile: Option<&str>, dir: &Path, repair: bool) -> bool {
+    let find_data_file = |path: &str| data_dir.map(|d| d.join(path)).filter(|p| p.exists())
+        .or_else(|| xdg_dirs.find_data_file(path));
+    let mut problems = Vec::new();
+    let config_path = config_path.or_else(|| xdg_dirs.find_config_file("config.toml"));
+    let config = match config_path {
+        Some(path) => check_config(&path, profile, &mut problems),
+        None => {
+            let path = xdg_dirs.get_config_home().join("config.toml");
+            problems.push(Problem::MissingConfig(path));
+            None
+        },
+    };
+
+    let config = config.unwrap_or_default();
+    for name in required_templates(&config) {
+        if find_data_file(name).is_none() {
+            problems.push(Problem::MissingTemplate(name));
+        }
+    }
+    let theme = &config.site.theme;
+    if !theme.is_empty() && find_data_file(&format!("templates/html/themes/{}.css", theme)).is_none() {
+        match TEMPLATES.iter().find(|(p, _)| *p == format!("templates/html/themes/{}.css", theme)) {
+            Some((p, _)) => problems.push(Problem::MissingTemplate(p)),
+            None => problems.push(Problem::Missing(format!("No CSS theme named {}", theme))),
+        }
+    }
+    if config.homepage.use_about_page == Some(true) && find_data_file("about.gmi").is_none() {
+        problems.push(Problem::Missing("use_about_page is on but there's no about.gmi in ~/.local/share/crosspub".to_string()));
+    }
+    for content_dir in config.content_dirs() {
+        if content_dir.kind != "page" && !dir.join(&content_dir.path).is_dir() {
+            problems.push(Problem::Missing(format!("No {}/ directory in the site directory", content_dir.path)));
+        }
+    }
+    // HTML published to a bucket doesn't need its root on disk.
+    let html_root = if config.s3.enabled == Some(true) { "" } else { config.site.html_root.as_str() };
+    for root in [html_root, &config.site.gemini_root] {
+        if !root.is_empty() && !Path::new(root).is_dir() {
+            problems.push(Problem::MissingDir(PathBuf::from(root)));
+        }
+    }
+
+    if problems.is_empty() {
+        println!("Everything crosspub needs is in place.");
+        return true;
+    }
+    let mut remaining = 0;
+    for problem in &problems {
+        if repair && problem.repairable() {
+            match fix(xdg_dirs, problem) {
+                Ok(done) => {
+                    println!("Fixed: {}, {}", problem.describe(), done);
+                    continue;
+                },
+                Err(e) => eprintln!("Error: Could not fix \"{}\": {}", problem.describe(), e),
+            }
+        } else {
+            println!("{}", problem.describe());
+        }
+        remaining += 1;
+    }
+    if remaining > 0 && !repair && problems.iter().any(Problem::repairable) {
+        println!("\nRun crosspub doctor --repair to install the missing defaults and create missing output directories.");
+    }
+    remaining == 0
+}
+
+// Read the config, reporting what's wrong with it. Returns it if it can be
+// used for the rest of the checks.
+fn check_config(path: &Path, profile: Option<&str>, problems: &mut Vec<Problem>) -> Option<Config> {
+    let contents = match fs::read_to_string(path) {
+        Ok(c) => c,
+        Err(e) => {
+            problems.push(Problem::Config(format!("Could not read {}: {}", path.to_string_lossy(), e)));
+            return None;
+        },
+    };
+    let value: toml::Value = match toml::from_str(&contents) {
+        Ok(v) => v,
+        Err(e) => {
+            problems.push(Problem::Config(format!("{} isn't valid TOML: {}", path.to_string_lossy(), e)));
+            return None;
+        },
+    };
+    let site = value.get("site");
+    let missing: Vec<&str> = SITE_KEYS.iter()
+        .filter(|k| !site.and_then(|s| s.get(**k)).map(|v| v.is_str()).unwrap_or(false))
+        .copied()
+        .collect();
+    for key in &missing {
+        problems.push(Problem::Config(format!("{} doesn't set {} under [site]", path.to_string_lossy(), key)));
+    }
+    let mut config: Config = match toml::from_str(&contents) {
+        Ok(c) => c,
+        Err(e) => {
+            if missing.is_empty() {
+                problems.push(Problem::Config(format!("{} couldn't be read as a crosspub config: {}", path.to_string_lossy(), e)));
+            }
+            return None;
+        },
+    };
+    if let Some(p) = profile {
+        if !config.apply_profile(p) {
+            problems.push(Problem::Config(format!("No profile named {} in {}", p, path.to_string_lossy())));
+        }
+    }
+    Some(config)
+}
+
+// The templates a build with this config reads.
+fn required_templates(config: &Config) -> Vec<&'static str> {
+    let mut names: Vec<String> = Vec::new();
+    for (dir, ext) in [("html", "html"), ("gemini", "gmi")] {
+        for t in CORE_TEMPLATES {
+            let ext = if t.starts_with("atom") { "xml" } else { ext };
+            names.push(format!("templates/{}/{}.{}", dir, t, ext));
+        }
+        if config.stats.enabled == Some(true) {
+            names.push(format!("templates/{}/stats.{}", dir, ext));
+        }
+        if config.link_log.enabled == Some(true) {
+            names.push(format!("templates/{}/links.{}", dir, ext));
+        }
+        if config.tags.enabled == Some(true) {
+            names.push(format!("templates/{}/tag.{}", dir, ext));
+            names.push(format!("templates/{}/tags.{}", dir, ext));
+        }
+        if config.author_pages.enabled == Some(true) {
+            names.push(format!("templates/{}/author.{}", dir, ext));
+            names.push(format!("templates/{}/authors.{}", dir, ext));
+        }
+        if config.digest.period.is_some() {
+            names.push(format!("templates/{}/digest.{}", dir, ext));
+            names.push(format!("templates/{}/digest-feed.xml", dir));
+        }
+    }
+    names.push("templates/html/style.css".to_string());
+    if config.print.enabled == Some(true) {
+        names.push("templates/html/print.css".to_string());
+    }
+    TEMPLATES.iter()
+        .map(|(p, _)| *p)
+        .filter(|p| names.iter().any(|n| n == p))
+        .collect()
+}
+
+// Install a default or create a directory, returning where.
+fn fix(xdg_dirs: &xdg::BaseDirectories, problem: &Problem) -> std::io::Result<String> {
+    match problem {
+        Problem::MissingConfig(path) => {
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::write(path, DEFAULT_CONFIG)?;
+            Ok("installed the default config, edit [site] in it before building".to_string())
+        },
+        Problem::MissingTemplate(name) => {
+            let contents = TEMPLATES.iter().find(|(p, _)| p == name).map(|(_, c)| *c).unwrap_or_default();
+            let path = xdg_dirs.place_data_file(name)?;
+            fs::write(&path, contents)?;
+            Ok(format!("installed the default at {}", path.to_string_lossy()))
+        },
+        Problem::MissingDir(path) => {
+            fs::create_dir_all(path)?;
+            Ok("created it".to_string())
+        },
+        _ => Ok(String::new()),
+    }
+}
@@ -0,0 +1,80 @@
+use serde::Serialize;
+
+use crate::config::Site;
+use crate::gemtext::GemtextToken;
+use crate::post::Post;
+use crate::topic::Topic;
+
+#[derive(Serialize)]
+pub struct Export<'a> {
+    pub site: &'a Site,
+    pub posts: Vec<ExportedPost<'a>>,
+    pub topics: Vec<ExportedTopic<'a>>,
+}
+
+#[derive(Serialize)]
+pub struct ExportedPost<'a> {
+    #[serde(flatten)]
+    pub post: &'a Post,
+    pub frontmatter: toml::Value,
+    pub tokens: &'a [GemtextToken],
+    pub html_url: String,
+    pub gemini_url: String,
+}
+
+#[derive(Serialize)]
+pub struct ExportedTopic<'a> {
+    #[serde(flatten)]
+    pub topic: &'a Topic,
+    pub frontmatter: toml::Value,
+    pub tokens: &'a [GemtextToken],
+    pub html_url: String,
+    pub gemini_url: String,
+}
+
+impl<'a> Export<'a> {
+    pub fn new(site: &'a Site, posts: &'a [Post], topics: &'a [Topic]) -> Export<'a> {
+        Export {
+            site,
+            posts: posts.iter()
+                .map(|p| ExportedPost {
+                    post: p,
+                    frontmatter: parse_frontmatter(&p.frontmatter),
+                    tokens: &p.tokens,
+                    html_url: p.url.clone(),
+                    gemini_url: p.gemini_url.clone(),
+                })
+                .collect(),
+            topics: topics.iter()
+                .map(|t| ExportedTopic {
+                    topic: t,
+                    frontmatter: parse_frontmatter(&t.frontmatter),
+                    tokens: &t.tokens,
+                    html_url: t.url.clone(),
+                    gemini_url: t.gemini_url.clone(),
+                })
+                .collect(),
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        // Everything in an Export is plain data, serializing it can't fail.
+        serde_json::to_string_pretty(self).unwrap()
+    }
+}
+
+pub fn page_url(site: &Site, scheme: &str, path: &str) -> String {
+    format!("{}://{}{}", scheme, site.url.trim_end_matches('/'), page_path(site, path))
+}
+
+// A path relative to the output roots as a path from the root of the host.
+pub fn page_path(site: &Site, path: &str) -> String {
+    format!("/~{}/{}", site.username, path)
+}
+
+fn parse_frontmatter(frontmatter: &str) -> toml::Value {
+    match frontmatter.parse::<toml::Value>() {
+        Ok(v) => v,
+        Err(_) => toml::Value::Table(toml::value::Table::new()),
+    }
+}
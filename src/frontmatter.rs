@@ -1,8 +1,145 @@
+use std::borrow::Cow;
+use std::fs;
+use std::path::Path;
+use std::process::exit;
+
+use schemars::JsonSchema;
 use serde::Deserialize;
+use serde::de::DeserializeOwned;
+
+use crate::meta::Meta;
+use crate::notify::warning;
+use crate::structured::{Recipe, Review};
 
-#[derive(Deserialize)]
+#[derive(Deserialize, JsonSchema)]
 pub struct Frontmatter {
+    pub title: Option<String>,
+    pub slug: Option<String>,
+    #[schemars(regex(pattern = r"^\d{4}-\d{2}-\d{2}( \d{2}:\d{2})?$"))]
+    pub date: String,
+    #[schemars(regex(pattern = r"^\d{4}-\d{2}-\d{2}( \d{2}:\d{2})?$"))]
+    pub updated: Option<String>,
+    pub summary: Option<String>,
+    pub author: Option<String>,
+    #[schemars(extend("enum" = ["article", "note", "link", "recipe", "review"]))]
+    pub kind: Option<String>,
+    pub url: Option<String>,
+    pub recipe: Option<Recipe>,
+    pub review: Option<Review>,
+    pub draft: Option<bool>,
+    pub featured_weight: Option<u32>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    pub lang: Option<String>,
+    pub meta: Option<Meta>,
+    pub toc: Option<bool>,
+    pub paginate_by_heading: Option<bool>,
+    pub raw_html: Option<bool>,
+    pub reflow: Option<bool>,
+    pub output_path: Option<String>,
+    #[serde(default)]
+    pub styles: Vec<String>,
+    #[serde(default)]
+    pub scripts: Vec<String>,
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub struct TopicFrontmatter {
     pub title: String,
     pub slug: String,
-    pub date: String,
+    #[schemars(regex(pattern = r"^\d{4}-\d{2}-\d{2}$"))]
+    pub date: Option<String>,
+    #[schemars(regex(pattern = r"^\d{4}-\d{2}-\d{2}$"))]
+    pub updated: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    pub toc: Option<bool>,
+    pub raw_html: Option<bool>,
+    pub reflow: Option<bool>,
+    pub output_path: Option<String>,
+    #[serde(default)]
+    pub styles: Vec<String>,
+    #[serde(default)]
+    pub scripts: Vec<String>,
+}
+
+#[derive(Default, Deserialize, JsonSchema)]
+pub struct PageFrontmatter {
+    pub title: Option<String>,
+    pub raw_html: Option<bool>,
+    pub reflow: Option<bool>,
+    pub output_path: Option<String>,
+}
+
+#[derive(Default, Deserialize, JsonSchema)]
+pub struct AboutFrontmatter {
+    pub title: Option<String>,
+    pub updated: Option<String>,
+    pub raw_html: Option<bool>,
+    pub reflow: Option<bool>,
+}
+
+// Where a page is written, relative to the output roots and without an
+// extension. An output_path from the frontmatter replaces the default, a
+// leading slash and a .html or .gmi extension are dropped so it can be written
+// like the URL.
+pub fn output_path(custom: Option<&str>, default: String) -> String {
+    match custom {
+        Some(p) => {
+            let p = p.trim_start_matches('/');
+            p.strip_suffix(".html")
+                .or_else(|| p.strip_suffix(".gmi"))
+                .unwrap_or(p)
+                .to_string()
+        },
+        None => default,
+    }
+}
+
+// The lines of a source file. Bytes that aren't valid UTF-8 are replaced with
+// U+FFFD rather than stopping the build, with a warning for each line that
+// has them.
+pub fn read_lines(source_path: &Path) -> Vec<String> {
+    let contents = match fs::read(source_path) {
+        Ok(c) => c,
+        Err(_) => {
+            eprintln!("Error: Could not open file {}", &source_path.to_string_lossy());
+            exit(1);
+        },
+    };
+    let mut raw: Vec<&[u8]> = contents.split(|&b| b == b'\n').collect();
+    if raw.last().is_some_and(|l| l.is_empty()) {
+        raw.pop();
+    }
+    raw.into_iter()
+        .enumerate()
+        .map(|(i, line)| {
+            let line = line.strip_suffix(b"\r").unwrap_or(line);
+            let text = String::from_utf8_lossy(line);
+            if let Cow::Owned(_) = text {
+                warning!("{}, line {}: not valid UTF-8, the invalid bytes are replaced",
+                    &source_path.to_string_lossy(), i + 1);
+            }
+            text.into_owned()
+        })
+        .collect()
+}
+
+// Parse the frontmatter of a source where it's optional. Returns the
+// frontmatter, or its default when there is none, and where the body starts.
+pub fn parse_optional<T: DeserializeOwned + Default>(lines: &[String], source_path: &Path) -> (T, usize) {
+    if lines.first().map(|l| l.trim()) != Some("---") {
+        return (T::default(), 0);
+    }
+    match lines.iter().skip(1).position(|l| l.trim() == "---") {
+        Some(i) => match toml::from_str(&lines[1..=i].join("\n")) {
+            Ok(fm) => (fm, i + 2),
+            Err(_) => {
+                eprintln!("Error: Frontmatter formatted incorrectly in {}",
+                    &source_path.to_string_lossy());
+                exit(1);
+            }
+        },
+        None => (T::default(), 0),
+    }
 }
@@ -0,0 +1,54 @@
+use crate::gemtext::{needs_escape, quote_depth, GemtextToken, TokenKind};
+
+// Turn a token chain back into gemtext. Anything done to the tokens after
+// parsing ends up in the Gemini output this way, not just the HTML.
+pub fn generate_gemini_from_tokens(tokens: &[GemtextToken]) -> String {
+    let mut lines: Vec<String> = Vec::new();
+    let mut in_references = false;
+    let mut in_fence = false;
+    for token in tokens {
+        // References outside a "References" section came from a ```refs
+        // block, so they go back into one.
+        let fenced = token.kind == TokenKind::Reference && !in_references;
+        if fenced != in_fence {
+            lines.push(if fenced { "```refs".to_string() } else { "```".to_string() });
+            in_fence = fenced;
+        }
+        if matches!(token.kind, TokenKind::Heading | TokenKind::SubHeading | TokenKind::SubSubHeading) {
+            in_references = token.data.trim().eq_ignore_ascii_case("references");
+        }
+        lines.push(token_as_gemini(token));
+    }
+    if in_fence {
+        lines.push("```".to_string());
+    }
+    lines.join("\n")
+}
+
+fn token_as_gemini(token: &GemtextToken) -> String {
+    match token.kind {
+        TokenKind::Heading => format!("# {}", token.data),
+        TokenKind::SubHeading => format!("## {}", token.data),
+        TokenKind::SubSubHeading => format!("### {}", token.data),
+        TokenKind::Link | TokenKind::Reference => {
+            if token.extra.is_empty() {
+                format!("=> {}", token.data)
+            } else {
+                format!("=> {} {}", token.data, token.extra)
+            }
+        },
+        TokenKind::Blockquote => format!("{} {}", ">".repeat(quote_depth(token)), token.data),
+        TokenKind::UnorderedList => format!("* {}", token.data),
+        TokenKind::OrderedList => format!("{} {}", token.extra, token.data),
+        TokenKind::PreFormattedText => {
+            if token.data.is_empty() {
+                format!("```{}\n```", token.extra)
+            } else {
+                format!("```{}\n{}\n```", token.extra, token.data)
+            }
+        },
+        TokenKind::Footnote => format!("[^{}]: {}", token.extra, token.data),
+        TokenKind::Text if needs_escape(&token.data) => format!("\\{}", token.data),
+        TokenKind::Text => token.data.clone(),
+    }
+}
@@ -1,34 +1,65 @@
-#[derive(Copy, Clone, Debug, PartialEq)]
+use std::collections::VecDeque;
+use std::fmt;
+use std::io::{self, BufRead};
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Serialize, Deserialize)]
 pub enum TokenKind {
     Text,
     Link,
     UnorderedList,
+    OrderedList,
     Blockquote,
     Heading,
     SubHeading,
     SubSubHeading,
     PreFormattedText,
+    Reference,
+    Footnote,
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Serialize, Deserialize)]
 pub struct GemtextToken {
     pub kind: TokenKind,
     pub data: String,
     pub extra: String,  // Right now this will be empty except when links are
-                        // named, when it will hold the user friendly name.
+                        // named, when it will hold the user friendly name, for
+                        // headings with an anchor, when it holds the id, for
+                        // preformatted text with alt text, for ordered list
+                        // items, when it holds the number like "1.", and for
+                        // footnotes, when it holds the label.
+    // Where in the source the token was parsed from, for pointing messages
+    // at it. Tokens made up later have an empty span.
+    #[serde(default, skip_serializing_if = "Span::is_empty")]
+    pub span: Span,
+}
+
+// The line a token starts on, counted from 1 within the body, and the raw
+// text of that line. Line 0 means the token isn't from the source.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Ord, PartialOrd, Serialize, Deserialize)]
+pub struct Span {
+    pub line: usize,
+    pub source: String,
+}
+
+impl Span {
+    pub fn is_empty(&self) -> bool {
+        self.line == 0
+    }
 }
 
 impl GemtextToken {
     pub fn as_html(&self) -> String {
         match self.kind {
             TokenKind::Heading => {
-                format!("<h1>{}</h1>\n", self.data)
+                format!("<h1{}>{}</h1>\n", self.id_attribute(), self.data)
             },
             TokenKind::SubHeading => {
-                format!("<h2>{}</h2>\n", self.data)
+                format!("<h2{}>{}</h2>\n", self.id_attribute(), self.data)
             },
             TokenKind::SubSubHeading => {
-                format!("<h3>{}</h3>\n", self.data)
+                format!("<h3{}>{}</h3>\n", self.id_attribute(), self.data)
             },
             TokenKind::Link => {
                 if self.extra.is_empty() {
@@ -41,12 +72,26 @@ impl GemtextToken {
                 format!("<blockquote><p>{}</p></blockquote>\n", self.data)
             },
             TokenKind::PreFormattedText => {
-                println!("<pre>{}</pre>\n", self.data.replace("\n", "<br>"));
-                String::new()
+                format!("<pre{}>{}</pre>\n", self.lang_attributes(), self.data.replace('\n', "<br>"))
+            },
+            TokenKind::UnorderedList => match task_item(&self.data) {
+                Some((done, rest)) => format!("<li class=\"task\"><input type=\"checkbox\" disabled{}> {}</li>\n",
+                    if done { " checked" } else { "" }, rest),
+                None => format!("<li>{}</li>\n", self.data),
             },
-            TokenKind::UnorderedList => {
+            TokenKind::OrderedList => {
                 format!("<li>{}</li>\n", self.data)
             }
+            TokenKind::Reference => {
+                if self.extra.is_empty() {
+                    format!("<li><a href=\"{}\">{}</a></li>\n", self.data, self.data)
+                } else {
+                    format!("<li><a href=\"{}\">{}</a></li>\n", self.data, self.extra)
+                }
+            }
+            TokenKind::Footnote => {
+                format!("<li id=\"fn-{}\">{}</li>\n", self.extra, self.data)
+            }
             TokenKind::Text => {
                 if !self.data.is_empty() {
                     format!("<p>{}</p>\n", self.data)
@@ -56,106 +101,641 @@ impl GemtextToken {
             }
         }
     }
+
+    // The first word of a preformatted block's alt text is usually the
+    // language of the code in it, exposed for CSS and syntax highlighters.
+    fn lang_attributes(&self) -> String {
+        let lang: String = self.extra.split_whitespace()
+            .next()
+            .unwrap_or("")
+            .chars()
+            .filter(|c| c.is_alphanumeric() || matches!(c, '-' | '_' | '+' | '.'))
+            .collect();
+        if lang.is_empty() {
+            String::new()
+        } else {
+            format!(" class=\"language-{0}\" data-lang=\"{0}\"", lang)
+        }
+    }
+
+    fn id_attribute(&self) -> String {
+        if self.extra.is_empty() {
+            String::new()
+        } else {
+            format!(" id=\"{}\"", self.extra)
+        }
+    }
+}
+
+// A problem found while parsing that didn't stop the parse. line counts
+// from 1 at the first line passed to the parser.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct Diagnostic {
+    pub line: usize,
+    pub message: String,
+}
+
+#[derive(Debug)]
+pub enum ParseErrorKind {
+    Io(io::Error),
+    LinkWithoutUrl,
+    ReferenceWithoutUrl,
+    UnclosedPreformatted,
+    UnclosedReferences,
+    EmptyHeading,
+}
+
+impl fmt::Display for ParseErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseErrorKind::Io(e) => write!(f, "could not read line: {}", e),
+            ParseErrorKind::LinkWithoutUrl => write!(f, "link without a URL, kept as text"),
+            ParseErrorKind::ReferenceWithoutUrl => write!(f, "reference without a URL"),
+            ParseErrorKind::UnclosedPreformatted => write!(f, "preformatted block is never closed"),
+            ParseErrorKind::UnclosedReferences => write!(f, "references block is never closed"),
+            ParseErrorKind::EmptyHeading => write!(f, "heading without text"),
+        }
+    }
+}
+
+// An error from Parser. line counts from 1, for blocks that are never closed
+// it's the line that opened them.
+#[derive(Debug)]
+pub struct ParseError {
+    pub line: usize,
+    pub kind: ParseErrorKind,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.kind)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+// A gemtext parser reading one line at a time. Every problem is given as an
+// error in place, followed by whatever could be kept of the line, so the
+// tokens stay usable when the errors are skipped. Only a failure to read
+// ends the parse early.
+pub struct Parser<I> {
+    lines: I,
+    line_number: usize,
+    // The line being parsed, and the opening fence of an open block.
+    current: Span,
+    fence: Span,
+    pft: bool,
+    refs: bool,
+    pft_lines: Vec<String>,
+    pft_alt_text: String,
+    // Links in a section headed "References" are references rather than
+    // plain links. The section ends at the next heading.
+    in_references: bool,
+    pending: VecDeque<Result<GemtextToken, ParseError>>,
+    finished: bool,
+    // Strict parsing also reports lines that are valid but most likely
+    // mistakes, like headings without text.
+    strict: bool,
+}
+
+impl<R: BufRead> Parser<io::Lines<R>> {
+    pub fn new(reader: R) -> Self {
+        Parser::from_lines(reader.lines())
+    }
+}
+
+impl<I: Iterator<Item = io::Result<String>>> Parser<I> {
+    pub fn from_lines(lines: I) -> Self {
+        Parser {
+            lines,
+            line_number: 0,
+            current: Span::default(),
+            fence: Span::default(),
+            pft: false,
+            refs: false,
+            pft_lines: Vec::new(),
+            pft_alt_text: String::new(),
+            in_references: false,
+            pending: VecDeque::new(),
+            finished: false,
+            strict: false,
+        }
+    }
+
+    pub fn strict(mut self) -> Self {
+        self.strict = true;
+        self
+    }
+
+    fn token(&mut self, kind: TokenKind, data: &str, extra: &str) {
+        let kind = match kind {
+            TokenKind::Heading | TokenKind::SubHeading | TokenKind::SubSubHeading => {
+                self.in_references = data.trim().eq_ignore_ascii_case("references");
+                kind
+            },
+            TokenKind::Link if self.in_references => TokenKind::Reference,
+            _ => kind,
+        };
+        // Preformatted text is placed at its opening fence.
+        let span = match kind {
+            TokenKind::PreFormattedText => self.fence.clone(),
+            _ => self.current.clone(),
+        };
+        self.pending.push_back(Ok(GemtextToken {
+            kind,
+            data: data.to_owned(),
+            extra: extra.to_owned(),
+            span,
+        }));
+    }
+
+    fn error(&mut self, line: usize, kind: ParseErrorKind) {
+        self.pending.push_back(Err(ParseError { line, kind }));
+    }
+
+    fn parse_line(&mut self, line: &str) {
+        // Fenced reference blocks hold a plain list of links.
+        if self.refs {
+            if line.starts_with("```") {
+                self.refs = false;
+            } else if let Some(link) = line.strip_prefix("=>") {
+                let token = reference_token(link, self.current.clone());
+                if token.data.is_empty() {
+                    self.error(self.line_number, ParseErrorKind::ReferenceWithoutUrl);
+                }
+                self.pending.push_back(Ok(token));
+            } else if !line.trim().is_empty() {
+                self.token(TokenKind::Text, line, "");
+            }
+            return;
+        }
+
+        if self.pft {
+            if line.starts_with("```") {
+                self.pft = false;
+                let pft_joined = self.pft_lines.join("\n");
+                self.pft_lines.clear();
+                let alt = std::mem::take(&mut self.pft_alt_text);
+                self.token(TokenKind::PreFormattedText, &pft_joined, &alt);
+            } else {
+                self.pft_lines.push(line.to_owned());
+            }
+            return;
+        }
+
+        if let Some(text) = unescape_line(line) {
+            self.token(TokenKind::Text, text, "");
+            return;
+        }
+        if is_refs_fence(line) {
+            self.refs = true;
+            self.fence = self.current.clone();
+            return;
+        }
+        if let Some(alt) = line.strip_prefix("```") {
+            self.pft = true;
+            self.pft_alt_text = alt.trim().to_owned();
+            self.fence = self.current.clone();
+            return;
+        }
+
+        // Line types are a marker followed by a space, anything else is text.
+        let (marker, rest) = line.split_once(' ').unwrap_or((line, ""));
+        let mode = match marker {
+            "=>"  => TokenKind::Link,
+            "*"   => TokenKind::UnorderedList,
+            ">"   => TokenKind::Blockquote,
+            "###" => TokenKind::SubSubHeading,
+            "##"  => TokenKind::SubHeading,
+            "#"   => TokenKind::Heading,
+            // Nested quotes, their marker is kept as the extra.
+            m if m.len() > 1 && m.chars().all(|c| c == '>') => {
+                self.token(TokenKind::Blockquote, rest, marker);
+                return;
+            },
+            _     => TokenKind::Text,
+        };
+
+        match mode {
+            TokenKind::Text => match footnote_definition(line) {
+                Some((label, text)) => self.token(TokenKind::Footnote, text, label),
+                None => self.token(mode, line, ""),
+            },
+            TokenKind::Link => {
+                let (url, name) = rest.split_once(' ').unwrap_or((rest, ""));
+                if url.is_empty() {
+                    self.error(self.line_number, ParseErrorKind::LinkWithoutUrl);
+                    self.token(TokenKind::Text, line, "");
+                } else {
+                    self.token(mode, url, name);
+                }
+            },
+            TokenKind::Heading | TokenKind::SubHeading | TokenKind::SubSubHeading => {
+                if self.strict && rest.trim().is_empty() {
+                    self.error(self.line_number, ParseErrorKind::EmptyHeading);
+                }
+                self.token(mode, rest, "");
+            },
+            _ => self.token(mode, rest, ""),
+        }
+    }
+
+    // Close blocks left open at the end of the input.
+    fn finish(&mut self) {
+        if self.pft {
+            self.error(self.fence.line, ParseErrorKind::UnclosedPreformatted);
+            let pft_joined = self.pft_lines.join("\n");
+            let alt = std::mem::take(&mut self.pft_alt_text);
+            self.token(TokenKind::PreFormattedText, &pft_joined, &alt);
+        }
+        if self.refs {
+            self.error(self.fence.line, ParseErrorKind::UnclosedReferences);
+        }
+    }
+}
+
+impl<I: Iterator<Item = io::Result<String>>> Iterator for Parser<I> {
+    type Item = Result<GemtextToken, ParseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(item) = self.pending.pop_front() {
+                return Some(item);
+            }
+            if self.finished {
+                return None;
+            }
+            match self.lines.next() {
+                Some(Ok(line)) => {
+                    self.line_number += 1;
+                    self.current = Span { line: self.line_number, source: line.clone() };
+                    self.parse_line(&line);
+                },
+                Some(Err(e)) => {
+                    self.finished = true;
+                    return Some(Err(ParseError {
+                        line: self.line_number + 1,
+                        kind: ParseErrorKind::Io(e),
+                    }));
+                },
+                None => {
+                    self.finished = true;
+                    self.finish();
+                },
+            }
+        }
+    }
 }
 
 // Take in a string of gemtext and convert it into a vector of GemtextTokens
 // with a kind and data.
 pub fn parse_gemtext(lines: &[String]) -> Vec<GemtextToken> {
-    let mut gemtext_token_chain = Vec::new();
-    let mut current_pft_state: bool = false;
-    let mut pft_lines: Vec<String> = Vec::new();
-    let mut _pft_alt_text: &str = "";
+    parse_gemtext_lossy(lines).0
+}
+
+// Parse gemtext without ever panicking, whatever the input. Lines that can't
+// be parsed as intended are kept as best as possible and reported in the
+// returned diagnostics.
+pub fn parse_gemtext_lossy(lines: &[String]) -> (Vec<GemtextToken>, Vec<Diagnostic>) {
+    let mut tokens = Vec::new();
+    let mut diagnostics = Vec::new();
+    for item in Parser::from_lines(lines.iter().cloned().map(Ok)) {
+        match item {
+            Ok(token) => tokens.push(token),
+            Err(e) => diagnostics.push(Diagnostic {
+                line: e.line,
+                message: e.kind.to_string(),
+            }),
+        }
+    }
+    (tokens, diagnostics)
+}
+
+fn is_refs_fence(line: &str) -> bool {
+    match line.strip_prefix("```") {
+        Some(alt) => alt.trim() == "refs",
+        None => false,
+    }
+}
+
+fn reference_token(link: &str, span: Span) -> GemtextToken {
+    let mut parts = link.trim().splitn(2, char::is_whitespace);
+    GemtextToken {
+        kind: TokenKind::Reference,
+        data: parts.next().unwrap_or("").to_owned(),
+        extra: parts.next().unwrap_or("").trim().to_owned(),
+        span,
+    }
+}
+
+// Turn text lines that start with a number like "1." or "2)" into ordered
+// list items. Gemtext has no such lines, so this is an extension sites opt
+// into.
+pub fn number_lists(tokens: &[GemtextToken]) -> Vec<GemtextToken> {
+    tokens.iter()
+        .map(|t| match list_number(&t.data) {
+            Some((number, rest)) if t.kind == TokenKind::Text => GemtextToken {
+                kind: TokenKind::OrderedList,
+                data: rest.to_owned(),
+                extra: number.to_owned(),
+                span: t.span.clone(),
+            },
+            _ => t.clone(),
+        })
+        .collect()
+}
+
+// Merge runs of text lines into single lines, for sources hard-wrapped at a
+// fixed width where each line is part of a longer paragraph. Blank lines and
+// anything that isn't text end a paragraph. Merged lines keep the span of
+// their first line.
+pub fn reflow_paragraphs(tokens: &[GemtextToken]) -> Vec<GemtextToken> {
+    let mut reflowed: Vec<GemtextToken> = Vec::new();
+    let mut in_paragraph = false;
+    for t in tokens {
+        let is_text = t.kind == TokenKind::Text && !t.data.trim().is_empty();
+        match reflowed.last_mut() {
+            Some(last) if is_text && in_paragraph => {
+                last.data = format!("{} {}", last.data.trim_end(), t.data.trim());
+            },
+            _ => reflowed.push(t.clone()),
+        }
+        in_paragraph = is_text;
+    }
+    reflowed
+}
+
+// The number of an ordered list item with its "." or ")", and the rest of the
+// line.
+fn list_number(line: &str) -> Option<(&str, &str)> {
+    let (number, rest) = line.split_once(' ')?;
+    let digits = number.strip_suffix('.').or_else(|| number.strip_suffix(')'))?;
+    let is_number = !digits.is_empty() && digits.len() <= 9
+        && digits.chars().all(|c| c.is_ascii_digit());
+    if is_number && !rest.trim().is_empty() {
+        Some((number, rest))
+    } else {
+        None
+    }
+}
+
+// The number an ordered list item was written with.
+pub fn list_item_number(token: &GemtextToken) -> usize {
+    token.extra.trim_end_matches(['.', ')']).parse().unwrap_or(1)
+}
+
+// A task list item like "[ ] Water the plants" or "[x] Buy soil", as
+// whether it's done and the rest of the item.
+pub fn task_item(data: &str) -> Option<(bool, &str)> {
+    let (marker, rest) = data.split_at_checked(3)?;
+    let done = match marker {
+        "[ ]" => false,
+        "[x]" | "[X]" => true,
+        _ => return None,
+    };
+    match rest.strip_prefix(' ') {
+        Some(rest) if !rest.trim().is_empty() => Some((done, rest)),
+        _ => None,
+    }
+}
+
+// A definition list item like "Gemtext :: The markup of Gemini", as the
+// term and its definition.
+pub fn definition_item(data: &str) -> Option<(&str, &str)> {
+    let (term, definition) = data.split_once(" :: ")?;
+    let (term, definition) = (term.trim(), definition.trim());
+    if term.is_empty() || definition.is_empty() {
+        None
+    } else {
+        Some((term, definition))
+    }
+}
+
+// How deep a quote line is nested, 1 for "> ", 2 for ">> " and so on.
+pub fn quote_depth(token: &GemtextToken) -> usize {
+    token.extra.len().max(1)
+}
+
+// A footnote definition like "[^1]: The text", as its label and text.
+pub fn footnote_definition(line: &str) -> Option<(&str, &str)> {
+    let (label, text) = line.strip_prefix("[^")?.split_once("]:")?;
+    if is_footnote_label(label) {
+        Some((label, text.trim()))
+    } else {
+        None
+    }
+}
+
+fn is_footnote_label(label: &str) -> bool {
+    !label.is_empty() && label.chars().all(|c| c.is_alphanumeric() || c == '-' || c == '_')
+}
 
+// Replace every footnote marker like [^1] in text with what replace returns
+// for its label. Markers it returns None for are left alone.
+pub fn replace_footnote_markers(text: &str, mut replace: impl FnMut(&str) -> Option<String>) -> String {
+    let mut replaced = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find("[^") {
+        replaced.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+        let marker = after.split_once(']')
+            .filter(|(label, _)| is_footnote_label(label))
+            .and_then(|(label, _)| replace(label).map(|r| (label.len(), r)));
+        match marker {
+            Some((length, r)) => {
+                replaced.push_str(&r);
+                rest = &after[length + 1..];
+            },
+            None => {
+                replaced.push_str("[^");
+                rest = after;
+            },
+        }
+    }
+    replaced.push_str(rest);
+    replaced
+}
+
+// Gemini has no links within a page, so footnote definitions are moved to a
+// numbered list at the end and their markers become the number, like [1].
+pub fn gemini_footnotes(lines: &[String]) -> Vec<String> {
+    let mut body = Vec::new();
+    let mut notes: Vec<(String, String)> = Vec::new();
+    let mut in_pft = false;
     for line in lines {
-        let mut mode: TokenKind;
-        let text_tokens: Vec<&str> = line.splitn(3, ' ').collect();
-
-        if !current_pft_state {
-            match text_tokens[0] {
-                "=>"  => { mode = TokenKind::Link; },
-                "*"   => { mode = TokenKind::UnorderedList; },
-                ">"   => { mode = TokenKind::Blockquote; },
-                "###" => { mode = TokenKind::SubSubHeading; },
-                "##"  => { mode = TokenKind::SubHeading; },
-                "#"   => { mode = TokenKind::Heading; },
-                _     => {
-                    mode = TokenKind::Text;
-                },
-            }
-            if text_tokens[0].starts_with("```") {
-                mode = TokenKind::PreFormattedText;
-            }
-
-            match text_tokens.len() {
-                3 => {
-                    if mode == TokenKind::Link {
-                        gemtext_token_chain.push(GemtextToken {
-                            kind: mode,
-                            data: text_tokens[1].to_owned(),
-                            extra: text_tokens[2].to_owned(),
-                        });
-                    } else if mode == TokenKind::Text {
-                        // Combine [0], [1], and [2] since Text doesn't have a
-                        // leading symbol.
-                        gemtext_token_chain.push(GemtextToken {
-                            kind: mode,
-                            data: format!("{} {} {}",
-                                text_tokens[0],
-                                text_tokens[1],
-                                text_tokens[2]),
-                                extra: "".to_owned(),
-                        });
-                    } else {
-                        // Combine [1] and [2] in other parse modes.
-                        gemtext_token_chain.push(GemtextToken {
-                            kind: mode,
-                            data: format!("{} {}",
-                                text_tokens[1],
-                                text_tokens[2]),
-                                extra: "".to_owned(),
-                        });
-                    }
-                },
-                2 => {
-                    if mode == TokenKind::PreFormattedText && !current_pft_state {
-                        current_pft_state = true;
-                        _pft_alt_text = text_tokens[1];
-                    }
-                    else {
-                        gemtext_token_chain.push(GemtextToken {
-                            kind: mode,
-                            data: text_tokens[1].to_owned(),
-                            extra: "".to_owned(),
-                        });
-                    }
-                },
-                _ => {
-                    if mode == TokenKind::PreFormattedText && !current_pft_state {
-                        current_pft_state = true;
-                    } else {
-                        gemtext_token_chain.push(GemtextToken {
-                            kind: mode,
-                            data: text_tokens[0].to_owned(),
-                            extra: "".to_owned(),
-                        });
-                    }
+        if line.starts_with("```") {
+            in_pft = !in_pft;
+        } else if !in_pft {
+            if let Some((label, text)) = footnote_definition(line) {
+                if !notes.iter().any(|(l, _)| l == label) {
+                    notes.push((label.to_owned(), text.to_owned()));
                 }
+                continue;
             }
-        } else {
-            if text_tokens[0].starts_with("```") {
-                current_pft_state = false;
-                let pft_joined = pft_lines.join("\n");
-                // TODO: Support PFT alt text.
-                gemtext_token_chain.push(GemtextToken {
-                    kind: TokenKind::PreFormattedText,
-                    data: pft_joined,
-                    extra: "".to_owned(),
-                });
+        }
+        body.push(line.clone());
+    }
+    if notes.is_empty() {
+        return body;
+    }
+
+    let number = |label: &str| notes.iter().position(|(l, _)| l == label).map(|i| i + 1);
+    let mut in_pft = false;
+    let mut numbered: Vec<String> = body.iter()
+        .map(|line| {
+            if line.starts_with("```") {
+                in_pft = !in_pft;
+            }
+            if in_pft {
+                line.clone()
+            } else {
+                replace_footnote_markers(line, |label| number(label).map(|n| format!("[{}]", n)))
+            }
+        })
+        .collect();
+    while numbered.last().map(|l| l.trim().is_empty()).unwrap_or(false) {
+        numbered.pop();
+    }
+    numbered.push(String::new());
+    numbered.push("## Footnotes".to_owned());
+    for (i, (_, text)) in notes.iter().enumerate() {
+        numbered.push(format!("[{}] {}", i + 1, text));
+    }
+    numbered
+}
+
+// A backslash at the start of a line keeps what follows from being read as a
+// heading, list, quote, link, fence, footnote or comment, so "\# 1 reason" is
+// text. Returns the line without the backslash if it's escaped.
+pub fn unescape_line(line: &str) -> Option<&str> {
+    let rest = line.strip_prefix('\\')?;
+    if rest.starts_with(['#', '*', '>', '=', '`', '[', '\\']) || rest.starts_with("%%") {
+        Some(rest)
+    } else {
+        None
+    }
+}
+
+// Whether a text line has to be escaped to stay text when it's parsed again.
+pub fn needs_escape(text: &str) -> bool {
+    let (marker, _) = text.split_once(' ').unwrap_or((text, ""));
+    matches!(marker, "=>" | "*" | ">" | "#" | "##" | "###")
+        || text.starts_with("```")
+        || text.starts_with("%%")
+        || footnote_definition(text).is_some()
+        || unescape_line(text).is_some()
+}
+
+// The lines of a body as they're published in the Gemini output.
+pub fn gemini_body(lines: &[String]) -> Vec<String> {
+    let lines = gemini_footnotes(&unfence_references(&select_output(lines, "gemini")));
+    // Gemtext has no escapes, a zero width space in front of an escaped line
+    // keeps it text without showing anything.
+    let mut in_pft = false;
+    lines.into_iter()
+        .map(|line| {
+            if line.starts_with("```") {
+                in_pft = !in_pft;
+                return line;
+            }
+            if let Some((done, rest)) = line.strip_prefix("* ").filter(|_| !in_pft).and_then(task_item) {
+                return format!("* {} {}", if done { '\u{2611}' } else { '\u{2610}' }, rest);
+            }
+            if let Some((term, definition)) = line.strip_prefix("* ").filter(|_| !in_pft).and_then(definition_item) {
+                return format!("* {}: {}", term, definition);
+            }
+            match unescape_line(&line) {
+                Some(rest) if !in_pft && rest.starts_with('\\') => rest.to_owned(),
+                Some(rest) if !in_pft => format!("\u{200B}{}", rest),
+                _ => line,
+            }
+        })
+        .collect()
+}
+
+// Remove the fences around reference blocks so Gemini clients show them as
+// a regular list of links instead of preformatted text.
+pub fn unfence_references(lines: &[String]) -> Vec<String> {
+    let mut unfenced = Vec::new();
+    let mut in_refs = false;
+    let mut in_pft = false;
+
+    for line in lines {
+        if in_refs {
+            if line.starts_with("```") {
+                in_refs = false;
             } else {
-                pft_lines.push(line.clone());
+                unfenced.push(line.clone());
+            }
+        } else if !in_pft && is_refs_fence(line) {
+            in_refs = true;
+        } else {
+            if line.starts_with("```") {
+                in_pft = !in_pft;
+            }
+            unfenced.push(line.clone());
+        }
+    }
+
+    unfenced
+}
+
+// The frontmatter between the two --- lines a source starts with, and the
+// body after it. Sources without frontmatter are all body.
+pub fn split_frontmatter(lines: &[String]) -> (&[String], &[String]) {
+    match lines.iter().skip(1).position(|l| l.trim() == "---") {
+        Some(i) if lines[0].trim() == "---" => (&lines[1..i + 1], &lines[i + 2..]),
+        _ => (&[], lines),
+    }
+}
+
+// Keep only the lines meant for the given output target ("html" or "gemini").
+// Lines between <!--html-only--> and <!--end--> are dropped from Gemini output
+// and lines between <!--gemini-only--> and <!--end--> from HTML output.
+// Comments are dropped from both.
+pub fn select_output(lines: &[String], target: &str) -> Vec<String> {
+    let mut selected = Vec::new();
+    let mut only: Option<&str> = None;
+
+    for line in &strip_comments(lines) {
+        match line.trim() {
+            "<!--html-only-->" => only = Some("html"),
+            "<!--gemini-only-->" => only = Some("gemini"),
+            "<!--end-->" if only.is_some() => only = None,
+            _ => {
+                if only.is_none() || only == Some(target) {
+                    selected.push(line.clone());
+                }
+            },
+        }
+    }
+
+    selected
+}
+
+// Lines starting with %% and blocks fenced with ```comment are notes for the
+// author, left out of the outputs. In preformatted text they're kept.
+pub fn strip_comments(lines: &[String]) -> Vec<String> {
+    let mut kept = Vec::new();
+    let mut in_pft = false;
+    let mut in_comment = false;
+
+    for line in lines {
+        if in_comment {
+            in_comment = !line.starts_with("```");
+            continue;
+        }
+        if let Some(alt) = line.strip_prefix("```") {
+            if !in_pft && alt.trim() == "comment" {
+                in_comment = true;
+                continue;
             }
+            in_pft = !in_pft;
+        } else if !in_pft && line.starts_with("%%") {
+            continue;
         }
+        kept.push(line.clone());
     }
 
-    gemtext_token_chain
+    kept
 }
@@ -0,0 +1,116 @@
+use crate::gemtext::{GemtextToken, TokenKind};
+
+#[derive(Clone, Debug)]
+pub struct Term {
+    pub name: String,
+    pub anchor: String,
+}
+
+// Every ## heading in the glossary topic defines a term.
+pub fn terms(tokens: &[GemtextToken]) -> Vec<Term> {
+    tokens.iter()
+        .filter(|t| t.kind == TokenKind::SubHeading && !t.data.trim().is_empty())
+        .map(|t| Term {
+            name: t.data.trim().to_owned(),
+            anchor: term_anchor(t.data.trim()),
+        })
+        .collect()
+}
+
+// Give the term headings in the glossary itself ids so they can be linked to.
+pub fn anchor_terms(tokens: &[GemtextToken]) -> Vec<GemtextToken> {
+    tokens.iter()
+        .map(|t| {
+            if t.kind == TokenKind::SubHeading && !t.data.trim().is_empty() {
+                GemtextToken {
+                    extra: term_anchor(t.data.trim()),
+                    ..t.clone()
+                }
+            } else {
+                t.clone()
+            }
+        })
+        .collect()
+}
+
+// Link the first occurrence of each term in the text tokens of a page to its
+// definition in the glossary at glossary_url.
+pub fn link_terms(tokens: &[GemtextToken], terms: &[Term], glossary_url: &str) -> Vec<GemtextToken> {
+    let mut tokens = tokens.to_vec();
+
+    // Longer terms go first so "Gemini protocol" wins over "Gemini".
+    let mut terms: Vec<&Term> = terms.iter().collect();
+    terms.sort_by_key(|t| std::cmp::Reverse(t.name.len()));
+
+    for term in terms {
+        for token in tokens.iter_mut().filter(|t| t.kind == TokenKind::Text) {
+            if let Some(start) = find_term(&token.data, &term.name) {
+                let end = start + term.name.len();
+                token.data = format!("{}<a class=\"glossary\" href=\"{}#{}\">{}</a>{}",
+                    &token.data[..start],
+                    glossary_url,
+                    term.anchor,
+                    &token.data[start..end],
+                    &token.data[end..]);
+                break;
+            }
+        }
+    }
+
+    tokens
+}
+
+fn term_anchor(name: &str) -> String {
+    let mut anchor = String::from("term-");
+    let mut last_dash = true;
+    for c in name.chars() {
+        if c.is_alphanumeric() {
+            anchor.extend(c.to_lowercase());
+            last_dash = false;
+        } else if !last_dash {
+            anchor.push('-');
+            last_dash = true;
+        }
+    }
+    anchor.trim_end_matches('-').to_owned()
+}
+
+// Case insensitive search for a whole word occurrence of term, skipping over
+// HTML tags and the text of existing links.
+fn find_term(text: &str, term: &str) -> Option<usize> {
+    let bytes = text.as_bytes();
+    let len = term.len();
+    let mut in_tag = false;
+    let mut in_link = false;
+
+    for (i, c) in text.char_indices() {
+        match c {
+            '<' => {
+                in_tag = true;
+                if text[i..].starts_with("<a ") || text[i..].starts_with("<a>") {
+                    in_link = true;
+                } else if text[i..].starts_with("</a>") {
+                    in_link = false;
+                }
+            },
+            '>' => {
+                in_tag = false;
+                continue;
+            },
+            _ => {},
+        }
+        if in_tag || in_link || i + len > text.len() || !text.is_char_boundary(i + len) {
+            continue;
+        }
+        if !text[i..i + len].eq_ignore_ascii_case(term) {
+            continue;
+        }
+        let before_ok = i == 0 || !(bytes[i - 1] as char).is_alphanumeric();
+        let after_ok = i + len == text.len() || !(bytes[i + len] as char).is_alphanumeric();
+        if before_ok && after_ok {
+            return Some(i);
+        }
+    }
+
+    None
+}
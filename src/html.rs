@@ -0,0 +1,574 @@
+use serde::Deserialize;
+
+use crate::gemtext::{definition_item, list_item_number, number_lists, quote_depth, reflow_paragraphs, replace_footnote_markers, GemtextToken, TokenKind};
+use crate::inline;
+use crate::toc::anchor_headings;
+use crate::typography::smarten_tokens;
+
+// Links to files with these extensions are images.
+const IMAGE_EXTENSIONS: [&str; 7] = ["png", "jpg", "jpeg", "gif", "webp", "svg", "avif"];
+
+// How gemtext is turned into HTML. raw_html is for sources that embed HTML on
+// purpose, rewrite_links points relative links to .gmi files at the HTML
+// version of the page instead. inline_images shows links to images as the
+// image, with the link name as a caption when figures is set. ordered_lists
+// turns lines like "1. First" into ordered lists and inline_formatting
+// renders *bold*, _italic_ and `code` in text. heading_ids gives every
+// heading an id made from its text, and heading_links adds a visible link to
+// it. gemini:// and gopher:// links go through gemini_proxy and gopher_proxy
+// when they're set, and scheme_badges labels them with their protocol. Links
+// to hosts other than site_host get external_rel and external_target.
+// smart_typography curls quotes and turns -- and ... into dashes and ellipses.
+// reflow merges consecutive text lines into one paragraph, and lede gives the
+// first paragraph a class for themes to style. sections wraps every heading
+// and what follows it up to the next heading of its level or higher in a
+// <section>. Read from JSON,
+// options left out are what they are for a config that doesn't set them.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Ord, PartialOrd, Deserialize)]
+#[serde(default)]
+pub struct HtmlOptions {
+    pub raw_html: bool,
+    #[serde(default = "enabled")]
+    pub rewrite_links: bool,
+    pub inline_images: bool,
+    pub figures: bool,
+    pub ordered_lists: bool,
+    pub inline_formatting: bool,
+    #[serde(default = "enabled")]
+    pub heading_ids: bool,
+    pub heading_links: bool,
+    pub gemini_proxy: Option<String>,
+    pub gopher_proxy: Option<String>,
+    pub scheme_badges: bool,
+    pub site_host: String,
+    pub external_rel: Option<String>,
+    pub external_target: Option<String>,
+    pub smart_typography: bool,
+    pub reflow: bool,
+    pub lede: bool,
+    pub sections: bool,
+}
+
+fn enabled() -> bool {
+    true
+}
+
+// Render a full token chain to HTML.
+pub fn generate_html_from_tokens(tokens: &[GemtextToken], options: &HtmlOptions) -> String {
+    render_tokens(&prepare_tokens(tokens, options), options)
+}
+
+// Rewrite links and escape the text of a token chain for HTML, unless it's
+// raw HTML. Markup can be added to the prepared tokens before they're
+// rendered.
+pub fn prepare_tokens(tokens: &[GemtextToken], options: &HtmlOptions) -> Vec<GemtextToken> {
+    let tokens: Vec<GemtextToken> = if options.heading_ids {
+        anchor_headings(tokens)
+    } else {
+        tokens.to_vec()
+    };
+    let tokens: Vec<GemtextToken> = if options.ordered_lists {
+        number_lists(&tokens)
+    } else {
+        tokens
+    };
+    let tokens: Vec<GemtextToken> = if options.rewrite_links {
+        tokens.iter()
+            .map(|t| match t.kind {
+                TokenKind::Link | TokenKind::Reference => GemtextToken {
+                    data: html_link(&t.data),
+                    ..t.clone()
+                },
+                _ => t.clone(),
+            })
+            .collect()
+    } else {
+        tokens
+    };
+    if options.raw_html {
+        return tokens;
+    }
+    let tokens: Vec<GemtextToken> = if options.reflow {
+        reflow_paragraphs(&tokens)
+    } else {
+        tokens
+    };
+    let tokens: Vec<GemtextToken> = if options.smart_typography {
+        smarten_tokens(&tokens)
+    } else {
+        tokens
+    };
+    let tokens: Vec<GemtextToken> = tokens.iter()
+        .map(|t| match t.kind {
+            // Heading extras are ids, which are already safe, and quote
+            // extras are the markers of nested quotes.
+            TokenKind::Heading | TokenKind::SubHeading | TokenKind::SubSubHeading
+                | TokenKind::Blockquote => GemtextToken {
+                data: escape_text(&t.data),
+                ..t.clone()
+            },
+            _ => GemtextToken {
+                data: escape_text(&t.data),
+                extra: escape_text(&t.extra),
+                ..t.clone()
+            },
+        })
+        .collect();
+    if !options.inline_formatting {
+        return tokens;
+    }
+    tokens.iter()
+        .map(|t| match t.kind {
+            TokenKind::Text | TokenKind::UnorderedList | TokenKind::OrderedList | TokenKind::Blockquote
+                | TokenKind::Footnote => GemtextToken {
+                data: inline::format_html(&t.data),
+                ..t.clone()
+            },
+            _ => t.clone(),
+        })
+        .collect()
+}
+
+// The HTML counterpart of a relative link to a .gmi file. Anything else,
+// including links with a scheme, is left alone.
+pub fn html_link(url: &str) -> String {
+    let path_end = url.find(['?', '#']).unwrap_or(url.len());
+    let (path, rest) = url.split_at(path_end);
+    let has_scheme = path.split('/').next().map(|s| s.contains(':')).unwrap_or(false);
+    if has_scheme || path.starts_with("//") {
+        return url.to_string();
+    }
+    match path.strip_suffix(".gmi") {
+        Some(stem) => format!("{}.html{}", stem, rest),
+        None => url.to_string(),
+    }
+}
+
+// Whether a link points at an image, going by the extension of its path.
+pub fn is_image_link(url: &str) -> bool {
+    let path_end = url.find(['?', '#']).unwrap_or(url.len());
+    match url[..path_end].rsplit_once('.') {
+        Some((_, ext)) => IMAGE_EXTENSIONS.contains(&ext.to_ascii_lowercase().as_str()),
+        None => false,
+    }
+}
+
+pub fn escape_text(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+// Render prepared tokens. Unlike calling as_html on each token this keeps
+// track of the surrounding tokens, which is needed for anything that spans
+// more than one line.
+pub fn render_tokens(tokens: &[GemtextToken], options: &HtmlOptions) -> String {
+    let reference_count = tokens.iter()
+        .filter(|t| t.kind == TokenKind::Reference)
+        .count();
+    let mut cited = vec![false; reference_count + 1];
+    let mut html = String::new();
+    // The kind of list currently open, list items and references are each
+    // wrapped in one, and so are the lines of a quote.
+    let mut open_list: Option<TokenKind> = None;
+    // Whether a paragraph of the open quote is waiting for more lines.
+    let mut in_quote_paragraph = false;
+    // Whether the open list is a definition list.
+    let mut in_definitions = false;
+    // How many quotes the open quote is nested in, counting itself.
+    let mut quote_level: usize = 0;
+    // Whether the first paragraph is still to come and gets the lede class.
+    let mut lede = options.lede;
+    // The levels of the headings of the open sections, outermost first.
+    let mut sections: Vec<usize> = Vec::new();
+    let mut reference_number = 0;
+
+    // Footnotes are numbered in the order they're defined, the first
+    // definition of a label wins.
+    let mut footnotes: Vec<&GemtextToken> = Vec::new();
+    for t in tokens.iter().filter(|t| t.kind == TokenKind::Footnote) {
+        if !footnotes.iter().any(|f| f.extra == t.extra) {
+            footnotes.push(t);
+        }
+    }
+    let mut footnote_referenced = vec![false; footnotes.len()];
+
+    // Citations and footnote references are linked before anything is
+    // rendered so the lists at the end know which entries to add
+    // backreferences to.
+    let tokens: Vec<GemtextToken> = tokens.iter()
+        .map(|t| match t.kind {
+            TokenKind::Text | TokenKind::UnorderedList | TokenKind::OrderedList | TokenKind::Blockquote => {
+                let data = link_footnotes(&t.data, &footnotes, &mut footnote_referenced);
+                GemtextToken {
+                    data: link_citations(&data, reference_count, &mut cited),
+                    ..t.clone()
+                }
+            },
+            _ => t.clone(),
+        })
+        .collect();
+
+    let mut previous: Option<&GemtextToken> = None;
+    for token in &tokens {
+        // Blank lines may separate references but end a list.
+        let continues = match open_list {
+            Some(TokenKind::Reference) => token.kind == TokenKind::Reference
+                || (token.kind == TokenKind::Text && token.data.is_empty()),
+            Some(TokenKind::UnorderedList) => token.kind == TokenKind::UnorderedList
+                && definition_item(&token.data).is_some() == in_definitions,
+            Some(kind) => token.kind == kind,
+            None => false,
+        };
+        if !continues {
+            if let Some(kind) = open_list.take() {
+                html.push_str(close_list(kind, in_quote_paragraph, in_definitions));
+                html.push_str(&"</blockquote>\n".repeat(quote_level.saturating_sub(1)));
+                in_quote_paragraph = false;
+                in_definitions = false;
+                quote_level = 0;
+            }
+        }
+
+        if options.sections {
+            if let Some(level) = heading_level(token) {
+                while sections.last().is_some_and(|l| *l >= level) {
+                    html.push_str("</section>\n");
+                    sections.pop();
+                }
+                html.push_str("<section>\n");
+                sections.push(level);
+            }
+        }
+
+        match token.kind {
+            TokenKind::Reference => {
+                if open_list.is_none() {
+                    html.push_str("<ol class=\"references\">\n");
+                    open_list = Some(TokenKind::Reference);
+                }
+                reference_number += 1;
+                html.push_str(&reference_as_html(token, reference_number, cited[reference_number], options));
+            },
+            // Consecutive quote lines are one quote, with a line break
+            // between lines and empty lines separating paragraphs.
+            // Deeper quote lines open quotes inside it. A quote right after a
+            // link is a quotation from that link.
+            TokenKind::Blockquote => {
+                if open_list.is_none() {
+                    html.push_str(&match previous.filter(|p| p.kind == TokenKind::Link) {
+                        Some(link) => format!("<blockquote cite=\"{}\">\n", link.data),
+                        None => "<blockquote>\n".to_string(),
+                    });
+                    open_list = Some(TokenKind::Blockquote);
+                    quote_level = 1;
+                }
+                let depth = quote_depth(token);
+                if depth != quote_level && in_quote_paragraph {
+                    html.push_str("</p>\n");
+                    in_quote_paragraph = false;
+                }
+                while quote_level < depth {
+                    html.push_str("<blockquote>\n");
+                    quote_level += 1;
+                }
+                while quote_level > depth {
+                    html.push_str("</blockquote>\n");
+                    quote_level -= 1;
+                }
+                if token.data.trim().is_empty() {
+                    if in_quote_paragraph {
+                        html.push_str("</p>\n");
+                        in_quote_paragraph = false;
+                    }
+                } else {
+                    html.push_str(if in_quote_paragraph { "<br>\n" } else { "<p>" });
+                    html.push_str(&token.data);
+                    in_quote_paragraph = true;
+                }
+            },
+            TokenKind::UnorderedList => match definition_item(&token.data) {
+                Some((term, definition)) => {
+                    if open_list.is_none() {
+                        html.push_str("<dl>\n");
+                        open_list = Some(TokenKind::UnorderedList);
+                        in_definitions = true;
+                    }
+                    html.push_str(&format!("<dt>{}</dt>\n<dd>{}</dd>\n", term, definition));
+                },
+                None => {
+                    if open_list.is_none() {
+                        html.push_str("<ul>\n");
+                        open_list = Some(TokenKind::UnorderedList);
+                    }
+                    html.push_str(&token.as_html());
+                },
+            },
+            TokenKind::OrderedList => {
+                if open_list.is_none() {
+                    match list_item_number(token) {
+                        1 => html.push_str("<ol>\n"),
+                        start => html.push_str(&format!("<ol start=\"{}\">\n", start)),
+                    }
+                    open_list = Some(TokenKind::OrderedList);
+                }
+                html.push_str(&token.as_html());
+            },
+            TokenKind::Heading | TokenKind::SubHeading | TokenKind::SubSubHeading
+                if options.heading_links && !token.extra.is_empty() => {
+                html.push_str(&heading_with_link(token));
+            },
+            TokenKind::Link if options.inline_images && is_image_link(&token.data) => {
+                html.push_str(&image_as_html(token, options.figures));
+            },
+            TokenKind::Link => html.push_str(&link_as_html(token, options)),
+            // Footnotes are gathered in a section at the end.
+            TokenKind::Footnote => {},
+            TokenKind::Text if lede && !token.data.is_empty() => {
+                html.push_str(&format!("<p class=\"lede\">{}</p>\n", token.data));
+                lede = false;
+            },
+            _ => html.push_str(&token.as_html()),
+        }
+        previous = Some(token);
+    }
+    if let Some(kind) = open_list {
+        html.push_str(close_list(kind, in_quote_paragraph, in_definitions));
+        html.push_str(&"</blockquote>\n".repeat(quote_level.saturating_sub(1)));
+    }
+    html.push_str(&"</section>\n".repeat(sections.len()));
+    if !footnotes.is_empty() {
+        html.push_str("<section class=\"footnotes\">\n<ol>\n");
+        for (footnote, referenced) in footnotes.iter().zip(footnote_referenced) {
+            html.push_str(&footnote_as_html(footnote, referenced));
+        }
+        html.push_str("</ol>\n</section>\n");
+    }
+
+    html
+}
+
+fn heading_level(token: &GemtextToken) -> Option<usize> {
+    match token.kind {
+        TokenKind::Heading => Some(1),
+        TokenKind::SubHeading => Some(2),
+        TokenKind::SubSubHeading => Some(3),
+        _ => None,
+    }
+}
+
+fn close_list(kind: TokenKind, in_quote_paragraph: bool, in_definitions: bool) -> &'static str {
+    match kind {
+        TokenKind::UnorderedList if in_definitions => "</dl>\n",
+        TokenKind::Reference | TokenKind::OrderedList => "</ol>\n",
+        TokenKind::Blockquote if in_quote_paragraph => "</p>\n</blockquote>\n",
+        TokenKind::Blockquote => "</blockquote>\n",
+        _ => "</ul>\n",
+    }
+}
+
+fn heading_with_link(token: &GemtextToken) -> String {
+    let tag = match token.kind {
+        TokenKind::Heading => "h1",
+        TokenKind::SubHeading => "h2",
+        _ => "h3",
+    };
+    format!("<{} id=\"{}\">{} <a class=\"anchor\" href=\"#{}\" aria-label=\"Link to this section\">#</a></{}>\n",
+        tag, token.extra, token.data, token.extra, tag)
+}
+
+fn image_as_html(token: &GemtextToken, figure: bool) -> String {
+    let img = format!("<img src=\"{}\" alt=\"{}\">", token.data, token.extra);
+    if figure && !token.extra.is_empty() {
+        format!("<figure>\n{}\n<figcaption>{}</figcaption>\n</figure>\n", img, token.extra)
+    } else {
+        format!("<p>{}</p>\n", img)
+    }
+}
+
+fn reference_as_html(token: &GemtextToken, number: usize, cited: bool, options: &HtmlOptions) -> String {
+    let name = if token.extra.is_empty() {
+        &token.data
+    } else {
+        strip_reference_number(&token.extra)
+    };
+    let backref = if cited {
+        format!(" <a class=\"backref\" href=\"#cite-{}\">↩</a>", number)
+    } else {
+        String::new()
+    };
+    let (href, _) = link_href(&token.data, options);
+    format!("<li id=\"ref-{}\"><a href=\"{}\"{}>{}</a>{}</li>\n",
+        number, href, external_attributes(&token.data, options), name, backref)
+}
+
+// Links to other protocols than the web get a class naming their scheme, so
+// they can be styled. Unnamed mailto: links show just the address.
+fn link_as_html(token: &GemtextToken, options: &HtmlOptions) -> String {
+    let (href, scheme) = link_href(&token.data, options);
+    let name = match (token.extra.is_empty(), scheme) {
+        (false, _) => token.extra.as_str(),
+        (true, Some("mailto")) => &token.data["mailto:".len()..],
+        (true, _) => token.data.as_str(),
+    };
+    let attributes = external_attributes(&token.data, options);
+    match scheme {
+        Some(scheme) if options.scheme_badges && scheme != "mailto" => format!(
+            "<p><a class=\"{}\" href=\"{}\"{}>{}</a> <span class=\"scheme\">{}</span></p>\n",
+            scheme, href, attributes, name, scheme),
+        Some(scheme) => format!("<p><a class=\"{}\" href=\"{}\"{}>{}</a></p>\n", scheme, href, attributes, name),
+        None => format!("<p><a href=\"{}\"{}>{}</a></p>\n", href, attributes, name),
+    }
+}
+
+// The configured rel and target attributes if url is on another host than
+// the site. Relative links and ones without a host are the site's own.
+fn external_attributes(url: &str, options: &HtmlOptions) -> String {
+    if options.external_rel.is_none() && options.external_target.is_none() {
+        return String::new();
+    }
+    let host = match url.split_once("://") {
+        Some((_, rest)) => rest.split(['/', '?', '#']).next().unwrap_or(""),
+        None => return String::new(),
+    };
+    let host = host.rsplit('@').next().unwrap_or(host);
+    if host.eq_ignore_ascii_case(&options.site_host) {
+        return String::new();
+    }
+    let mut attributes = String::new();
+    if let Some(rel) = &options.external_rel {
+        attributes.push_str(&format!(" rel=\"{}\"", escape_text(rel)));
+    }
+    if let Some(target) = &options.external_target {
+        attributes.push_str(&format!(" target=\"{}\"", escape_text(target)));
+    }
+    attributes
+}
+
+// Where a link points in HTML and its scheme if it's gemini, gopher or mailto.
+// gemini:// and gopher:// links go through the proxy for their scheme, which
+// gets the rest of the URL appended.
+fn link_href(url: &str, options: &HtmlOptions) -> (String, Option<&'static str>) {
+    let scheme = url.split_once(':').and_then(|(scheme, _)| {
+        ["gemini", "gopher", "mailto"].into_iter().find(|s| scheme.eq_ignore_ascii_case(s))
+    });
+    let proxy = match scheme {
+        Some("gemini") => options.gemini_proxy.as_ref(),
+        Some("gopher") => options.gopher_proxy.as_ref(),
+        _ => None,
+    };
+    match proxy {
+        Some(proxy) => {
+            let rest = url.split_once("://").map(|(_, r)| r).unwrap_or(url);
+            (format!("{}{}", escape_text(proxy), rest), scheme)
+        },
+        None => (url.to_owned(), scheme),
+    }
+}
+
+fn footnote_as_html(token: &GemtextToken, referenced: bool) -> String {
+    let backref = if referenced {
+        format!(" <a class=\"backref\" href=\"#fnref-{}\">↩</a>", token.extra)
+    } else {
+        String::new()
+    };
+    format!("<li id=\"fn-{}\">{}{}</li>\n", token.extra, token.data, backref)
+}
+
+// Turn [^label] markers into superscript links to the matching footnote,
+// numbered like the footnotes section. As with citations only the first
+// reference gets the id its backreference points to.
+fn link_footnotes(text: &str, footnotes: &[&GemtextToken], referenced: &mut [bool]) -> String {
+    if footnotes.is_empty() {
+        return text.to_owned();
+    }
+
+    replace_footnote_markers(text, |label| {
+        let i = footnotes.iter().position(|f| f.extra == label)?;
+        let id = if referenced[i] {
+            String::new()
+        } else {
+            referenced[i] = true;
+            format!(" id=\"fnref-{}\"", label)
+        };
+        Some(format!("<sup class=\"footnote-ref\"><a{} href=\"#fn-{}\">{}</a></sup>", id, label, i + 1))
+    })
+}
+
+// Reference names may be written as "[1] Name" so they read well in Gemini,
+// the HTML list already shows the number.
+fn strip_reference_number(name: &str) -> &str {
+    if let Some(rest) = name.strip_prefix('[') {
+        if let Some((number, after)) = rest.split_once(']') {
+            if !number.is_empty() && number.chars().all(|c| c.is_ascii_digit()) {
+                return after.trim_start();
+            }
+        }
+    }
+    name
+}
+
+// Turn [N] markers into superscript links to the matching reference. Only the
+// first citation of a reference gets the id its backreference points to.
+fn link_citations(text: &str, reference_count: usize, cited: &mut [bool]) -> String {
+    if reference_count == 0 {
+        return text.to_owned();
+    }
+
+    let mut linked = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find('[') {
+        linked.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        let digits = after.split_once(']')
+            .map(|(n, _)| n)
+            .filter(|n| !n.is_empty() && n.chars().all(|c| c.is_ascii_digit()));
+        let number = digits
+            .and_then(|n| n.parse::<usize>().ok())
+            .filter(|n| *n >= 1 && *n <= reference_count);
+
+        match (digits, number) {
+            (Some(digits), Some(n)) => {
+                let id = if cited[n] {
+                    String::new()
+                } else {
+                    cited[n] = true;
+                    format!(" id=\"cite-{}\"", n)
+                };
+                linked.push_str(&format!("<sup><a{} href=\"#ref-{}\">[{}]</a></sup>", id, n, n));
+                rest = &after[digits.len() + 1..];
+            },
+            _ => {
+                linked.push('[');
+                rest = after;
+            },
+        }
+    }
+    linked.push_str(rest);
+
+    linked
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gemtext::parse_gemtext;
+
+    fn render(source: &str) -> String {
+        let lines: Vec<String> = source.lines().map(String::from).collect();
+        generate_html_from_tokens(&parse_gemtext(&lines), &HtmlOptions::default())
+    }
+
+    #[test]
+    fn link_to_non_ascii_target() {
+        assert_eq!(render("=> a日本.gmi Japan"), "<p><a href=\"a日本.gmi\">Japan</a></p>\n");
+        assert_eq!(render("=> 日本語:x Japan"), "<p><a href=\"日本語:x\">Japan</a></p>\n");
+    }
+
+    #[test]
+    fn link_scheme_is_case_insensitive() {
+        let html = render("=> GEMINI://example.org/ Capsule");
+        assert!(html.contains("class=\"gemini\""), "{}", html);
+    }
+}
@@ -0,0 +1,93 @@
+// Inline formatting within a line of gemtext, an extension to the line based
+// format: *bold*, _italic_ and `code`. Markers only count at word boundaries
+// so snake_case names and 2*3*4 stay as they are, and unclosed markers are
+// left in the text.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Span {
+    Text(String),
+    Strong(Vec<Span>),
+    Emphasis(Vec<Span>),
+    Code(String),
+}
+
+pub fn tokenize(text: &str) -> Vec<Span> {
+    let mut spans = Vec::new();
+    let mut plain = String::new();
+    let mut i = 0;
+
+    while let Some(c) = text[i..].chars().next() {
+        match delimited(text, i, c) {
+            Some((inner, length)) => {
+                if !plain.is_empty() {
+                    spans.push(Span::Text(std::mem::take(&mut plain)));
+                }
+                spans.push(match c {
+                    '`' => Span::Code(inner.to_owned()),
+                    '*' => Span::Strong(tokenize(inner)),
+                    _ => Span::Emphasis(tokenize(inner)),
+                });
+                i += length;
+            },
+            None => {
+                plain.push(c);
+                i += c.len_utf8();
+            },
+        }
+    }
+    if !plain.is_empty() {
+        spans.push(Span::Text(plain));
+    }
+
+    spans
+}
+
+pub fn to_html(spans: &[Span]) -> String {
+    spans.iter()
+        .map(|s| match s {
+            Span::Text(t) => t.clone(),
+            Span::Strong(inner) => format!("<strong>{}</strong>", to_html(inner)),
+            Span::Emphasis(inner) => format!("<em>{}</em>", to_html(inner)),
+            Span::Code(c) => format!("<code>{}</code>", c),
+        })
+        .collect()
+}
+
+// Render the inline formatting of already escaped text.
+pub fn format_html(text: &str) -> String {
+    to_html(&tokenize(text))
+}
+
+// The text between a marker at start and its closing marker, and the length
+// of the whole span with both markers.
+fn delimited(text: &str, start: usize, marker: char) -> Option<(&str, usize)> {
+    if !matches!(marker, '*' | '_' | '`') {
+        return None;
+    }
+    let after_open = &text[start + 1..];
+    // Code is taken literally, the other markers have to hug a word.
+    let word_marker = marker != '`';
+    if word_marker {
+        let before = text[..start].chars().next_back();
+        if before.map(|c| c.is_alphanumeric()).unwrap_or(false)
+            || after_open.starts_with(char::is_whitespace) {
+            return None;
+        }
+    }
+
+    for (j, c) in after_open.char_indices() {
+        if c != marker || j == 0 {
+            continue;
+        }
+        let inner = &after_open[..j];
+        if word_marker {
+            let after = after_open[j + 1..].chars().next();
+            if inner.ends_with(char::is_whitespace)
+                || after.map(|c| c.is_alphanumeric()).unwrap_or(false) {
+                continue;
+            }
+        }
+        return Some((inner, j + 2));
+    }
+
+    None
+}
@@ -0,0 +1,109 @@
+// The gemtext parser and the HTML and Gemini renderers build on their own,
+// without the filesystem and CLI parts of crosspub, for targets like
+// wasm32-unknown-unknown.
+pub mod gemini;
+pub mod gemtext;
+pub mod html;
+pub mod inline;
+pub mod toc;
+pub mod typography;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+
+// The rest of crosspub, which the binary is a thin wrapper around.
+#[cfg(feature = "cli")]
+pub mod about;
+#[cfg(feature = "cli")]
+pub mod archive;
+#[cfg(feature = "cli")]
+pub mod asset;
+#[cfg(feature = "cli")]
+pub mod authors;
+#[cfg(feature = "cli")]
+pub mod backlinks;
+#[cfg(feature = "cli")]
+pub mod bookmark;
+#[cfg(feature = "cli")]
+pub mod buildlock;
+#[cfg(feature = "cli")]
+pub mod changes;
+#[cfg(feature = "cli")]
+pub mod check;
+#[cfg(feature = "cli")]
+pub mod config;
+#[cfg(feature = "cli")]
+pub mod contexts;
+#[cfg(feature = "cli")]
+pub mod crosspub;
+#[cfg(feature = "cli")]
+pub mod deploy;
+#[cfg(feature = "cli")]
+pub mod digest;
+#[cfg(feature = "cli")]
+pub mod doctor;
+#[cfg(feature = "cli")]
+pub mod export;
+#[cfg(feature = "cli")]
+pub mod frontmatter;
+#[cfg(feature = "cli")]
+pub mod glossary;
+#[cfg(feature = "cli")]
+pub mod linktitle;
+#[cfg(feature = "cli")]
+pub mod lint;
+#[cfg(feature = "cli")]
+pub mod lock;
+#[cfg(feature = "cli")]
+pub mod markdown;
+#[cfg(feature = "cli")]
+pub mod meta;
+#[cfg(feature = "cli")]
+pub mod nostr;
+#[cfg(feature = "cli")]
+pub mod notify;
+#[cfg(feature = "cli")]
+pub mod outbound;
+#[cfg(feature = "cli")]
+pub mod page;
+#[cfg(feature = "cli")]
+pub mod post;
+#[cfg(feature = "cli")]
+pub mod redirect;
+#[cfg(feature = "cli")]
+pub mod s3;
+#[cfg(feature = "cli")]
+pub mod schema;
+#[cfg(feature = "cli")]
+pub mod search;
+#[cfg(feature = "cli")]
+pub mod selfcheck;
+#[cfg(feature = "cli")]
+pub mod serve;
+#[cfg(feature = "cli")]
+pub mod sqlite;
+#[cfg(feature = "cli")]
+pub mod stats;
+#[cfg(feature = "cli")]
+pub mod store;
+#[cfg(feature = "cli")]
+pub mod structured;
+#[cfg(feature = "cli")]
+pub mod summary;
+#[cfg(feature = "cli")]
+pub mod tags;
+// Fixtures for building whole sites in tests, ours and those of tools
+// built on crosspub.
+#[cfg(feature = "cli")]
+pub mod testing;
+#[cfg(feature = "cli")]
+pub mod timezone;
+#[cfg(feature = "cli")]
+pub mod timings;
+#[cfg(feature = "cli")]
+pub mod tokens;
+#[cfg(feature = "cli")]
+pub mod topic;
+#[cfg(feature = "cli")]
+pub mod websub;
+#[cfg(feature = "cli")]
+pub mod wikilink;
@@ -0,0 +1,133 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::Read;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::gemtext::{GemtextToken, TokenKind};
+use crate::notify::warning;
+
+// Only the start of a page is read looking for its title.
+const MAX_PAGE_BYTES: u64 = 512 * 1024;
+
+// Titles of linked pages, kept in a file of "{url}\t{title}" lines. Pages
+// without a title are remembered with an empty one so they aren't fetched
+// again on every build.
+pub struct TitleCache {
+    path: PathBuf,
+    titles: BTreeMap<String, String>,
+    changed: bool,
+}
+
+impl TitleCache {
+    pub fn load(path: PathBuf) -> TitleCache {
+        let titles = match fs::read_to_string(&path) {
+            Ok(c) => c.lines()
+                .filter_map(|l| l.split_once('\t'))
+                .map(|(url, title)| (url.to_string(), title.to_string()))
+                .collect(),
+            Err(_) => BTreeMap::new(),
+        };
+        TitleCache { path, titles, changed: false }
+    }
+
+    // The title of a page, fetched over the network when it isn't cached and
+    // an agent is given.
+    pub fn title(&mut self, url: &str, agent: Option<&ureq::Agent>) -> Option<String> {
+        if let Some(title) = self.titles.get(url) {
+            return if title.is_empty() { None } else { Some(title.clone()) };
+        }
+        let agent = agent?;
+        println!("Fetching title of {}", url);
+        // Failed requests aren't cached so they're tried again next build.
+        let title = match fetch_title(agent, url) {
+            Ok(t) => t,
+            Err(e) => {
+                warning!("Could not fetch title of {}: {}", url, e);
+                return None;
+            }
+        };
+        self.titles.insert(url.to_string(), title.clone().unwrap_or_default());
+        self.changed = true;
+        title
+    }
+
+    pub fn save(&self) {
+        if !self.changed {
+            return;
+        }
+        let contents: String = self.titles.iter()
+            .map(|(url, title)| format!("{}\t{}\n", url, title))
+            .collect();
+        if fs::write(&self.path, contents).is_err() {
+            warning!("Could not write link title cache {}",
+                &self.path.to_string_lossy());
+        }
+    }
+}
+
+pub fn agent() -> ureq::Agent {
+    ureq::AgentBuilder::new()
+        .timeout(Duration::from_secs(10))
+        .build()
+}
+
+// http(s) links without a name.
+pub fn bare_links(tokens: &[GemtextToken]) -> Vec<String> {
+    tokens.iter()
+        .filter(|t| t.kind == TokenKind::Link && t.extra.is_empty())
+        .filter(|t| t.data.starts_with("http://") || t.data.starts_with("https://"))
+        .map(|t| t.data.clone())
+        .collect()
+}
+
+// Give bare links the title of the page they point to as their name.
+pub fn name_links(tokens: &[GemtextToken], titles: &BTreeMap<String, String>) -> Vec<GemtextToken> {
+    tokens.iter()
+        .map(|t| match titles.get(&t.data) {
+            Some(title) if t.kind == TokenKind::Link && t.extra.is_empty() => GemtextToken {
+                extra: title.clone(),
+                ..t.clone()
+            },
+            _ => t.clone(),
+        })
+        .collect()
+}
+
+fn fetch_title(agent: &ureq::Agent, url: &str) -> Result<Option<String>, Box<ureq::Error>> {
+    let response = agent.get(url).call().map_err(Box::new)?;
+    if response.content_type() != "text/html" {
+        return Ok(None);
+    }
+    let mut page = String::new();
+    if response.into_reader().take(MAX_PAGE_BYTES).read_to_string(&mut page).is_err() {
+        return Ok(None);
+    }
+    Ok(page_title(&page))
+}
+
+fn page_title(page: &str) -> Option<String> {
+    let lower = page.to_ascii_lowercase();
+    let open = lower.find("<title")?;
+    let start = open + lower[open..].find('>')? + 1;
+    let end = start + lower[start..].find("</title")?;
+    let title = decode_entities(&page[start..end])
+        .split_whitespace()
+        .collect::<Vec<&str>>()
+        .join(" ");
+    if title.is_empty() {
+        None
+    } else {
+        Some(title)
+    }
+}
+
+fn decode_entities(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&#x27;", "'")
+        .replace("&nbsp;", " ")
+        .replace("&amp;", "&")
+}
@@ -0,0 +1,297 @@
+use std::fs;
+use std::path::Path;
+
+use chrono::NaiveDate;
+use serde::de::DeserializeOwned;
+
+use crate::config::Config;
+use crate::crosspub::content_files;
+use crate::frontmatter::{Frontmatter, PageFrontmatter, TopicFrontmatter};
+use crate::gemtext::{GemtextToken, Parser, TokenKind};
+use crate::html::is_image_link;
+use crate::post::{parse_date, valid_lang, PostKind};
+
+// Something wrong in a source file. line counts from 1 within the whole
+// file, 0 when the problem is with the file itself.
+struct Problem {
+    file: String,
+    line: usize,
+    message: String,
+    level: Level,
+}
+
+// How much a problem matters. Problems with the sources themselves are
+// errors, the optional rules of [lint] can be either.
+#[derive(Clone, Copy, PartialEq)]
+enum Level {
+    Off,
+    Warn,
+    Error,
+}
+
+impl Level {
+    fn parse(rule: &str, level: Option<&str>) -> Result<Level, String> {
+        match level {
+            None | Some("off") => Ok(Level::Off),
+            Some("warn") => Ok(Level::Warn),
+            Some("error") => Ok(Level::Error),
+            Some(l) => Err(format!("Unknown level \"{}\" for {} in [lint], expected \"off\", \"warn\" or \"error\"", l, rule)),
+        }
+    }
+}
+
+// The optional rules, about accessibility rather than whether a source
+// builds.
+struct Rules {
+    preformatted_alt: Level,
+    image_description: Level,
+}
+
+impl Rules {
+    fn check(&self, token: &GemtextToken) -> Option<(Level, String)> {
+        let (level, message) = match token.kind {
+            TokenKind::PreFormattedText if token.extra.trim().is_empty() =>
+                (self.preformatted_alt, "preformatted block without alt text after the ```".to_string()),
+            TokenKind::Link if is_image_link(&token.data) && token.extra.trim().is_empty() =>
+                (self.image_description, format!("image {} without a description", token.data)),
+            _ => return None,
+        };
+        (level != Level::Off).then_some((level, message))
+    }
+}
+
+// What a source is, which decides what its frontmatter has to hold.
+#[derive(Clone, Copy, PartialEq)]
+enum Source {
+    Post,
+    Topic,
+    Page,
+}
+
+// Parse every source of the site strictly and print the problems found as
+// "file:line: message", without building anything. Unlike a build, which
+// stops at the first broken frontmatter, every file is looked at. Returns
+// whether no problems were found.
+pub fn run(config: &Config, dir: &Path) -> bool {
+    let rules = match (Level::parse("preformatted_alt", config.lint.preformatted_alt.as_deref()),
+        Level::parse("image_description", config.lint.image_description.as_deref())) {
+        (Ok(preformatted_alt), Ok(image_description)) => Rules { preformatted_alt, image_description },
+        (Err(e), _) | (_, Err(e)) => {
+            eprintln!("Error: {}", e);
+            return false;
+        },
+    };
+    let follow_symlinks = config.build.follow_symlinks.unwrap_or(true);
+    let mut problems = Vec::new();
+    let mut files = 0;
+    for content_dir in config.content_dirs() {
+        let source = match content_dir.kind.as_str() {
+            "post" => Source::Post,
+            "topic" => Source::Topic,
+            "page" => Source::Page,
+            k => {
+                problems.push(Problem {
+                    file: content_dir.path.clone(),
+                    line: 0,
+                    message: format!("unknown content kind \"{}\", expected \"post\", \"topic\" or \"page\"", k),
+                    level: Level::Error,
+                });
+                continue;
+            },
+        };
+        let path = dir.join(&content_dir.path);
+        if source == Source::Page && !path.is_dir() {
+            continue;
+        }
+        if !path.is_dir() {
+            problems.push(Problem {
+                file: content_dir.path.clone(),
+                line: 0,
+                message: "no such directory".to_string(),
+                level: Level::Error,
+            });
+            continue;
+        }
+        for (p, relative) in content_files(&path, &["gmi"], follow_symlinks) {
+            lint_file(&p, &format!("{}/{}", content_dir.path, relative), source, &rules, &mut problems);
+            files += 1;
+        }
+    }
+
+    // Standalone pages next to the content directories.
+    let mut pages: Vec<_> = fs::read_dir(dir).into_iter().flatten().flatten()
+        .map(|e| e.path())
+        .filter(|p| p.is_file() && p.extension() == Some(std::ffi::OsStr::new("gmi")))
+        .collect();
+    pages.sort();
+    for p in pages {
+        let name = p.file_name().unwrap_or_default().to_string_lossy().into_owned();
+        lint_file(&p, &name, Source::Page, &rules, &mut problems);
+        files += 1;
+    }
+
+    for p in &problems {
+        let message = match p.level {
+            Level::Warn => format!("warning: {}", p.message),
+            _ => p.message.clone(),
+        };
+        if p.line == 0 {
+            println!("{}: {}", p.file, message);
+        } else {
+            println!("{}:{}: {}", p.file, p.line, message);
+        }
+    }
+    let errors = problems.iter().filter(|p| p.level == Level::Error).count();
+    let warnings = problems.len() - errors;
+    if problems.is_empty() {
+        println!("No problems found in {} file(s).", files);
+    } else if errors == 0 {
+        println!("{} warning(s) found in {} file(s).", warnings, files);
+    } else {
+        eprintln!("{} problem(s) and {} warning(s) found in {} file(s).", errors, warnings, files);
+    }
+    errors == 0
+}
+
+fn lint_file(path: &Path, name: &str, source: Source, rules: &Rules, problems: &mut Vec<Problem>) {
+    let mut found = Vec::new();
+    check_file(path, name, source, rules, &mut found);
+    // In the order of the file rather than of the checks.
+    found.sort_by_key(|p| p.line);
+    problems.append(&mut found);
+}
+
+fn check_file(path: &Path, name: &str, source: Source, rules: &Rules, problems: &mut Vec<Problem>) {
+    let mut problem = |line: usize, message: String, level: Level| problems.push(Problem {
+        file: name.to_string(),
+        line,
+        message,
+        level,
+    });
+    let contents = match fs::read_to_string(path) {
+        Ok(c) => c,
+        Err(e) => {
+            problem(0, format!("could not read: {}", e), Level::Error);
+            return;
+        },
+    };
+    let lines: Vec<String> = contents.lines().map(String::from).collect();
+
+    // The index of the closing --- of the frontmatter.
+    let frontmatter_end = match lines.iter().skip(1).position(|l| l.trim() == "---") {
+        Some(i) if lines[0].trim() == "---" => Some(i + 1),
+        _ => None,
+    };
+    let body_start = match frontmatter_end {
+        Some(end) => {
+            let frontmatter = &lines[1..end];
+            let found = match source {
+                Source::Post => parse::<Frontmatter>(frontmatter).map(|fm| post_problems(&fm)),
+                Source::Topic => parse::<TopicFrontmatter>(frontmatter).map(|fm| topic_problems(&fm)),
+                Source::Page => parse::<PageFrontmatter>(frontmatter).map(|_| Vec::new()),
+            };
+            match found {
+                Ok(found) => {
+                    for (key, message) in found {
+                        problem(key_line(frontmatter, key), message, Level::Error);
+                    }
+                },
+                Err((line, message)) => problem(line, message, Level::Error),
+            }
+            end + 1
+        },
+        None => {
+            if source != Source::Page {
+                problem(1, "missing frontmatter between two --- lines".to_string(), Level::Error);
+            }
+            0
+        },
+    };
+
+    let body = lines[body_start..].iter().cloned().map(Ok);
+    for token in Parser::from_lines(body).strict() {
+        match token {
+            Ok(t) => if let Some((level, message)) = rules.check(&t) {
+                problem(body_start + t.span.line, message, level);
+            },
+            Err(e) => problem(body_start + e.line, e.kind.to_string(), Level::Error),
+        }
+    }
+}
+
+// Parse frontmatter, or give the line of the file and message of the error.
+fn parse<T: DeserializeOwned>(frontmatter: &[String]) -> Result<T, (usize, String)> {
+    toml::from_str(&frontmatter.join("\n")).map_err(|e| {
+        // The position toml gives is within the frontmatter, which starts on
+        // the second line of the file.
+        let line = e.line_col().map(|(l, _)| l + 2).unwrap_or(1);
+        let message = e.to_string();
+        let message = match message.rfind(" at line ") {
+            Some(i) => message[..i].to_string(),
+            None => message,
+        };
+        (line, format!("frontmatter: {}", message))
+    })
+}
+
+// The line of the file a frontmatter key is set on, or the opening --- when
+// it isn't set.
+fn key_line(frontmatter: &[String], key: &str) -> usize {
+    frontmatter.iter()
+        .position(|l| l.trim_start().strip_prefix(key)
+            .map(|rest| rest.trim_start().starts_with('='))
+            .unwrap_or(false))
+        .map(|i| i + 2)
+        .unwrap_or(1)
+}
+
+// The same checks Post::from_source stops a build on, with the key each is
+// about.
+fn post_problems(fm: &Frontmatter) -> Vec<(&'static str, String)> {
+    let mut found = Vec::new();
+    let kind = match &fm.kind {
+        None => PostKind::Article,
+        Some(k) => PostKind::from_name(k).unwrap_or_else(|| {
+            found.push(("kind", format!("unknown post kind \"{}\"", k)));
+            PostKind::Article
+        }),
+    };
+    if kind != PostKind::Note {
+        if fm.title.is_none() {
+            found.push(("title", "missing title".to_string()));
+        }
+        if fm.slug.is_none() {
+            found.push(("slug", "missing slug".to_string()));
+        }
+    }
+    let lang = fm.lang.as_ref().or_else(|| fm.meta.as_ref().and_then(|m| m.lang.as_ref()));
+    if let Some(lang) = lang.filter(|l| !valid_lang(l)) {
+        found.push(("lang", format!("language \"{}\" should be a tag like \"en\" or \"pt-BR\"", lang)));
+    }
+    if kind == PostKind::Link && fm.url.is_none() {
+        found.push(("kind", "link post without a url".to_string()));
+    }
+    if kind == PostKind::Recipe && fm.recipe.is_none() {
+        found.push(("kind", "recipe post without a [recipe] table".to_string()));
+    }
+    if kind == PostKind::Review && fm.review.is_none() {
+        found.push(("kind", "review post without a [review] table".to_string()));
+    }
+    if let Err(e) = parse_date(&fm.date) {
+        found.push(("date", e.to_lowercase()));
+    }
+    if let Some(Err(e)) = fm.updated.as_deref().map(parse_date) {
+        found.push(("updated", format!("updated {}", e.to_lowercase())));
+    }
+    found
+}
+
+fn topic_problems(fm: &TopicFrontmatter) -> Vec<(&'static str, String)> {
+    let mut found = Vec::new();
+    for (key, date) in [("date", &fm.date), ("updated", &fm.updated)] {
+        if let Some(date) = date.as_ref().filter(|d| NaiveDate::parse_from_str(d, "%Y-%m-%d").is_err()) {
+            found.push((key, format!("date \"{}\" should look like 2022-01-31", date)));
+        }
+    }
+    found
+}
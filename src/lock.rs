@@ -0,0 +1,30 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use sha2::{Digest, Sha256};
+
+// The SHA-256 of a source file as lowercase hex.
+pub fn hash_file(path: &Path) -> std::io::Result<String> {
+    let contents = fs::read(path)?;
+    let digest = Sha256::digest(&contents);
+    Ok(digest.iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+// Read a lock file, one "{hash}  {file}" line per post like sha256sum writes.
+pub fn read(path: &Path) -> std::io::Result<BTreeMap<String, String>> {
+    let contents = fs::read_to_string(path)?;
+    Ok(contents.lines()
+        .filter(|l| !l.trim().is_empty() && !l.starts_with('#'))
+        .filter_map(|l| l.split_once("  "))
+        .map(|(hash, file)| (file.to_string(), hash.to_string()))
+        .collect())
+}
+
+pub fn write(path: &Path, entries: &BTreeMap<String, String>) -> std::io::Result<()> {
+    let mut contents = String::from("# Generated by crosspub lock, the posts included in a --locked build.\n");
+    for (file, hash) in entries {
+        contents.push_str(&format!("{}  {}\n", hash, file));
+    }
+    fs::write(path, contents)
+}
@@ -1,20 +1,14 @@
-pub mod about;
-pub mod config;
-pub mod contexts;
-pub mod crosspub;
-pub mod frontmatter;
-pub mod gemtext;
-pub mod post;
-pub mod topic;
-
 use std::fs;
+use std::io;
 use std::process::exit;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use clap::Parser;
-use xdg;
 
-use crosspub::{Args, CrossPub};
+use crosspub::buildlock::{self, LockError};
+use crosspub::config::Config;
+use crosspub::crosspub::{html_options, Args, Command, CrossPub, TemplateCommand, TokensCommand};
+use crosspub::{changes, doctor, lint, schema, search, serve, tokens};
 
 fn main() {
     let mut args = Args::parse();
@@ -49,34 +43,58 @@ fn main() {
                 exit(1);
             }
         }
-        match fs::create_dir("~/.config/crosspub") {
-            _ => {}
-        }
+        let _ = fs::create_dir("~/.config/crosspub");
         println!("Initialized crosspub directories and created config.\n\n\
             Blogs/articles go in posts/\n\
             Wikis/digital gardens go in topics/");
         exit(0);
     }
 
+    // Answer a search request from a Gemini server, this runs as CGI so it
+    // skips loading the config and site entirely.
+    if let Some(Command::SearchCgi { index }) = &args.command {
+        let query = std::env::var("QUERY_STRING").unwrap_or_default();
+        print!("{}", search::cgi_response(index, &query));
+        exit(0);
+    }
+
+    // Schemas only depend on crosspub itself.
+    if let Some(Command::Schema { kind }) = &args.command {
+        println!("{}", schema::schema(kind));
+        exit(0);
+    }
+
+    // Parsing is the same for every site, so it doesn't need a config.
+    if let Some(Command::Tokens { command: TokensCommand::Parse { file, output } }) = &args.command {
+        println!("{}", tokens::parse(&read_input(file), output));
+        exit(0);
+    }
+
     if args.dir.is_none() {
         args.dir = Some(PathBuf::from("."));
     }
 
-    // Load config
     let xdg_dirs = xdg::BaseDirectories::with_prefix("crosspub").unwrap();
-    let config_path: PathBuf;
 
-    if !args.config.is_none() {
-        config_path = args.config.clone().unwrap();
+    // The doctor looks at a setup that may not load, so it runs before the
+    // config is read.
+    if let Some(Command::Doctor { repair }) = &args.command {
+        let ok = doctor::run(&xdg_dirs, args.data_dir.as_deref(), args.config.clone(), args.profile.as_deref(), args.dir.as_ref().unwrap(), *repair);
+        exit(if ok { 0 } else { 1 });
+    }
+
+    // Load config
+    let config_path: PathBuf = if let Some(c) = &args.config {
+        c.clone()
     } else {
-        config_path = match xdg_dirs.find_config_file("config.toml") {
+        match xdg_dirs.find_config_file("config.toml") {
             Some(p) => p,
             None => {
                 eprintln!("Error: could not find config file.");
                 exit(1);
             }
-        };
-    }
+        }
+    };
     let config_contents = match std::fs::read_to_string(&config_path) {
         Ok(c) => c,
         Err(_) => {
@@ -84,16 +102,127 @@ fn main() {
             exit(1);
         }
     };
-    let config = match toml::from_str(&config_contents) {
+    let mut config: Config = match toml::from_str(&config_contents) {
         Ok(c) => c,
         Err(_) => {
             eprintln!("Error: could not parse config.toml.");
             exit(1);
         }
     };
-    
+    if let Some(p) = &args.profile {
+        if !config.apply_profile(p) {
+            eprintln!("Error: no profile named {} in config.toml.", p);
+            exit(1);
+        }
+    }
+
+    // Linting reads the sources on its own so it can report on every file
+    // rather than stopping at the first one that doesn't load.
+    if let Some(Command::Lint) = &args.command {
+        let ok = lint::run(&config, args.dir.as_ref().unwrap());
+        exit(if ok { 0 } else { 1 });
+    }
+
+    // Changes between refs come from git rather than loading the site.
+    if let Some(Command::Changes { since, until, json }) = &args.command {
+        match changes::between(&config, args.dir.as_ref().unwrap(), since, until) {
+            // The summary goes to stderr with --json so the output can be
+            // piped to a script. Changes are plain data, serializing them
+            // can't fail.
+            Ok(c) if *json => {
+                eprint!("{}", c.summary());
+                println!("{}", serde_json::to_string_pretty(&c).unwrap());
+            },
+            Ok(c) => print!("{}", c.summary()),
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                exit(1);
+            },
+        }
+        exit(0);
+    }
+
+    // Serving builds the site in separate processes, each taking the build
+    // lock for itself.
+    if let Some(Command::Serve { port }) = &args.command {
+        let templates = match &args.data_dir {
+            Some(d) => vec![d.join("templates")],
+            None => xdg_dirs.find_data_files("templates").collect(),
+        };
+        let ok = serve::run(&config, &args, &config_path, templates, *port);
+        exit(if ok { 0 } else { 1 });
+    }
+
+    // Rendering tokens only needs the HTML settings of the config.
+    if let Some(Command::Tokens { command: TokensCommand::Render { file, output } }) = &args.command {
+        match tokens::render(&read_input(file), output, &html_options(&config)) {
+            Ok(r) => print!("{}", r),
+            Err(e) => {
+                eprintln!("Error: could not read tokens from {}: {}", file.to_string_lossy(), e);
+                exit(1);
+            }
+        }
+        exit(0);
+    }
+
+    // Only one build of a site may run at a time, the lock is held until
+    // main returns.
+    let _build_lock = if args.command.is_none() {
+        let lock_path = args.dir.clone().unwrap().join(".crosspub-build.lock");
+        match buildlock::acquire(&lock_path, args.wait) {
+            Ok(l) => Some(l),
+            Err(LockError::Held(holder)) => {
+                eprintln!("Error: Another build (process {}) is running, wait for it with --wait", holder);
+                exit(1);
+            },
+            Err(LockError::Io(_)) => {
+                eprintln!("Error: Could not create build lock {}", lock_path.to_string_lossy());
+                exit(1);
+            },
+        }
+    } else {
+        None
+    };
+
     let crosspub = CrossPub::new(&config, &args);
-    crosspub.write();
 
-    println!("Finished");
+    match &args.command {
+        Some(Command::Export { format }) => {
+            println!("{}", crosspub.export(format));
+        },
+        Some(Command::Lock) => {
+            crosspub.write_lock();
+        },
+        Some(Command::Template { command: TemplateCommand::Test { template, sample } }) => {
+            print!("{}", crosspub.test_template(template, sample));
+        },
+        Some(Command::SearchCgi { .. }) | Some(Command::Doctor { .. }) | Some(Command::Lint) | Some(Command::Changes { .. })
+            | Some(Command::Schema { .. }) | Some(Command::Tokens { .. }) | Some(Command::Serve { .. }) => {},
+        None if !args.rerender.is_empty() => {
+            crosspub.write_templates(&args.rerender);
+            println!("Finished");
+            crosspub.print_timings();
+        },
+        None => {
+            crosspub.write();
+            println!("Finished");
+            crosspub.print_timings();
+        },
+    }
+}
+
+// The contents of a file given on the command line, - being standard input.
+fn read_input(file: &Path) -> String {
+    let contents = if file.as_os_str() == "-" {
+        io::read_to_string(io::stdin())
+    } else {
+        fs::read_to_string(file)
+    };
+    match contents {
+        Ok(c) => c,
+        Err(e) => {
+            eprintln!("Error: could not read {}: {}", file.to_string_lossy(), e);
+            exit(1);
+        }
+    }
 }
@@ -0,0 +1,50 @@
+use crate::gemtext::{needs_escape, quote_depth, GemtextToken, TokenKind};
+
+// Turn a token chain into Markdown for platforms that don't speak gemtext.
+// Every gemtext line is its own block, so blocks are kept apart with a blank
+// line except for consecutive list items.
+pub fn generate_markdown_from_tokens(tokens: &[GemtextToken]) -> String {
+    let mut markdown = String::new();
+    let mut previous: Option<&GemtextToken> = None;
+    for token in tokens {
+        if token.kind == TokenKind::Text && token.data.trim().is_empty() {
+            continue;
+        }
+        if let Some(p) = previous {
+            let in_list = matches!(p.kind, TokenKind::UnorderedList | TokenKind::OrderedList)
+                && token.kind == p.kind;
+            markdown.push_str(if in_list { "\n" } else { "\n\n" });
+        }
+        markdown.push_str(&token_as_markdown(token));
+        previous = Some(token);
+    }
+    markdown
+}
+
+fn token_as_markdown(token: &GemtextToken) -> String {
+    match token.kind {
+        TokenKind::Heading => format!("# {}", token.data),
+        TokenKind::SubHeading => format!("## {}", token.data),
+        TokenKind::SubSubHeading => format!("### {}", token.data),
+        TokenKind::Link | TokenKind::Reference => {
+            if token.extra.is_empty() {
+                format!("<{}>", token.data)
+            } else {
+                format!("[{}]({})", token.extra, token.data)
+            }
+        },
+        TokenKind::Blockquote => format!("{} {}", "> ".repeat(quote_depth(token)).trim_end(), token.data),
+        TokenKind::UnorderedList => format!("- {}", token.data),
+        TokenKind::OrderedList => format!("{} {}", token.extra, token.data),
+        TokenKind::PreFormattedText => {
+            if token.data.is_empty() {
+                format!("```{}\n```", token.extra)
+            } else {
+                format!("```{}\n{}\n```", token.extra, token.data)
+            }
+        },
+        TokenKind::Footnote => format!("[^{}]: {}", token.extra, token.data),
+        TokenKind::Text if needs_escape(&token.data) => format!("\\{}", token.data),
+        TokenKind::Text => token.data.clone(),
+    }
+}
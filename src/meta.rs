@@ -0,0 +1,43 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+// Response metadata for a single Gemini file, set in a post's [meta]
+// frontmatter table.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, Eq, PartialEq, Ord, PartialOrd, JsonSchema)]
+pub struct Meta {
+    pub lang: Option<String>,
+    pub mime: Option<String>,
+    // Seconds a client may cache the response for.
+    pub cache: Option<u64>,
+}
+
+impl Meta {
+    // The value of a .meta line. A full MIME type replaces the server's
+    // guess, a value starting with ; only adds parameters to it.
+    pub fn value(&self, default_lang: Option<&str>) -> Option<String> {
+        let mut value = self.mime.clone().unwrap_or_default();
+        if let Some(lang) = self.lang.as_deref().or(default_lang) {
+            value.push_str(&format!(";lang={}", lang));
+        }
+        if let Some(cache) = self.cache {
+            value.push_str(&format!(";cache={}", cache));
+        }
+        if value.is_empty() {
+            None
+        } else {
+            Some(value)
+        }
+    }
+}
+
+pub const SIDECAR_HEADER: &str = "# Generated by crosspub";
+
+// A .meta sidecar in the format read by agate and similar servers, one
+// "{file}: {value}" line per file in the directory.
+pub fn sidecar(entries: &[(String, String)]) -> String {
+    let mut contents = format!("{}\n", SIDECAR_HEADER);
+    for (file, value) in entries {
+        contents.push_str(&format!("{}: {}\n", file, value));
+    }
+    contents
+}
@@ -0,0 +1,69 @@
+use secp256k1::{Keypair, Message, Secp256k1};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+// NIP-23 long-form content.
+pub const LONG_FORM_KIND: u32 = 30023;
+
+// A Nostr event. Unsigned events leave id, pubkey and sig out so they can be
+// signed by another client.
+#[derive(Serialize)]
+pub struct Event {
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub id: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub pubkey: String,
+    pub created_at: i64,
+    pub kind: u32,
+    pub tags: Vec<Vec<String>>,
+    pub content: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub sig: String,
+}
+
+impl Event {
+    pub fn long_form(created_at: i64, tags: Vec<Vec<String>>, content: String) -> Event {
+        Event {
+            id: String::new(),
+            pubkey: String::new(),
+            created_at,
+            kind: LONG_FORM_KIND,
+            tags,
+            content,
+            sig: String::new(),
+        }
+    }
+
+    // Fill in pubkey, id and sig from a hex encoded secret key.
+    pub fn sign(&mut self, secret_key: &str) -> Result<(), secp256k1::Error> {
+        let secp = Secp256k1::new();
+        let keypair = Keypair::from_seckey_str(&secp, secret_key.trim())?;
+        let (pubkey, _) = keypair.x_only_public_key();
+        self.pubkey = hex(&pubkey.serialize());
+
+        // The id is the hash of the event serialized as
+        // [0, pubkey, created_at, kind, tags, content] with no extra whitespace.
+        let serialized = serde_json::json!([
+            0,
+            self.pubkey,
+            self.created_at,
+            self.kind,
+            self.tags,
+            self.content,
+        ]).to_string();
+        let digest: [u8; 32] = Sha256::digest(serialized.as_bytes()).into();
+        self.id = hex(&digest);
+
+        let signature = secp.sign_schnorr_no_aux_rand(&Message::from_digest(digest), &keypair);
+        self.sig = hex(&signature.serialize());
+        Ok(())
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap()
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
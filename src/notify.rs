@@ -0,0 +1,145 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+use serde::Serialize;
+
+use crate::changes::ChangeSet;
+use crate::gemtext::Diagnostic;
+use crate::lock::hash_file;
+
+static WARNINGS: AtomicUsize = AtomicUsize::new(0);
+
+// Print a warning, counted for the build report.
+macro_rules! warning {
+    ($($arg:tt)*) => {{
+        $crate::notify::count_warning();
+        eprintln!("Warning: {}", format_args!($($arg)*));
+    }};
+}
+pub(crate) use warning;
+
+pub fn count_warning() {
+    WARNINGS.fetch_add(1, Ordering::Relaxed);
+}
+
+pub fn warnings() -> usize {
+    WARNINGS.load(Ordering::Relaxed)
+}
+
+// Print parser diagnostics as warnings about the given file.
+pub fn report_diagnostics(source_path: &Path, diagnostics: &[Diagnostic]) {
+    for d in diagnostics {
+        warning!("{}, line {} of the body: {}",
+            &source_path.to_string_lossy(), d.line, d.message);
+    }
+}
+
+// The hash of every file under the output roots, to tell which files a build
+// changed.
+pub type Snapshot = BTreeMap<PathBuf, String>;
+
+pub fn snapshot(roots: &[&Path]) -> Snapshot {
+    let mut snapshot = Snapshot::new();
+    for root in roots {
+        add_files(root, &mut snapshot);
+    }
+    snapshot
+}
+
+fn add_files(dir: &Path, snapshot: &mut Snapshot) {
+    let entries = match fs::read_dir(dir) {
+        Ok(e) => e,
+        Err(_) => return,
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            add_files(&path, snapshot);
+        } else if let Ok(hash) = hash_file(&path) {
+            snapshot.insert(path, hash);
+        }
+    }
+}
+
+// What an unattended build did, sent to a webhook as JSON or by email.
+#[derive(Clone, Debug, Serialize)]
+pub struct Report {
+    pub site: String,
+    pub duration_seconds: f64,
+    pub warnings: usize,
+    pub files_changed: usize,
+    pub changed: Vec<String>,
+    // The posts new or changed since the last build, when changes are tracked.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub posts: Option<ChangeSet>,
+}
+
+impl Report {
+    pub fn new(site: &str, duration: Duration, before: &Snapshot, after: &Snapshot, posts: Option<ChangeSet>) -> Report {
+        // Files that are new, changed or gone.
+        let mut changed: Vec<String> = after.iter()
+            .filter(|(path, hash)| before.get(*path) != Some(hash))
+            .map(|(path, _)| path)
+            .chain(before.keys().filter(|path| !after.contains_key(*path)))
+            .map(|path| path.to_string_lossy().into_owned())
+            .collect();
+        changed.sort();
+        Report {
+            site: site.to_string(),
+            duration_seconds: duration.as_secs_f64(),
+            warnings: warnings(),
+            files_changed: changed.len(),
+            changed,
+            posts,
+        }
+    }
+
+    pub fn summary(&self) -> String {
+        let mut text = format!("Built {} in {:.1}s with {} warning(s), {} file(s) changed.\n",
+            self.site, self.duration_seconds, self.warnings, self.files_changed);
+        if let Some(posts) = self.posts.as_ref().filter(|p| !p.is_empty()) {
+            text.push_str(&format!("\n{}", posts.summary()));
+        }
+        for path in &self.changed {
+            text.push_str(&format!("\n{}", path));
+        }
+        text
+    }
+}
+
+pub fn post_webhook(url: &str, report: &Report) -> Result<(), String> {
+    let agent = ureq::AgentBuilder::new()
+        .timeout(Duration::from_secs(30))
+        .build();
+    let body = serde_json::to_string(report).map_err(|e| e.to_string())?;
+    agent.post(url)
+        .set("Content-Type", "application/json")
+        .send_string(&body)
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+}
+
+// Hand the report to a sendmail compatible program, which reads the recipient
+// from the headers.
+pub fn send_email(sendmail: &str, to: &str, report: &Report) -> Result<(), String> {
+    let mut child = Command::new(sendmail)
+        .arg("-t")
+        .stdin(Stdio::piped())
+        .spawn()
+        .map_err(|e| e.to_string())?;
+    let message = format!("To: {}\nSubject: crosspub build of {}\n\n{}\n", to, report.site, report.summary());
+    child.stdin.take()
+        .ok_or("no stdin")?
+        .write_all(message.as_bytes())
+        .map_err(|e| e.to_string())?;
+    match child.wait() {
+        Ok(status) if status.success() => Ok(()),
+        Ok(status) => Err(format!("{} exited with {}", sendmail, status)),
+        Err(e) => Err(e.to_string()),
+    }
+}
@@ -0,0 +1,59 @@
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+
+use crate::config::Site;
+use crate::gemtext::TokenKind;
+use crate::post::Post;
+
+#[derive(Clone, Debug, Serialize)]
+pub struct LinkingPost {
+    pub title: String,
+    pub output_path: String,
+}
+
+// An external URL and the posts that link to it, newest first.
+#[derive(Clone, Debug, Serialize)]
+pub struct OutboundLink {
+    pub url: String,
+    pub posts: Vec<LinkingPost>,
+}
+
+// Every link in the posts that leaves the site, sorted by URL.
+pub fn collect(posts: &[Post], site: &Site) -> Vec<OutboundLink> {
+    let mut links: BTreeMap<&str, Vec<LinkingPost>> = BTreeMap::new();
+    for post in posts {
+        let urls = post.tokens.iter()
+            .filter(|t| matches!(t.kind, TokenKind::Link | TokenKind::Reference))
+            .map(|t| t.data.as_str())
+            .filter(|url| is_external(url, site));
+        for url in urls {
+            let linking = links.entry(url).or_default();
+            if linking.iter().any(|p| p.output_path == post.output_path) {
+                continue;
+            }
+            // Notes have no title, name them by their date instead.
+            let title = if post.title.is_empty() {
+                post.date.format("%B %e, %Y").to_string()
+            } else {
+                post.title.clone()
+            };
+            linking.push(LinkingPost { title, output_path: post.output_path.clone() });
+        }
+    }
+
+    links.into_iter()
+        .map(|(url, posts)| OutboundLink { url: url.to_string(), posts })
+        .collect()
+}
+
+// Absolute URLs that don't point back to the site's own host.
+fn is_external(url: &str, site: &Site) -> bool {
+    match url.split_once("://") {
+        Some((_, rest)) => {
+            let host = site.url.trim_end_matches('/');
+            host.is_empty() || !(rest == host || rest.starts_with(&format!("{}/", host)))
+        },
+        None => false,
+    }
+}
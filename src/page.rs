@@ -0,0 +1,76 @@
+use std::path::PathBuf;
+
+use serde::Serialize;
+
+use crate::frontmatter::{self, PageFrontmatter};
+use crate::gemtext::{gemini_body, select_output, GemtextToken, TokenKind, parse_gemtext_lossy};
+use crate::html::{generate_html_from_tokens, HtmlOptions};
+use crate::notify::report_diagnostics;
+
+// A standalone page like uses.gmi or contact.gmi, rendered with the generic
+// page template.
+#[derive(Clone, Default, Debug, Serialize)]
+pub struct Page {
+    pub title: String,
+    pub filename: String,
+    // Where the page is written relative to the output roots, without an
+    // extension.
+    pub output_path: String,
+    pub html_content: String,
+    pub gemini_content: String,
+    pub source_hash: String,
+    #[serde(skip)]
+    pub html_options: HtmlOptions,
+    #[serde(skip)]
+    pub tokens: Vec<GemtextToken>,
+}
+
+// A standalone page as navigation menus link to it.
+#[derive(Clone, Debug, Serialize)]
+pub struct PageLink {
+    pub title: String,
+    pub path: String,
+    pub gemini_path: String,
+}
+
+impl Page {
+    pub fn from_source(source_path: PathBuf, html_options: HtmlOptions) -> Page {
+        let lines = frontmatter::read_lines(&source_path);
+
+        // Frontmatter is optional for pages.
+        let (frontmatter, body_start): (PageFrontmatter, usize) =
+            frontmatter::parse_optional(&lines, &source_path);
+        let body = &lines[body_start..];
+        let (tokens, diagnostics) = parse_gemtext_lossy(&select_output(body, "html"));
+        report_diagnostics(&source_path, &diagnostics);
+
+        // Without a title in the frontmatter fall back to the first heading,
+        // then the filename.
+        let filename = source_path.file_stem()
+            .map(|s| s.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let title = match frontmatter.title {
+            Some(t) => t,
+            None => tokens.iter()
+                .find(|t| t.kind == TokenKind::Heading)
+                .map(|t| t.data.clone())
+                .unwrap_or_else(|| filename.clone()),
+        };
+
+        let html_options = HtmlOptions {
+            raw_html: frontmatter.raw_html.unwrap_or(html_options.raw_html),
+            reflow: frontmatter.reflow.unwrap_or(html_options.reflow),
+            ..html_options
+        };
+        Page {
+            title,
+            output_path: frontmatter::output_path(frontmatter.output_path.as_deref(), filename.clone()),
+            filename,
+            html_content: generate_html_from_tokens(&tokens, &html_options),
+            html_options,
+            gemini_content: gemini_body(body).join("\n"),
+            tokens,
+            ..Default::default()
+        }
+    }
+}
@@ -1,23 +1,235 @@
-use std::io::{BufRead, BufReader};
-use std::fs::OpenOptions;
 use std::path::PathBuf;
 use std::process::exit;
 
-use chrono::{NaiveDate, NaiveDateTime};
+use chrono::{DateTime, FixedOffset, NaiveDate, NaiveDateTime, TimeZone, Utc};
+use chrono::format::{Item, StrftimeItems};
 use serde::Serialize;
 use toml;
 
-use crate::frontmatter::Frontmatter;
-use crate::gemtext::parse_gemtext;
+use crate::authors::AuthorLink;
+use crate::config::Site;
+use crate::export::{page_path, page_url};
+use crate::frontmatter::{self, Frontmatter};
+use crate::gemtext::{gemini_body, replace_footnote_markers, select_output, GemtextToken, TokenKind, parse_gemtext_lossy};
+use crate::html::{generate_html_from_tokens, HtmlOptions};
+use crate::notify::report_diagnostics;
+use crate::meta::Meta;
+use crate::structured::Structured;
+use crate::summary::{first_paragraph, xml_escape};
+use crate::timezone::SiteTimezone;
+use crate::toc::{self, Toc};
+
+#[derive(Clone, Copy, Debug, Default, Serialize, Eq, PartialEq, Ord, PartialOrd)]
+#[serde(rename_all = "lowercase")]
+pub enum PostKind {
+    #[default]
+    Article,
+    Note,
+    Link,
+    Recipe,
+    Review,
+}
+
+impl PostKind {
+    pub const ALL: [PostKind; 5] = [
+        PostKind::Article,
+        PostKind::Note,
+        PostKind::Link,
+        PostKind::Recipe,
+        PostKind::Review,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            PostKind::Article => "article",
+            PostKind::Note => "note",
+            PostKind::Link => "link",
+            PostKind::Recipe => "recipe",
+            PostKind::Review => "review",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<PostKind> {
+        PostKind::ALL.iter().find(|k| k.name() == name).copied()
+    }
+}
+
+// Post dates are a day like 2022-01-31, or a day and time like
+// 2022-01-31 14:30.
+pub fn parse_date(date: &str) -> Result<NaiveDateTime, &'static str> {
+    if date.len() == 10 {
+        NaiveDate::parse_from_str(date, "%Y-%m-%d")
+            .map(|d| d.and_hms(0, 0, 0))
+            .map_err(|_| "Date formatted incorrectly")
+    } else if date.len() > 10 {
+        NaiveDateTime::parse_from_str(date, "%Y-%m-%d %H:%M")
+            .map_err(|_| "Date and time formatted incorrectly")
+    } else {
+        Err("Date too short")
+    }
+}
+
+// A language is a tag like "en" or "pt-BR".
+pub fn valid_lang(lang: &str) -> bool {
+    lang.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+}
+
+// How the date in front of the slug of post filenames is formatted.
+pub const DEFAULT_DATE_PREFIX: &str = "%Y%m%d";
+
+// The filename of a note without a slug is its date and time alone, which
+// keeps this format whatever the date prefix is: a shorter prefix or none
+// would give notes written close together the same filename.
+const NOTE_FILENAME_FORMAT: &str = "%Y%m%d_%H%M";
+
+// A date prefix has to be a valid strftime format that stays in one path
+// component. An empty one leaves the date out.
+pub fn valid_date_prefix(format: &str) -> bool {
+    !format.contains('/') && !StrftimeItems::new(format).any(|i| matches!(i, Item::Error))
+}
+
+// The filename of a post with a slug, with the date formatted by date_prefix
+// in front unless that's empty.
+pub fn post_filename(date: &NaiveDateTime, slug: &str, date_prefix: &str) -> String {
+    if date_prefix.is_empty() {
+        slug.to_string()
+    } else {
+        format!("{}_{}", date.format(date_prefix), slug)
+    }
+}
+
+// The tokens of a post split before each ## heading, along with the text of
+// the heading each part starts with. A part before the first heading has an
+// empty title, and is left out when there's nothing in it.
+fn split_at_subheadings(tokens: &[GemtextToken]) -> Vec<(String, Vec<GemtextToken>)> {
+    let mut parts: Vec<(String, Vec<GemtextToken>)> = vec![(String::new(), Vec::new())];
+    for token in tokens {
+        if token.kind == TokenKind::SubHeading {
+            parts.push((token.data.clone(), Vec::new()));
+        }
+        if let Some((_, part)) = parts.last_mut() {
+            part.push(token.clone());
+        }
+    }
+    if parts[0].1.is_empty() {
+        parts.remove(0);
+    }
+    parts
+}
+
+// The tokens of one page of a paginated post. Footnotes and references are
+// defined once for the whole post, usually at its end, so they're taken out
+// of the part they're in and every page that cites them gets all of them at
+// its end, numbered as they would be on a single page.
+fn page_tokens(tokens: &[GemtextToken], part: Vec<GemtextToken>) -> Vec<GemtextToken> {
+    let footnotes: Vec<&GemtextToken> = tokens.iter().filter(|t| t.kind == TokenKind::Footnote).collect();
+    let references: Vec<&GemtextToken> = tokens.iter().filter(|t| t.kind == TokenKind::Reference).collect();
+    let mut has_footnotes = part.iter().any(|t| t.kind == TokenKind::Footnote);
+    let mut has_references = part.iter().any(|t| t.kind == TokenKind::Reference);
+    let texts = part.iter().filter(|t| matches!(t.kind,
+        TokenKind::Text | TokenKind::UnorderedList | TokenKind::OrderedList | TokenKind::Blockquote));
+    for t in texts {
+        replace_footnote_markers(&t.data, |label| {
+            has_footnotes |= footnotes.iter().any(|f| f.extra == label);
+            None
+        });
+        has_references |= cites_reference(&t.data, references.len());
+    }
+
+    let mut page: Vec<GemtextToken> = part.into_iter()
+        .filter(|t| t.kind != TokenKind::Footnote && t.kind != TokenKind::Reference)
+        .collect();
+    if has_references {
+        page.extend(references.into_iter().cloned());
+    }
+    if has_footnotes {
+        page.extend(footnotes.into_iter().cloned());
+    }
+    page
+}
+
+// Whether text has a citation like [2] of one of reference_count references.
+fn cites_reference(text: &str, reference_count: usize) -> bool {
+    text.split('[').skip(1).any(|after| {
+        after.split_once(']')
+            .filter(|(n, _)| !n.is_empty() && n.chars().all(|c| c.is_ascii_digit()))
+            .and_then(|(n, _)| n.parse::<usize>().ok())
+            .is_some_and(|n| n >= 1 && n <= reference_count)
+    })
+}
 
 #[derive(Clone, Debug, Serialize, Eq, PartialEq, Ord, PartialOrd)]
 pub struct Post {
     pub title: String,
+    pub slug: String,
     pub filename: String,
+    // Where the post is written relative to the output roots, without an
+    // extension. posts/{filename} unless the frontmatter says otherwise.
+    pub output_path: String,
+    // The HTML and Gemini pages as full URLs and as paths from the root of
+    // the host, set once the site is known.
+    pub url: String,
+    pub path: String,
+    pub gemini_url: String,
+    pub gemini_path: String,
+    pub kind: PostKind,
+    pub is_link: bool,
+    pub link_url: String,
     #[serde(with = "cp_date_format")]
     pub date: NaiveDateTime,
+    // The date as a moment in the site's timezone, for sorting and feeds.
+    #[serde(skip)]
+    pub published: DateTime<FixedOffset>,
+    // When the post was last changed as YYYY-MM-DD, empty unless the
+    // frontmatter has an updated date, and as a moment for feeds.
+    pub updated: String,
+    #[serde(skip)]
+    pub updated_at: Option<DateTime<FixedOffset>>,
+    // A plain text summary from the frontmatter or the first paragraph, and
+    // the same escaped for HTML and feeds.
+    pub summary: String,
+    pub summary_html: String,
+    // Only the key from the frontmatter until the config is applied.
+    pub author: AuthorLink,
     pub html_content: String,
     pub gemini_content: String,
+    pub structured_html: String,
+    pub structured_gemini: String,
+    pub json_ld: String,
+    pub draft: bool,
+    // Posts with a weight can be highlighted on the index, heavier first.
+    pub featured_weight: u32,
+    pub tags: Vec<String>,
+    // A language tag like "de", empty unless the frontmatter or its [meta]
+    // table sets one.
+    pub lang: String,
+    pub source_hash: String,
+    pub meta: Meta,
+    // Files from the includes directory only this post's HTML page loads.
+    pub styles: Vec<String>,
+    pub scripts: Vec<String>,
+    // Templates get the table of contents through PostContext.
+    #[serde(skip)]
+    pub toc: Toc,
+    #[serde(skip)]
+    pub html_options: HtmlOptions,
+    #[serde(skip)]
+    pub frontmatter: String,
+    #[serde(skip)]
+    pub tokens: Vec<GemtextToken>,
+    // The HTML split into pages at each ## heading, empty unless the
+    // frontmatter asks for it with paginate_by_heading and there's more than
+    // one page.
+    #[serde(skip)]
+    pub html_pages: Vec<HtmlPage>,
+}
+
+// A page of a post split at its headings, titled by the heading it starts
+// with or by the post for the first.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Ord, PartialOrd)]
+pub struct HtmlPage {
+    pub title: String,
+    pub html: String,
 }
 
 mod cp_date_format {
@@ -40,75 +252,243 @@ impl Default for Post {
     fn default() -> Post {
         Post {
             title: String::new(),
+            slug: String::new(),
             filename: String::new(),
+            output_path: String::new(),
+            url: String::new(),
+            path: String::new(),
+            gemini_url: String::new(),
+            gemini_path: String::new(),
+            kind: PostKind::Article,
+            is_link: false,
+            link_url: String::new(),
             date: NaiveDate::from_ymd(1980, 1, 1).and_hms(0, 0, 0),
+            published: Utc.ymd(1980, 1, 1).and_hms(0, 0, 0).into(),
+            updated: String::new(),
+            updated_at: None,
+            summary: String::new(),
+            summary_html: String::new(),
+            author: AuthorLink::default(),
             html_content: String::new(),
             gemini_content: String::new(),
+            structured_html: String::new(),
+            structured_gemini: String::new(),
+            json_ld: String::new(),
+            draft: false,
+            featured_weight: 0,
+            tags: Vec::new(),
+            lang: String::new(),
+            source_hash: String::new(),
+            meta: Meta::default(),
+            styles: Vec::new(),
+            scripts: Vec::new(),
+            toc: Toc::default(),
+            html_options: HtmlOptions::default(),
+            frontmatter: String::new(),
+            tokens: Vec::new(),
+            html_pages: Vec::new(),
         }
     }
 }
 
 impl Post {
-    pub fn from_source(source_path: PathBuf) -> Post {
-        // Read from source .gmi file.
-        let source = OpenOptions::new().read(true).open(&source_path);
-        let source = match source {
-            Ok(s) => s,
-            Err(_) => {
-                eprintln!("Error: Could not open file {}",
-                    &source_path.to_string_lossy());
+    // The frontmatter can override raw_html and reflow in html_options. The date is read
+    // as being in the given timezone and put in front of the slug in the
+    // filename as date_prefix says.
+    pub fn from_source(source_path: PathBuf, html_options: HtmlOptions, timezone: SiteTimezone, date_prefix: &str) -> Post {
+        let lines = frontmatter::read_lines(&source_path);
+
+        // Load frontmatter, which sits between the first two --- lines.
+        let frontmatter_end = match lines.iter().skip(1).position(|l| l.trim() == "---") {
+            Some(i) if lines[0].trim() == "---" => i + 1,
+            _ => {
+                eprintln!("Error: Missing frontmatter in {}", &source_path.to_string_lossy());
                 exit(1);
-            },
+            }
         };
-        let reader = BufReader::new(source);
-        let lines: Vec<String> = reader.lines().map(|l| l.unwrap()).collect();
-
-        // Load frontmatter.
-        let frontmatter: Frontmatter = match toml::from_str(&lines[1..=3].join("\n")) {
+        let frontmatter: Frontmatter = match toml::from_str(&lines[1..frontmatter_end].join("\n")) {
             Ok(fm) => fm,
             Err(_) => {
-                eprintln!("Error: date formatted in {}", &source_path.to_string_lossy());
+                eprintln!("Error: Frontmatter formatted incorrectly in {}", &source_path.to_string_lossy());
                 exit(1);
             }
         };
+        let kind = match &frontmatter.kind {
+            None => PostKind::Article,
+            Some(k) => match PostKind::from_name(k) {
+                Some(kind) => kind,
+                None => {
+                    eprintln!("Error: Unknown post kind \"{}\" in {}", k, &source_path.to_string_lossy());
+                    exit(1);
+                }
+            },
+        };
+
+        // Notes are allowed to skip the title and slug.
+        if kind != PostKind::Note && (frontmatter.title.is_none() || frontmatter.slug.is_none()) {
+            eprintln!("Error: Missing title or slug in {}", &source_path.to_string_lossy());
+            exit(1);
+        }
+        let mut meta = frontmatter.meta.unwrap_or_default();
+        let lang = frontmatter.lang.or_else(|| meta.lang.clone()).unwrap_or_default();
+        if !valid_lang(&lang) {
+            eprintln!("Error: Language \"{}\" in {} should be a tag like \"en\" or \"pt-BR\"", lang, &source_path.to_string_lossy());
+            exit(1);
+        }
+        // The Gemini .meta sidecar gets the language too.
+        if !lang.is_empty() {
+            meta.lang = Some(lang.clone());
+        }
+        if kind == PostKind::Link && frontmatter.url.is_none() {
+            eprintln!("Error: Link post without a url in {}", &source_path.to_string_lossy());
+            exit(1);
+        }
 
-        let mut post = Post::default();
-        post.title = frontmatter.title;
-        if frontmatter.date.len() == 10 {
-            // let temp_date = NaiveDate::parse_from_str(&)
-            post.date = match NaiveDate::parse_from_str(&frontmatter.date, "%Y-%m-%d") {
-                Ok(t) => {
-                    t.and_hms(0, 0, 0)
-                },
-                Err(_) => {
-                    eprintln!("Error: Date formatted incorrectly in {}",
-                        &source_path.to_string_lossy());
+        let date = match parse_date(&frontmatter.date) {
+            Ok(d) => d,
+            Err(e) => {
+                eprintln!("Error: {} in {}", e, &source_path.to_string_lossy());
+                exit(1);
+            }
+        };
+        let updated = match frontmatter.updated.as_deref().map(parse_date) {
+            Some(Ok(d)) => Some(d),
+            Some(Err(e)) => {
+                eprintln!("Error: Updated {} in {}", e.to_lowercase(), &source_path.to_string_lossy());
+                exit(1);
+            },
+            None => None,
+        };
+        let iso_date = format!("{}", date.format("%Y-%m-%d"));
+        let title = frontmatter.title.unwrap_or_default();
+        let structured = match kind {
+            PostKind::Recipe => match &frontmatter.recipe {
+                Some(r) => r.render(&title, &iso_date),
+                None => {
+                    eprintln!("Error: Recipe post without a [recipe] table in {}", &source_path.to_string_lossy());
                     exit(1);
                 }
-            };
-        } else if frontmatter.date.len() > 10 {
-            post.date = match NaiveDateTime::parse_from_str(&frontmatter.date, "%Y-%m-%d %H:%M") {
-                Ok(p) => p,
-                Err(_) => {
-                    eprintln!("Error: Date and time formatted incorrectly in {}",
-                        &source_path.to_string_lossy());
+            },
+            PostKind::Review => match &frontmatter.review {
+                Some(r) => r.render(&title, &iso_date),
+                None => {
+                    eprintln!("Error: Review post without a [review] table in {}", &source_path.to_string_lossy());
                     exit(1);
                 }
-            };
-        } else {
-            eprintln!("Error: Date too short in {}",
-                &source_path.to_string_lossy());
-            exit(1);
-        }
-        post.filename = format!("{}_{}", post.date.format("%Y%m%d"), frontmatter.slug);
+            },
+            _ => Structured::default(),
+        };
+
+        let filename = match &frontmatter.slug {
+            Some(slug) => post_filename(&date, slug, date_prefix),
+            None => format!("{}", date.format(NOTE_FILENAME_FORMAT)),
+        };
+        let mut post = Post {
+            title,
+            slug: frontmatter.slug.clone().unwrap_or_default(),
+            output_path: frontmatter::output_path(frontmatter.output_path.as_deref(), format!("posts/{}", filename)),
+            filename,
+            kind,
+            is_link: kind == PostKind::Link,
+            link_url: frontmatter.url.unwrap_or_default(),
+            structured_html: structured.html,
+            structured_gemini: structured.gemini,
+            json_ld: structured.json_ld,
+            draft: frontmatter.draft.unwrap_or(false),
+            featured_weight: frontmatter.featured_weight.unwrap_or(0),
+            tags: frontmatter.tags,
+            lang,
+            meta,
+            styles: frontmatter.styles,
+            scripts: frontmatter.scripts,
+            html_options: HtmlOptions {
+                raw_html: frontmatter.raw_html.unwrap_or(html_options.raw_html),
+                reflow: frontmatter.reflow.unwrap_or(html_options.reflow),
+                ..html_options
+            },
+            frontmatter: lines[1..frontmatter_end].join("\n"),
+            date,
+            published: timezone.resolve(&date),
+            updated: updated.map(|d| d.format("%Y-%m-%d").to_string()).unwrap_or_default(),
+            updated_at: updated.map(|d| timezone.resolve(&d)),
+            summary: frontmatter.summary.map(|s| s.trim().to_string()).unwrap_or_default(),
+            author: AuthorLink {
+                key: frontmatter.author.unwrap_or_default(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
 
         // Generate content bodies for HTML and Gemini.
-        let tokens = parse_gemtext(&lines[5..]);
-        for token in tokens {
-            post.html_content.push_str(&token.as_html())
+        let body = &lines[frontmatter_end + 1..];
+        let (mut tokens, diagnostics) = parse_gemtext_lossy(&select_output(body, "html"));
+        report_diagnostics(&source_path, &diagnostics);
+        if frontmatter.toc.unwrap_or(false) {
+            tokens = toc::anchor_headings(&tokens);
+            post.toc = toc::render(&toc::entries(&tokens));
         }
-        post.gemini_content = lines[5..].join("\n");
+        if frontmatter.paginate_by_heading.unwrap_or(false) {
+            let parts = split_at_subheadings(&tokens);
+            if parts.len() > 1 {
+                post.html_pages = parts.into_iter()
+                    .map(|(title, _)| HtmlPage {
+                        title: if title.is_empty() { post.title.clone() } else { title },
+                        html: String::new(),
+                    })
+                    .collect();
+            }
+        }
+        post.tokens = tokens;
+        post.render_html(generate_html_from_tokens);
+        post.gemini_content = gemini_body(body).join("\n");
 
         post
     }
+
+    // Render the HTML of the post from its tokens, and of each of its pages
+    // when it's paginated. Passes that change the tokens once every post is
+    // loaded render again through this, so the pages get their changes too.
+    pub fn render_html(&mut self, mut render: impl FnMut(&[GemtextToken], &HtmlOptions) -> String) {
+        self.html_content = render(&self.tokens, &self.html_options);
+        if self.html_pages.is_empty() {
+            return;
+        }
+        // Only the first page opens with the lede.
+        let later_pages = HtmlOptions { lede: false, ..self.html_options.clone() };
+        let parts = split_at_subheadings(&self.tokens);
+        for (i, (page, (_, part))) in self.html_pages.iter_mut().zip(parts).enumerate() {
+            page.html = render(&page_tokens(&self.tokens, part), if i == 0 { &self.html_options } else { &later_pages });
+        }
+    }
+
+    // Where the HTML pages of the post are written, without an extension. The
+    // first page is the post's own output path, the others add -2, -3 and so
+    // on to it.
+    pub fn html_page_output_paths(&self) -> Vec<String> {
+        (1..=self.html_pages.len().max(1))
+            .map(|n| if n == 1 { self.output_path.clone() } else { format!("{}-{}", self.output_path, n) })
+            .collect()
+    }
+
+    // The last time the post changed, its updated date if it has a later
+    // one.
+    pub fn last_changed(&self) -> DateTime<FixedOffset> {
+        self.updated_at.map_or(self.published, |u| u.max(self.published))
+    }
+
+    // Posts without a summary in their frontmatter get the start of their
+    // first paragraph, or none with a max_length of 0.
+    pub fn set_summary(&mut self, max_length: usize) {
+        if self.summary.is_empty() && max_length > 0 {
+            self.summary = first_paragraph(&self.tokens, max_length);
+        }
+        self.summary_html = xml_escape(&self.summary);
+    }
+
+    pub fn set_urls(&mut self, site: &Site) {
+        self.url = page_url(site, "http", &format!("{}.html", self.output_path));
+        self.path = page_path(site, &format!("{}.html", self.output_path));
+        self.gemini_url = page_url(site, "gemini", &format!("{}.gmi", self.output_path));
+        self.gemini_path = page_path(site, &format!("{}.gmi", self.output_path));
+    }
 }
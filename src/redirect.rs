@@ -0,0 +1,76 @@
+use std::collections::BTreeMap;
+
+use crate::config::Site;
+use crate::export::page_path;
+
+// An old URL and the path it moved to, both relative to the HTML root.
+pub struct Redirect {
+    pub from: String,
+    pub to: String,
+}
+
+// Parse a redirects.toml, a flat table of old URLs to new slugs.
+pub fn parse(contents: &str) -> Result<BTreeMap<String, String>, toml::de::Error> {
+    toml::from_str(contents)
+}
+
+// Turn an old URL into a path relative to the HTML root. Full URLs lose their
+// host and the tilde part of the path is dropped.
+pub fn old_path(url: &str, username: &str) -> String {
+    let path = match url.split_once("://") {
+        Some((_, rest)) => rest.find('/').map(|i| &rest[i..]).unwrap_or("/"),
+        None => url,
+    };
+    let path = path.split(['?', '#']).next().unwrap_or("");
+    let path = path.trim_start_matches('/');
+    let tilde = format!("~{}", username);
+    let path = path.strip_prefix(&tilde).unwrap_or(path);
+    path.trim_start_matches('/').to_string()
+}
+
+// The file to write for a redirect, directory style URLs get an index.html.
+pub fn page_file(path: &str) -> String {
+    let last = path.rsplit('/').next().unwrap_or("");
+    if path.is_empty() || path.ends_with('/') {
+        format!("{}index.html", path)
+    } else if !last.contains('.') {
+        format!("{}/index.html", path)
+    } else {
+        path.to_string()
+    }
+}
+
+pub fn redirect_page(target: &str) -> String {
+    format!("<!DOCTYPE html>\n\
+        <html>\n\
+        <head>\n\
+        <meta charset=\"utf-8\">\n\
+        <title>Redirecting…</title>\n\
+        <link rel=\"canonical\" href=\"{0}\">\n\
+        <meta http-equiv=\"refresh\" content=\"0; url={0}\">\n\
+        </head>\n\
+        <body>\n\
+        <p>This page has moved to <a href=\"{0}\">{0}</a>.</p>\n\
+        </body>\n\
+        </html>\n", target)
+}
+
+// A map for nginx's http block. Use it with
+// `if ($crosspub_redirect) { return 301 $crosspub_redirect; }`.
+pub fn nginx_map(redirects: &[Redirect], site: &Site) -> String {
+    let mut map = String::from("# Generated by crosspub for moved pages\n\
+        map $uri $crosspub_redirect {\n    default \"\";\n");
+    for r in redirects {
+        map.push_str(&format!("    {} {};\n", page_path(site, &r.from), page_path(site, &r.to)));
+    }
+    map.push_str("}\n");
+    map
+}
+
+pub fn caddy_map(redirects: &[Redirect], site: &Site) -> String {
+    let mut map = String::from("# Generated by crosspub for moved pages\n");
+    for r in redirects {
+        map.push_str(&format!("redir {} {} permanent\n", page_path(site, &r.from), page_path(site, &r.to)));
+    }
+    map
+}
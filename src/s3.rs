@@ -0,0 +1,280 @@
+use std::collections::BTreeMap;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+
+use crate::config;
+use crate::deploy::Deploy;
+use crate::store::{ContentStore, LocalStore};
+
+// Content types for the files a build writes, by extension. Anything else is
+// uploaded as application/octet-stream unless the config maps it, and served
+// as such by crosspub serve.
+pub const CONTENT_TYPES: [(&str, &str); 18] = [
+    ("html", "text/html; charset=utf-8"),
+    ("css", "text/css; charset=utf-8"),
+    ("xml", "application/atom+xml; charset=utf-8"),
+    ("js", "text/javascript; charset=utf-8"),
+    ("json", "application/json"),
+    ("txt", "text/plain; charset=utf-8"),
+    ("gmi", "text/gemini; charset=utf-8"),
+    ("png", "image/png"),
+    ("jpg", "image/jpeg"),
+    ("jpeg", "image/jpeg"),
+    ("gif", "image/gif"),
+    ("webp", "image/webp"),
+    ("svg", "image/svg+xml"),
+    ("ico", "image/x-icon"),
+    ("pdf", "application/pdf"),
+    ("mp3", "audio/mpeg"),
+    ("woff2", "font/woff2"),
+    ("woff", "font/woff"),
+];
+
+// Pages and feeds change with every post, the rest rarely does.
+const PAGE_EXTENSIONS: [&str; 2] = ["html", "xml"];
+
+// The manifest of the last deploy, stored next to the objects.
+const DEFAULT_MANIFEST: &str = ".crosspub-manifest";
+
+// Publishes the HTML output to an S3 compatible bucket. Files under the HTML
+// root become objects under the prefix, everything else is written to disk as
+// usual. Objects are only uploaded when their contents differ from the
+// manifest in the bucket, so it works the same from any machine.
+pub struct S3Store {
+    root: PathBuf,
+    bucket: String,
+    endpoint: String,
+    host: String,
+    region: String,
+    prefix: String,
+    cache_control: Option<String>,
+    page_cache_control: Option<String>,
+    content_types: BTreeMap<String, String>,
+    delete_removed: bool,
+    access_key: String,
+    secret_key: String,
+    session_token: Option<String>,
+    manifest_key: String,
+    agent: ureq::Agent,
+    deploy: Mutex<Deploy>,
+}
+
+impl S3Store {
+    // Credentials come from the usual AWS_ACCESS_KEY_ID and
+    // AWS_SECRET_ACCESS_KEY environment variables rather than the config.
+    pub fn new(config: &config::S3, root: &Path) -> Result<S3Store, String> {
+        let bucket = config.bucket.clone().ok_or("[s3] needs a bucket")?;
+        let access_key = std::env::var("AWS_ACCESS_KEY_ID").map_err(|_| "AWS_ACCESS_KEY_ID isn't set")?;
+        let secret_key = std::env::var("AWS_SECRET_ACCESS_KEY").map_err(|_| "AWS_SECRET_ACCESS_KEY isn't set")?;
+        let region = config.region.clone().unwrap_or_else(|| "us-east-1".to_string());
+        let endpoint = config.endpoint.clone()
+            .unwrap_or_else(|| format!("https://s3.{}.amazonaws.com", region))
+            .trim_end_matches('/')
+            .to_string();
+        let host = match endpoint.split_once("://") {
+            Some((_, rest)) => rest.split('/').next().unwrap_or_default().to_string(),
+            None => return Err(format!("endpoint {} should start with https://", endpoint)),
+        };
+        let mut content_types: BTreeMap<String, String> = CONTENT_TYPES.iter()
+            .map(|(e, t)| (e.to_string(), t.to_string()))
+            .collect();
+        content_types.extend(config.content_types.clone());
+
+        let mut store = S3Store {
+            root: root.to_path_buf(),
+            bucket,
+            endpoint,
+            host,
+            region,
+            prefix: config.prefix.clone().unwrap_or_default().trim_matches('/').to_string(),
+            cache_control: config.cache_control.clone(),
+            page_cache_control: config.page_cache_control.clone().or_else(|| config.cache_control.clone()),
+            content_types,
+            delete_removed: config.delete_removed.unwrap_or(false),
+            access_key,
+            secret_key,
+            session_token: std::env::var("AWS_SESSION_TOKEN").ok(),
+            manifest_key: String::new(),
+            agent: ureq::AgentBuilder::new()
+                .timeout(Duration::from_secs(60))
+                .build(),
+            deploy: Mutex::new(Deploy::default()),
+        };
+        store.manifest_key = store.prefixed(config.manifest.as_deref().unwrap_or(DEFAULT_MANIFEST));
+        let manifest = match store.get(&store.manifest_key) {
+            Ok(m) => Some(String::from_utf8_lossy(&m).into_owned()),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+            Err(e) => return Err(format!("could not read the manifest {}: {}", store.manifest_key, e)),
+        };
+        store.deploy = Mutex::new(Deploy::new(manifest.as_deref()));
+        Ok(store)
+    }
+
+    // The key of the object for a path under the HTML root.
+    fn key(&self, path: &Path) -> Option<String> {
+        let relative = path.strip_prefix(&self.root).ok()?;
+        let relative: Vec<String> = relative.components()
+            .map(|c| c.as_os_str().to_string_lossy().into_owned())
+            .collect();
+        Some(self.prefixed(&relative.join("/")))
+    }
+
+    fn prefixed(&self, path: &str) -> String {
+        if self.prefix.is_empty() {
+            path.to_string()
+        } else {
+            format!("{}/{}", self.prefix, path)
+        }
+    }
+
+    fn headers(&self, key: &str) -> Vec<(String, String)> {
+        let extension = key.rsplit_once('.').map(|(_, e)| e.to_lowercase()).unwrap_or_default();
+        let content_type = self.content_types.get(&extension).map(String::as_str)
+            .unwrap_or("application/octet-stream");
+        let mut headers = vec![("content-type".to_string(), content_type.to_string())];
+        let cache_control = if PAGE_EXTENSIONS.contains(&extension.as_str()) {
+            &self.page_cache_control
+        } else {
+            &self.cache_control
+        };
+        if let Some(c) = cache_control {
+            headers.push(("cache-control".to_string(), c.clone()));
+        }
+        headers
+    }
+
+    fn request(&self, method: &str, key: &str, mut headers: Vec<(String, String)>, body: &[u8]) -> io::Result<ureq::Response> {
+        let uri = format!("/{}/{}", self.bucket, uri_encode(key));
+        let payload_hash = hex(&Sha256::digest(body));
+        let now = Utc::now();
+        let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+        let date = now.format("%Y%m%d").to_string();
+
+        headers.push(("host".to_string(), self.host.clone()));
+        headers.push(("x-amz-content-sha256".to_string(), payload_hash.clone()));
+        headers.push(("x-amz-date".to_string(), amz_date.clone()));
+        if let Some(token) = &self.session_token {
+            headers.push(("x-amz-security-token".to_string(), token.clone()));
+        }
+        headers.sort();
+
+        // AWS Signature Version 4, over the method, path, headers and body.
+        let signed_headers: Vec<&str> = headers.iter().map(|(n, _)| n.as_str()).collect();
+        let signed_headers = signed_headers.join(";");
+        let canonical_headers: String = headers.iter()
+            .map(|(n, v)| format!("{}:{}\n", n, v.trim()))
+            .collect();
+        let canonical_request = format!("{}\n{}\n\n{}\n{}\n{}",
+            method, uri, canonical_headers, signed_headers, payload_hash);
+        let scope = format!("{}/{}/s3/aws4_request", date, self.region);
+        let string_to_sign = format!("AWS4-HMAC-SHA256\n{}\n{}\n{}",
+            amz_date, scope, hex(&Sha256::digest(canonical_request.as_bytes())));
+        let mut signing_key = hmac(format!("AWS4{}", self.secret_key).as_bytes(), date.as_bytes());
+        for part in [self.region.as_str(), "s3", "aws4_request"] {
+            signing_key = hmac(&signing_key, part.as_bytes());
+        }
+        let signature = hex(&hmac(&signing_key, string_to_sign.as_bytes()));
+
+        let mut request = self.agent.request(method, &format!("{}{}", self.endpoint, uri))
+            .set("Authorization", &format!("AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+                self.access_key, scope, signed_headers, signature));
+        for (name, value) in headers.iter().filter(|(n, _)| n != "host") {
+            request = request.set(name, value);
+        }
+        match request.send_bytes(body) {
+            Ok(r) => Ok(r),
+            Err(ureq::Error::Status(404, _)) => Err(io::Error::from(io::ErrorKind::NotFound)),
+            Err(e) => Err(io::Error::other(e.to_string())),
+        }
+    }
+
+    fn get(&self, key: &str) -> io::Result<Vec<u8>> {
+        let response = self.request("GET", key, Vec::new(), &[])?;
+        let mut contents = Vec::new();
+        response.into_reader().read_to_end(&mut contents)?;
+        Ok(contents)
+    }
+
+    fn delete(&self, key: &str) -> io::Result<()> {
+        match self.request("DELETE", key, Vec::new(), &[]) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+            _ => {},
+        }
+        self.deploy.lock().unwrap().deleted(key);
+        Ok(())
+    }
+}
+
+impl ContentStore for S3Store {
+    fn write(&self, path: &Path, contents: &[u8]) -> io::Result<()> {
+        let key = match self.key(path) {
+            Some(k) => k,
+            None => return LocalStore.write(path, contents),
+        };
+        if !self.deploy.lock().unwrap().write(&key, contents) {
+            return Ok(());
+        }
+        self.request("PUT", &key, self.headers(&key), contents)?;
+        self.deploy.lock().unwrap().uploaded(&key);
+        Ok(())
+    }
+
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        let key = match self.key(path) {
+            Some(k) => k,
+            None => return LocalStore.read(path),
+        };
+        self.get(&key)
+    }
+
+    fn remove(&self, path: &Path) -> io::Result<()> {
+        match self.key(path) {
+            Some(key) => self.delete(&key),
+            None => LocalStore.remove(path),
+        }
+    }
+
+    // Delete what earlier deploys uploaded and this one didn't write, when
+    // asked to, and leave the manifest for the next deploy.
+    fn finish(&self) -> io::Result<()> {
+        if self.delete_removed {
+            let stale = self.deploy.lock().unwrap().stale();
+            for key in stale {
+                self.delete(&key)?;
+            }
+        }
+        let deploy = self.deploy.lock().unwrap();
+        let headers = vec![("content-type".to_string(), "text/plain; charset=utf-8".to_string())];
+        self.request("PUT", &self.manifest_key, headers, deploy.manifest().as_bytes())?;
+        print!("Deployed to s3://{}/{}: {}", self.bucket, self.prefix, deploy.summary());
+        Ok(())
+    }
+}
+
+// Percent-encode a key for the request path, keeping the slashes.
+fn uri_encode(key: &str) -> String {
+    let mut encoded = String::new();
+    for b in key.bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => encoded.push(b as char),
+            _ => encoded.push_str(&format!("%{:02X}", b)),
+        }
+    }
+    encoded
+}
+
+fn hmac(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC takes keys of any length");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
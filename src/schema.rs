@@ -0,0 +1,19 @@
+use schemars::{schema_for, Schema};
+
+use crate::config::Config;
+use crate::crosspub::SchemaKind;
+use crate::frontmatter::{AboutFrontmatter, Frontmatter, PageFrontmatter, TopicFrontmatter};
+
+// A JSON Schema for config.toml or the frontmatter of a kind of source, for
+// editors to validate and complete them with.
+pub fn schema(kind: &SchemaKind) -> String {
+    let (mut schema, title): (Schema, &str) = match kind {
+        SchemaKind::Config => (schema_for!(Config), "crosspub config.toml"),
+        SchemaKind::Post => (schema_for!(Frontmatter), "crosspub post frontmatter"),
+        SchemaKind::Topic => (schema_for!(TopicFrontmatter), "crosspub topic frontmatter"),
+        SchemaKind::Page => (schema_for!(PageFrontmatter), "crosspub page frontmatter"),
+        SchemaKind::About => (schema_for!(AboutFrontmatter), "crosspub about frontmatter"),
+    };
+    schema.insert("title".to_string(), title.into());
+    serde_json::to_string_pretty(&schema).unwrap_or_default()
+}
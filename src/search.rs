@@ -0,0 +1,96 @@
+use std::fs;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize)]
+pub struct SearchEntry {
+    pub path: String,
+    pub title: String,
+    pub text: String,
+}
+
+pub fn write_index(path: &Path, entries: &[SearchEntry]) -> std::io::Result<()> {
+    // Plain data, serializing it can't fail.
+    fs::write(path, serde_json::to_string(entries).unwrap())
+}
+
+// A CGI script that hands the query over to this crosspub binary.
+pub fn write_cgi_script(path: &Path, crosspub: &Path, index: &Path) -> std::io::Result<()> {
+    let script = format!("#!/bin/sh\n\
+        # Generated by crosspub, searches the posts and topics of this capsule.\n\
+        exec \"{}\" search-cgi --index \"{}\"\n",
+        crosspub.to_string_lossy(),
+        index.to_string_lossy());
+    fs::write(path, script)?;
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(path, fs::Permissions::from_mode(0o755))?;
+    }
+    Ok(())
+}
+
+// Build the full Gemini response for a CGI request. Without a query the
+// visitor is asked for one.
+pub fn cgi_response(index: &Path, query_string: &str) -> String {
+    if query_string.is_empty() {
+        return "10 Search posts and topics\r\n".to_string();
+    }
+    let entries: Vec<SearchEntry> = match fs::read_to_string(index) {
+        Ok(c) => serde_json::from_str(&c).unwrap_or_default(),
+        Err(_) => return "42 Search index unavailable\r\n".to_string(),
+    };
+
+    let query = percent_decode(query_string).replace(['\r', '\n'], " ");
+    let words: Vec<String> = query.split_whitespace().map(|w| w.to_lowercase()).collect();
+    let mut results: Vec<(usize, &SearchEntry)> = entries.iter()
+        .filter_map(|e| {
+            let title = e.title.to_lowercase();
+            let text = e.text.to_lowercase();
+            if !words.iter().all(|w| title.contains(w.as_str()) || text.contains(w.as_str())) {
+                return None;
+            }
+            // Title matches count for more than body matches.
+            let score = words.iter()
+                .map(|w| title.matches(w.as_str()).count() * 10 + text.matches(w.as_str()).count())
+                .sum();
+            Some((score, e))
+        })
+        .collect();
+    results.sort_by_key(|(score, _)| std::cmp::Reverse(*score));
+
+    let mut response = format!("20 text/gemini\r\n# Search results for \"{}\"\n\n", query);
+    if results.is_empty() {
+        response.push_str("No results.\n");
+    }
+    for (_, entry) in results {
+        response.push_str(&format!("=> {} {}\n", entry.path, entry.title));
+    }
+    response
+}
+
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'%' if i + 2 < bytes.len() => {
+                let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).unwrap_or("");
+                match u8::from_str_radix(hex, 16) {
+                    Ok(b) => {
+                        decoded.push(b);
+                        i += 3;
+                        continue;
+                    },
+                    Err(_) => decoded.push(b'%'),
+                }
+            },
+            b => decoded.push(b),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
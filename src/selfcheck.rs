@@ -0,0 +1,117 @@
+use crate::gemini::generate_gemini_from_tokens;
+use crate::gemtext::{parse_gemtext_lossy, GemtextToken, Span, TokenKind};
+
+// The conversion contract between the outputs: every token renders to HTML,
+// and serializing the tokens back to gemtext and parsing that again gives
+// the same tokens. Returns a description of the first broken token.
+pub fn check(tokens: &[GemtextToken]) -> Result<(), String> {
+    for token in tokens {
+        let empty = token.data.is_empty() && token.extra.is_empty();
+        if token.as_html().is_empty() && !empty {
+            return Err(format!("{}{:?} \"{}\" renders to no HTML", at(token), token.kind, token.data));
+        }
+    }
+
+    let expected = comparable(tokens);
+    let gemini = generate_gemini_from_tokens(tokens);
+    let lines: Vec<String> = gemini.lines().map(String::from).collect();
+    let (reparsed, _) = parse_gemtext_lossy(&lines);
+    let found = comparable(&reparsed);
+
+    for (i, token) in expected.iter().enumerate() {
+        match found.get(i) {
+            Some(t) if t == token => {},
+            Some(t) => return Err(format!("{}{:?} \"{}\" comes back as {:?} \"{}\"",
+                at(&tokens[i]), token.kind, token.data, t.kind, t.data)),
+            None => return Err(format!("{}{:?} \"{}\" is lost", at(&tokens[i]), token.kind, token.data)),
+        }
+    }
+    if let Some(t) = found.get(expected.len()) {
+        return Err(format!("{:?} \"{}\" appears from nowhere", t.kind, t.data));
+    }
+    Ok(())
+}
+
+// Where a token came from, to start a message with.
+fn at(token: &GemtextToken) -> String {
+    if token.span.is_empty() {
+        String::new()
+    } else {
+        format!("line {} of the body \"{}\": ", token.span.line, token.span.source)
+    }
+}
+
+// Heading anchors are added after parsing and aren't part of the gemtext, so
+// they're left out of the comparison, as are the lines tokens came from.
+fn comparable(tokens: &[GemtextToken]) -> Vec<GemtextToken> {
+    tokens.iter()
+        .map(|t| match t.kind {
+            TokenKind::Heading | TokenKind::SubHeading | TokenKind::SubSubHeading => GemtextToken {
+                extra: String::new(),
+                span: Span::default(),
+                ..t.clone()
+            },
+            _ => GemtextToken {
+                span: Span::default(),
+                ..t.clone()
+            },
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gemtext::parse_gemtext;
+    use crate::html::{generate_html_from_tokens, HtmlOptions};
+    use proptest::prelude::*;
+
+    // A line of gemtext, mostly made of the markers lines start with.
+    fn line() -> impl Strategy<Value = String> {
+        let marker = prop::sample::select(vec![
+            "", "# ", "## ", "### ", "* ", "> ", ">> ", "=> ", "```", "1. ", "[^1]: ", "\\", "%% ",
+        ]);
+        (marker, "[ -~\u{e9}\u{65e5}\u{1f600}]{0,24}").prop_map(|(m, text)| format!("{}{}", m, text))
+    }
+
+    fn lines() -> impl Strategy<Value = Vec<String>> {
+        prop::collection::vec(line(), 0..24)
+    }
+
+    proptest! {
+        #[test]
+        fn gemini_round_trips(lines in lines()) {
+            let tokens = parse_gemtext(&lines);
+            let gemini = generate_gemini_from_tokens(&tokens);
+            // Not lines(), which drops a last empty line and with it an empty
+            // text token.
+            let relines: Vec<String> = if tokens.is_empty() {
+                Vec::new()
+            } else {
+                gemini.split('\n').map(String::from).collect()
+            };
+            let reparsed = parse_gemtext(&relines);
+            prop_assert_eq!(comparable(&reparsed), comparable(&tokens));
+        }
+
+        #[test]
+        fn html_never_panics(lines in lines(), raw_html: bool, inline_formatting: bool, ordered_lists: bool,
+            heading_links: bool, smart_typography: bool, reflow: bool, sections: bool, lede: bool) {
+            let options = HtmlOptions {
+                raw_html,
+                inline_formatting,
+                ordered_lists,
+                heading_ids: true,
+                heading_links,
+                smart_typography,
+                reflow,
+                sections,
+                lede,
+                inline_images: true,
+                gemini_proxy: Some("https://proxy.example/".to_string()),
+                ..Default::default()
+            };
+            generate_html_from_tokens(&parse_gemtext(&lines), &options);
+        }
+    }
+}
@@ -0,0 +1,287 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Component, Path, PathBuf};
+use std::process::Command;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use base64::Engine;
+use sha1::{Digest, Sha1};
+
+use crate::config::Config;
+use crate::crosspub::Args;
+use crate::export::page_path;
+use crate::s3::CONTENT_TYPES;
+
+// Where served pages connect to hear about rebuilds.
+const LIVE_RELOAD_PATH: &str = "/.crosspub/live-reload";
+
+// Added to the end of every HTML page served, never to the files on disk.
+const LIVE_RELOAD_SCRIPT: &str = r#"<script>
+(function connect() {
+  var socket = new WebSocket("ws://" + location.host + "/.crosspub/live-reload");
+  socket.onmessage = function () { location.reload(); };
+  socket.onclose = function () { setTimeout(connect, 1000); };
+})();
+</script>
+"#;
+
+// How often sources and templates are checked for changes.
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+// Pages connected to the live reload socket.
+type Clients = Arc<Mutex<Vec<TcpStream>>>;
+
+// Build the site, serve its HTML output on localhost and build it again
+// whenever a source, the config or a template changes, reloading the pages
+// open in a browser. When only templates changed just their pages are
+// rendered again. Builds run as separate crosspub processes, so one that
+// fails leaves the server running. Returns false if the server can't start.
+pub fn run(config: &Config, args: &Args, config_path: &Path, templates: Vec<PathBuf>, port: u16) -> bool {
+    if config.s3.enabled == Some(true) {
+        eprintln!("Error: crosspub serve previews the local HTML output, which [s3] replaces. Use a profile without it.");
+        return false;
+    }
+    let listener = match TcpListener::bind(("127.0.0.1", port)) {
+        Ok(l) => l,
+        Err(e) => {
+            eprintln!("Error: Could not listen on port {}: {}", port, e);
+            return false;
+        },
+    };
+
+    let dir = args.dir.clone().unwrap_or_else(|| PathBuf::from("."));
+    let root = PathBuf::from(&config.site.html_root);
+    let mut watched = vec![dir.clone(), config_path.to_path_buf()];
+    watched.extend(templates.iter().cloned());
+    // Output written inside the site directory mustn't trigger a rebuild.
+    let ignored = vec![root.clone(), PathBuf::from(&config.site.gemini_root)];
+
+    build(args, config_path, &dir, &[]);
+    let clients: Clients = Arc::new(Mutex::new(Vec::new()));
+    let watcher_clients = clients.clone();
+    let watcher_args = args.clone();
+    let watcher_config = config_path.to_path_buf();
+    thread::spawn(move || {
+        let mut last = snapshot(&watched, &ignored);
+        loop {
+            thread::sleep(POLL_INTERVAL);
+            let current = snapshot(&watched, &ignored);
+            if current == last {
+                continue;
+            }
+            let changed = changed_templates(&last, &current, &templates).unwrap_or_default();
+            if changed.is_empty() {
+                println!("Sources changed, rebuilding");
+            } else {
+                println!("Templates changed, rendering their pages again");
+            }
+            last = current;
+            if build(&watcher_args, &watcher_config, &dir, &changed) {
+                reload(&watcher_clients);
+            }
+        }
+    });
+
+    let prefix = page_path(&config.site, "").trim_end_matches('/').to_string();
+    println!("Serving {} at http://127.0.0.1:{}{}/", root.to_string_lossy(), port, prefix);
+    for stream in listener.incoming().flatten() {
+        let root = root.clone();
+        let prefix = prefix.clone();
+        let clients = clients.clone();
+        thread::spawn(move || {
+            let _ = handle(stream, &root, &prefix, &clients);
+        });
+    }
+    true
+}
+
+// Build the HTML output with the settings crosspub serve was started with,
+// offline so previews never ping hubs or send reports. Given templates, only
+// their pages are rendered.
+fn build(args: &Args, config_path: &Path, dir: &Path, templates: &[String]) -> bool {
+    let exe = match std::env::current_exe() {
+        Ok(e) => e,
+        Err(_) => {
+            eprintln!("Error: Could not find the crosspub executable to build with");
+            return false;
+        },
+    };
+    let mut command = Command::new(exe);
+    command.args(["--only", "html", "--offline", "--wait", "--config"]).arg(config_path);
+    if let Some(d) = &args.data_dir {
+        command.arg("--data-dir").arg(d);
+    }
+    if let Some(p) = &args.profile {
+        command.args(["--profile", p]);
+    }
+    if args.locked {
+        command.arg("--locked");
+    }
+    for template in templates {
+        command.args(["--rerender", template]);
+    }
+    command.arg(dir);
+    match command.status() {
+        Ok(s) if s.success() => true,
+        _ => {
+            eprintln!("Build failed, serving the last output");
+            false
+        },
+    }
+}
+
+// The modification time of every file under the watched paths, minus hidden
+// files and the output roots.
+fn snapshot(watched: &[PathBuf], ignored: &[PathBuf]) -> BTreeMap<PathBuf, SystemTime> {
+    let mut files = BTreeMap::new();
+    let mut pending: Vec<PathBuf> = watched.to_vec();
+    while let Some(path) = pending.pop() {
+        if ignored.iter().any(|i| !i.as_os_str().is_empty() && path.starts_with(i)) {
+            continue;
+        }
+        if path.is_dir() {
+            for entry in fs::read_dir(&path).into_iter().flatten().flatten() {
+                if !entry.file_name().to_string_lossy().starts_with('.') {
+                    pending.push(entry.path());
+                }
+            }
+        } else if let Ok(modified) = fs::metadata(&path).and_then(|m| m.modified()) {
+            files.insert(path, modified);
+        }
+    }
+    files
+}
+
+// The files that changed between two snapshots as paths under templates/,
+// like html/post.html, or None if anything besides templates changed.
+fn changed_templates(last: &BTreeMap<PathBuf, SystemTime>, current: &BTreeMap<PathBuf, SystemTime>, templates: &[PathBuf]) -> Option<Vec<String>> {
+    let removed = last.keys().filter(|p| !current.contains_key(*p));
+    let changed = current.iter().filter(|(p, t)| last.get(*p) != Some(*t)).map(|(p, _)| p);
+    removed.chain(changed)
+        .map(|path| {
+            let relative = templates.iter().find_map(|t| path.strip_prefix(t).ok())?;
+            let parts: Vec<String> = relative.components()
+                .map(|c| c.as_os_str().to_string_lossy().into_owned())
+                .collect();
+            Some(parts.join("/"))
+        })
+        .collect()
+}
+
+fn handle(mut stream: TcpStream, root: &Path, prefix: &str, clients: &Clients) -> io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    let mut headers = BTreeMap::new();
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 || line.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            headers.insert(name.trim().to_lowercase(), value.trim().to_string());
+        }
+    }
+
+    let mut parts = request_line.split_whitespace();
+    let (method, target) = (parts.next().unwrap_or_default(), parts.next().unwrap_or("/"));
+    let path = target.split(['?', '#']).next().unwrap_or("/");
+    if path == LIVE_RELOAD_PATH {
+        if let Some(key) = headers.get("sec-websocket-key") {
+            write!(stream, "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: {}\r\n\r\n",
+                websocket_accept(key))?;
+            clients.lock().unwrap().push(stream);
+            return Ok(());
+        }
+    }
+    if method != "GET" {
+        return respond(&mut stream, "405 Method Not Allowed", "text/plain; charset=utf-8", b"Method not allowed\n");
+    }
+    if path == "/" {
+        return write!(stream, "HTTP/1.1 302 Found\r\nLocation: {}/\r\nContent-Length: 0\r\nConnection: close\r\n\r\n", prefix);
+    }
+
+    let file = match resolve(root, path.strip_prefix(prefix).unwrap_or(path)) {
+        Some(f) => f,
+        None => return respond(&mut stream, "404 Not Found", "text/plain; charset=utf-8", b"Not found\n"),
+    };
+    let extension = file.extension().map(|e| e.to_string_lossy().to_lowercase()).unwrap_or_default();
+    let content_type = CONTENT_TYPES.iter()
+        .find(|(e, _)| *e == extension)
+        .map(|(_, t)| *t)
+        .unwrap_or("application/octet-stream");
+    let mut body = fs::read(&file)?;
+    if extension == "html" {
+        body = inject_live_reload(&String::from_utf8_lossy(&body)).into_bytes();
+    }
+    respond(&mut stream, "200 OK", content_type, &body)
+}
+
+// The file under the root for a request path, with index.html for
+// directories. Paths leaving the root aren't served.
+fn resolve(root: &Path, path: &str) -> Option<PathBuf> {
+    let relative = PathBuf::from(percent_decode(path.trim_start_matches('/')));
+    if !relative.components().all(|c| matches!(c, Component::Normal(_))) {
+        return None;
+    }
+    let file = root.join(relative);
+    let file = if file.is_dir() { file.join("index.html") } else { file };
+    if file.is_file() {
+        Some(file)
+    } else {
+        None
+    }
+}
+
+fn respond(stream: &mut TcpStream, status: &str, content_type: &str, body: &[u8]) -> io::Result<()> {
+    write!(stream, "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nCache-Control: no-store\r\nConnection: close\r\n\r\n",
+        status, content_type, body.len())?;
+    stream.write_all(body)
+}
+
+fn inject_live_reload(html: &str) -> String {
+    match html.rfind("</body>") {
+        Some(i) => format!("{}{}{}", &html[..i], LIVE_RELOAD_SCRIPT, &html[i..]),
+        None => format!("{}{}", html, LIVE_RELOAD_SCRIPT),
+    }
+}
+
+// Tell every connected page to reload, forgetting the ones that went away.
+fn reload(clients: &Clients) {
+    // A single unmasked text frame.
+    let frame = [&[0x81, 6][..], b"reload"].concat();
+    clients.lock().unwrap().retain_mut(|c| c.write_all(&frame).is_ok());
+}
+
+fn percent_decode(path: &str) -> String {
+    let bytes = path.as_bytes();
+    let mut decoded = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes.get(i + 1..i + 3)
+            .and_then(|h| std::str::from_utf8(h).ok())
+            .and_then(|h| u8::from_str_radix(h, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(b)) => {
+                decoded.push(b);
+                i += 3;
+            },
+            (b, _) => {
+                decoded.push(b);
+                i += 1;
+            },
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+// The Sec-WebSocket-Accept answer to a handshake key, the base64 of the
+// SHA-1 of the key and a fixed GUID.
+fn websocket_accept(key: &str) -> String {
+    let digest = Sha1::digest(format!("{}258EAFA5-E914-47DA-95CA-C5AB0DC85B11", key).as_bytes());
+    base64::engine::general_purpose::STANDARD.encode(digest)
+}
@@ -0,0 +1,109 @@
+use std::fs;
+use std::path::Path;
+
+use rusqlite::{params, Connection};
+
+use crate::gemtext::{GemtextToken, TokenKind};
+use crate::post::Post;
+use crate::topic::Topic;
+
+const SCHEMA: &str = "
+CREATE TABLE posts (
+    filename TEXT PRIMARY KEY,
+    title TEXT NOT NULL,
+    kind TEXT NOT NULL,
+    date TEXT NOT NULL,
+    link_url TEXT,
+    html_url TEXT NOT NULL,
+    gemini_url TEXT NOT NULL,
+    gemini_content TEXT NOT NULL,
+    html_content TEXT NOT NULL
+);
+CREATE TABLE topics (
+    filename TEXT PRIMARY KEY,
+    title TEXT NOT NULL,
+    html_url TEXT NOT NULL,
+    gemini_url TEXT NOT NULL,
+    gemini_content TEXT NOT NULL,
+    html_content TEXT NOT NULL
+);
+CREATE TABLE tags (
+    page TEXT NOT NULL,
+    tag TEXT NOT NULL
+);
+CREATE TABLE links (
+    page TEXT NOT NULL,
+    url TEXT NOT NULL,
+    name TEXT
+);
+CREATE VIRTUAL TABLE search USING fts5(page, title, body);
+";
+
+// Write a fresh database indexing every post and topic. Pages are identified
+// by their filename in every table.
+pub fn write_index(path: &Path, posts: &[Post], topics: &[Topic]) -> rusqlite::Result<()> {
+    if path.exists() {
+        let _ = fs::remove_file(path);
+    }
+    let mut db = Connection::open(path)?;
+    db.execute_batch(SCHEMA)?;
+
+    let tx = db.transaction()?;
+    for post in posts {
+        let link_url = if post.is_link { Some(&post.link_url) } else { None };
+        tx.execute(
+            "INSERT INTO posts VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            params![
+                post.filename,
+                post.title,
+                post.kind.name(),
+                post.date.format("%Y-%m-%d %H:%M").to_string(),
+                link_url,
+                post.url.clone(),
+                post.gemini_url.clone(),
+                post.gemini_content,
+                post.html_content,
+            ],
+        )?;
+        insert_links(&tx, &post.filename, &post.tokens)?;
+        tx.execute(
+            "INSERT INTO search VALUES (?1, ?2, ?3)",
+            params![post.filename, post.title, post.gemini_content],
+        )?;
+    }
+    for topic in topics {
+        tx.execute(
+            "INSERT INTO topics VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                topic.filename,
+                topic.title,
+                topic.url.clone(),
+                topic.gemini_url.clone(),
+                topic.gemini_content,
+                topic.html_content,
+            ],
+        )?;
+        insert_links(&tx, &topic.filename, &topic.tokens)?;
+        for tag in &topic.tags {
+            tx.execute("INSERT INTO tags VALUES (?1, ?2)", params![topic.filename, tag])?;
+        }
+        tx.execute(
+            "INSERT INTO search VALUES (?1, ?2, ?3)",
+            params![topic.filename, topic.title, topic.gemini_content],
+        )?;
+    }
+    tx.commit()
+}
+
+fn insert_links(db: &Connection, page: &str, tokens: &[GemtextToken]) -> rusqlite::Result<()> {
+    let links = tokens.iter()
+        .filter(|t| t.kind == TokenKind::Link || t.kind == TokenKind::Reference);
+    for link in links {
+        let name = if link.extra.is_empty() { None } else { Some(&link.extra) };
+        db.execute(
+            "INSERT INTO links VALUES (?1, ?2, ?3)",
+            params![page, link.data, name],
+        )?;
+    }
+    Ok(())
+}
@@ -0,0 +1,87 @@
+use std::collections::BTreeMap;
+
+use chrono::Datelike;
+use serde::Serialize;
+
+use crate::gemtext::{GemtextToken, TokenKind};
+use crate::post::Post;
+use crate::topic::Topic;
+
+// The widest bar drawn in a histogram.
+const BAR_WIDTH: usize = 40;
+
+// One row of a histogram. line is the whole row ready to go in a
+// preformatted block, with the labels padded to the same width.
+#[derive(Clone, Debug, Serialize)]
+pub struct Bar {
+    pub label: String,
+    pub count: usize,
+    pub bar: String,
+    pub line: String,
+}
+
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct Stats {
+    pub total_posts: usize,
+    pub total_notes: usize,
+    pub total_topics: usize,
+    pub total_words: usize,
+    pub years: Vec<Bar>,
+    pub tags: Vec<Bar>,
+}
+
+pub fn collect(posts: &[Post], notes: &[Post], topics: &[Topic]) -> Stats {
+    let mut years: BTreeMap<i32, usize> = BTreeMap::new();
+    for post in posts.iter().chain(notes) {
+        *years.entry(post.date.year()).or_insert(0) += 1;
+    }
+    let years = years.into_iter()
+        .rev()
+        .map(|(y, n)| (y.to_string(), n))
+        .collect();
+
+    let mut tags: BTreeMap<&str, usize> = BTreeMap::new();
+    for tag in topics.iter().flat_map(|t| &t.tags) {
+        *tags.entry(tag.as_str()).or_insert(0) += 1;
+    }
+    let mut tags: Vec<(String, usize)> = tags.into_iter()
+        .map(|(t, n)| (t.to_string(), n))
+        .collect();
+    // Most used first, ties alphabetically.
+    tags.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+
+    Stats {
+        total_posts: posts.len(),
+        total_notes: notes.len(),
+        total_topics: topics.len(),
+        total_words: posts.iter().chain(notes).map(|p| word_count(&p.tokens)).sum(),
+        years: histogram(years),
+        tags: histogram(tags),
+    }
+}
+
+// Words of prose, link names included but not URLs or preformatted text.
+pub fn word_count(tokens: &[GemtextToken]) -> usize {
+    tokens.iter()
+        .map(|t| match t.kind {
+            TokenKind::Link | TokenKind::Reference => t.extra.split_whitespace().count(),
+            TokenKind::PreFormattedText => 0,
+            _ => t.data.split_whitespace().count(),
+        })
+        .sum()
+}
+
+fn histogram(counts: Vec<(String, usize)>) -> Vec<Bar> {
+    let max = counts.iter().map(|(_, n)| *n).max().unwrap_or(0);
+    let label_width = counts.iter().map(|(l, _)| l.chars().count()).max().unwrap_or(0);
+    counts.into_iter()
+        .map(|(label, count)| {
+            // Every non-zero count gets at least one mark.
+            let width = (count * BAR_WIDTH / max.max(1)).max(1);
+            let bar = "#".repeat(width);
+            let padding = " ".repeat(label_width - label.chars().count());
+            let line = format!("{}{} {} {}", label, padding, bar, count);
+            Bar { label, count, bar, line }
+        })
+        .collect()
+}
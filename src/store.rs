@@ -0,0 +1,96 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+// Where a build puts its outputs. Paths are the ones the files would have on
+// disk, under the output roots, whatever the store does with them.
+pub trait ContentStore {
+    // Write a file, creating the directories it's in.
+    fn write(&self, path: &Path, contents: &[u8]) -> io::Result<()>;
+
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>>;
+
+    fn remove(&self, path: &Path) -> io::Result<()>;
+
+    // Copy a file from the local disk, like an attachment or the CSS.
+    fn copy_from(&self, source: &Path, path: &Path) -> io::Result<()> {
+        self.write(path, &fs::read(source)?)
+    }
+
+    // Called once a build has written everything.
+    fn finish(&self) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn read_to_string(&self, path: &Path) -> io::Result<String> {
+        String::from_utf8(self.read(path)?)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+}
+
+// Outputs written to the local disk, what a normal build does.
+pub struct LocalStore;
+
+impl ContentStore for LocalStore {
+    fn write(&self, path: &Path, contents: &[u8]) -> io::Result<()> {
+        create_parent(path)?;
+        fs::write(path, contents)
+    }
+
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        fs::read(path)
+    }
+
+    fn remove(&self, path: &Path) -> io::Result<()> {
+        fs::remove_file(path)
+    }
+
+    fn copy_from(&self, source: &Path, path: &Path) -> io::Result<()> {
+        create_parent(path)?;
+        fs::copy(source, path).map(|_| ())
+    }
+}
+
+fn create_parent(path: &Path) -> io::Result<()> {
+    match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() && !parent.exists() => fs::create_dir_all(parent),
+        _ => Ok(()),
+    }
+}
+
+// Outputs kept in memory, so a build can run without touching the output
+// roots. Files not written by the build are read from the disk.
+#[derive(Default)]
+pub struct MemoryStore {
+    files: Mutex<BTreeMap<PathBuf, Option<Vec<u8>>>>,
+}
+
+impl MemoryStore {
+    // Every file the build wrote, with its contents, and the ones it removed
+    // as None.
+    pub fn files(&self) -> BTreeMap<PathBuf, Option<Vec<u8>>> {
+        self.files.lock().unwrap().clone()
+    }
+}
+
+impl ContentStore for MemoryStore {
+    fn write(&self, path: &Path, contents: &[u8]) -> io::Result<()> {
+        self.files.lock().unwrap().insert(path.to_path_buf(), Some(contents.to_vec()));
+        Ok(())
+    }
+
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        match self.files.lock().unwrap().get(path) {
+            Some(Some(contents)) => Ok(contents.clone()),
+            Some(None) => Err(io::Error::from(io::ErrorKind::NotFound)),
+            None => fs::read(path),
+        }
+    }
+
+    fn remove(&self, path: &Path) -> io::Result<()> {
+        self.files.lock().unwrap().insert(path.to_path_buf(), None);
+        Ok(())
+    }
+}
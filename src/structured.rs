@@ -0,0 +1,134 @@
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+#[derive(Clone, Default, Deserialize, JsonSchema)]
+pub struct Recipe {
+    #[serde(default)]
+    pub ingredients: Vec<String>,
+    #[serde(default)]
+    pub steps: Vec<String>,
+    // Times are in minutes.
+    pub prep_time: Option<u32>,
+    pub cook_time: Option<u32>,
+    pub servings: Option<String>,
+}
+
+#[derive(Clone, Default, Deserialize, JsonSchema)]
+pub struct Review {
+    pub item: String,
+    pub item_type: Option<String>,
+    pub rating: f32,
+    pub best_rating: Option<f32>,
+}
+
+// The formatted section and schema.org data for a structured post.
+#[derive(Default)]
+pub struct Structured {
+    pub html: String,
+    pub gemini: String,
+    pub json_ld: String,
+}
+
+impl Recipe {
+    pub fn render(&self, title: &str, date: &str) -> Structured {
+        let mut html = String::from("<section class=\"recipe\">\n");
+        let mut gemini = String::new();
+
+        let mut details = Vec::new();
+        if let Some(p) = self.prep_time {
+            details.push(format!("Prep time: {} minutes", p));
+        }
+        if let Some(c) = self.cook_time {
+            details.push(format!("Cook time: {} minutes", c));
+        }
+        if let Some(s) = &self.servings {
+            details.push(format!("Serves: {}", s));
+        }
+        if !details.is_empty() {
+            html.push_str(&format!("<p>{}</p>\n", details.join(" · ")));
+            gemini.push_str(&format!("{}\n\n", details.join("\n")));
+        }
+
+        html.push_str("<h2>Ingredients</h2>\n<ul>\n");
+        gemini.push_str("## Ingredients\n");
+        for ingredient in &self.ingredients {
+            html.push_str(&format!("<li>{}</li>\n", ingredient));
+            gemini.push_str(&format!("* {}\n", ingredient));
+        }
+        html.push_str("</ul>\n<h2>Steps</h2>\n<ol>\n");
+        gemini.push_str("\n## Steps\n");
+        for (i, step) in self.steps.iter().enumerate() {
+            html.push_str(&format!("<li>{}</li>\n", step));
+            gemini.push_str(&format!("{}. {}\n", i + 1, step));
+        }
+        html.push_str("</ol>\n</section>\n");
+
+        let mut data = json!({
+            "@context": "https://schema.org",
+            "@type": "Recipe",
+            "name": title,
+            "datePublished": date,
+            "recipeIngredient": self.ingredients,
+            "recipeInstructions": self.steps.iter()
+                .map(|s| json!({ "@type": "HowToStep", "text": s }))
+                .collect::<Vec<Value>>(),
+        });
+        if let Some(p) = self.prep_time {
+            data["prepTime"] = json!(format!("PT{}M", p));
+        }
+        if let Some(c) = self.cook_time {
+            data["cookTime"] = json!(format!("PT{}M", c));
+        }
+        if let Some(s) = &self.servings {
+            data["recipeYield"] = json!(s);
+        }
+
+        Structured {
+            html,
+            gemini,
+            json_ld: script_safe(&data),
+        }
+    }
+}
+
+impl Review {
+    pub fn render(&self, title: &str, date: &str) -> Structured {
+        let best = self.best_rating.unwrap_or(5.0);
+        let filled = self.rating.round().clamp(0.0, best) as usize;
+        let stars = format!("{}{}", "★".repeat(filled), "☆".repeat((best as usize).saturating_sub(filled)));
+
+        let html = format!("<section class=\"review\">\n<p><strong>{}</strong>: \
+            <span title=\"{} out of {}\">{}</span> ({}/{})</p>\n</section>\n",
+            self.item, self.rating, best, stars, self.rating, best);
+        let gemini = format!("Review of {}\nRating: {} ({}/{})\n", self.item, stars, self.rating, best);
+
+        let data = json!({
+            "@context": "https://schema.org",
+            "@type": "Review",
+            "name": title,
+            "datePublished": date,
+            "itemReviewed": {
+                "@type": self.item_type.clone().unwrap_or_else(|| "Thing".to_string()),
+                "name": self.item,
+            },
+            "reviewRating": {
+                "@type": "Rating",
+                "ratingValue": self.rating,
+                "bestRating": best,
+            },
+        });
+
+        Structured {
+            html,
+            gemini,
+            json_ld: script_safe(&data),
+        }
+    }
+}
+
+// JSON-LD is embedded in a <script> tag, so make sure nothing in it can close
+// the tag early.
+fn script_safe(data: &Value) -> String {
+    data.to_string().replace("</", "<\\/")
+}
@@ -0,0 +1,65 @@
+use crate::gemtext::{reflow_paragraphs, GemtextToken, TokenKind};
+use crate::wikilink;
+
+// The text of a post's paragraphs, lists and quotes, cut off at a word
+// boundary once it reaches max_length characters. Wikilinks are replaced by
+// their labels.
+pub fn summary_text(tokens: &[GemtextToken], max_length: usize) -> String {
+    let text = tokens.iter()
+        .filter(|t| matches!(t.kind, TokenKind::Text | TokenKind::UnorderedList | TokenKind::OrderedList | TokenKind::Blockquote))
+        .map(|t| strip_tags(&wikilink::labels(&t.data)))
+        .collect::<Vec<String>>()
+        .join(" ");
+    let words: Vec<&str> = text.split_whitespace().collect();
+
+    let mut summary = String::new();
+    let mut truncated = false;
+    for word in words {
+        let length = summary.chars().count() + word.chars().count() + 1;
+        if length > max_length {
+            truncated = true;
+            break;
+        }
+        if !summary.is_empty() {
+            summary.push(' ');
+        }
+        summary.push_str(word);
+    }
+    if truncated {
+        summary = summary.trim_end_matches(|c: char| c.is_ascii_punctuation()).to_string();
+        summary.push('…');
+    }
+    summary
+}
+
+// The first paragraph of a post as plain text, cut off like summary_text.
+// Hard wrapped lines are joined into one paragraph first.
+pub fn first_paragraph(tokens: &[GemtextToken], max_length: usize) -> String {
+    reflow_paragraphs(tokens).iter()
+        .filter(|t| t.kind == TokenKind::Text)
+        .map(|t| summary_text(std::slice::from_ref(t), max_length))
+        .find(|s| !s.is_empty())
+        .unwrap_or_default()
+}
+
+fn strip_tags(text: &str) -> String {
+    let mut stripped = String::new();
+    let mut in_tag = false;
+    for c in text.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' if in_tag => in_tag = false,
+            _ if !in_tag => stripped.push(c),
+            _ => {},
+        }
+    }
+    stripped
+}
+
+// Also safe in HTML text and attributes.
+pub fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
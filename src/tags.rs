@@ -0,0 +1,60 @@
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+
+use crate::config::Site;
+use crate::export::page_path;
+use crate::post::Post;
+use crate::toc::heading_anchor;
+
+// A tag as posts link to it, its page being tags/{slug} in both outputs.
+#[derive(Clone, Debug, Serialize)]
+pub struct TagLink {
+    pub name: String,
+    pub slug: String,
+    pub path: String,
+    pub gemini_path: String,
+}
+
+// A tag and the posts using it, newest first.
+#[derive(Clone, Serialize)]
+pub struct Tag {
+    #[serde(flatten)]
+    pub link: TagLink,
+    pub count: usize,
+    pub posts: Vec<Post>,
+}
+
+pub fn link(name: &str, site: &Site) -> TagLink {
+    let slug = heading_anchor(name);
+    TagLink {
+        name: name.to_string(),
+        path: page_path(site, &format!("tags/{}.html", slug)),
+        gemini_path: page_path(site, &format!("tags/{}.gmi", slug)),
+        slug,
+    }
+}
+
+// Every tag used by the posts, sorted by name. Tags that only differ in case
+// or punctuation, like "Rust" and "rust", share a page named after the first
+// one seen.
+pub fn collect(posts: &[Post], site: &Site) -> Vec<Tag> {
+    let mut tags: BTreeMap<String, Tag> = BTreeMap::new();
+    for post in posts {
+        for name in &post.tags {
+            let link = link(name, site);
+            let tag = tags.entry(link.slug.clone()).or_insert_with(|| Tag {
+                link,
+                count: 0,
+                posts: Vec::new(),
+            });
+            if !tag.posts.iter().any(|p| p.output_path == post.output_path) {
+                tag.posts.push(post.clone());
+                tag.count += 1;
+            }
+        }
+    }
+    let mut tags: Vec<Tag> = tags.into_values().collect();
+    tags.sort_by_key(|t| t.link.name.to_lowercase());
+    tags
+}
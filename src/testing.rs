@@ -0,0 +1,174 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::config::Config;
+use crate::crosspub::{Args, CrossPub};
+
+// 2024-01-01 12:00 UTC, when a site builds unless given another clock.
+pub const DEFAULT_CLOCK: i64 = 1_704_110_400;
+
+// Numbers the scratch directories of the sites built by one process.
+static SITES: AtomicUsize = AtomicUsize::new(0);
+
+// A site built from the sources under a root directory, with the templates
+// in a data directory, at a fixed time and in UTC. Outputs are kept in memory
+// by a dry run rather than written to the output roots, so building never
+// touches anything outside the root.
+pub struct TestSite {
+    root: PathBuf,
+    // Whether the root is a scratch directory to remove once the site is
+    // dropped.
+    scratch: bool,
+    templates: PathBuf,
+    clock: i64,
+    config: Config,
+}
+
+impl TestSite {
+    // A site in a new scratch directory, with the templates crosspub ships
+    // and the default clock.
+    pub fn new() -> TestSite {
+        let root = std::env::temp_dir().join(format!("crosspub-test-{}-{}",
+            std::process::id(), SITES.fetch_add(1, Ordering::SeqCst)));
+        let _ = fs::remove_dir_all(&root);
+        let mut site = TestSite::at(root);
+        site.scratch = true;
+        site
+    }
+
+    // A site with its sources under root, which is left in place once the
+    // site is dropped.
+    pub fn at(root: impl Into<PathBuf>) -> TestSite {
+        let root = root.into();
+        for content_dir in ["posts", "topics"] {
+            fs::create_dir_all(root.join(content_dir)).expect("could not create the test site");
+        }
+        let mut config: Config = toml::from_str(r#"
+            [site]
+            name = "Test Site"
+            url = "example.org"
+            username = "test"
+            timezone = "UTC"
+
+            [homepage]
+        "#).expect("the test config parses");
+        config.site.html_root = root.join("out/html").to_string_lossy().into_owned();
+        config.site.gemini_root = root.join("out/gemini").to_string_lossy().into_owned();
+        TestSite {
+            root,
+            scratch: false,
+            templates: PathBuf::from(env!("CARGO_MANIFEST_DIR")),
+            clock: DEFAULT_CLOCK,
+            config,
+        }
+    }
+
+    // Take the templates from templates/ in another data directory.
+    pub fn templates(&mut self, data_dir: impl Into<PathBuf>) -> &mut TestSite {
+        self.templates = data_dir.into();
+        self
+    }
+
+    // Build at another time, in seconds since the epoch.
+    pub fn clock(&mut self, epoch: i64) -> &mut TestSite {
+        self.clock = epoch;
+        self
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    pub fn config(&mut self) -> &mut Config {
+        &mut self.config
+    }
+
+    // Add a source, or any other file, at a path under the root.
+    pub fn source(&mut self, path: &str, contents: &str) -> &mut TestSite {
+        let path = self.root.join(path);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).expect("could not create a source directory");
+        }
+        fs::write(path, contents).expect("could not write a source");
+        self
+    }
+
+    // Build the site and return what it wrote, by path under the output
+    // roots like html/index.html.
+    pub fn build(&self) -> BTreeMap<String, String> {
+        let args = Args {
+            dir: Some(self.root.clone()),
+            data_dir: Some(self.templates.clone()),
+            dry_run: true,
+            source_date_epoch: Some(self.clock),
+            ..Default::default()
+        };
+        let crosspub = CrossPub::new(&self.config, &args);
+        crosspub.write();
+        let out = self.root.join("out");
+        crosspub.outputs().into_iter()
+            .filter_map(|(path, contents)| {
+                let relative = path.strip_prefix(&out).ok()?;
+                Some((relative_name(relative), String::from_utf8_lossy(&contents?).into_owned()))
+            })
+            .collect()
+    }
+}
+
+impl Default for TestSite {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for TestSite {
+    fn drop(&mut self) {
+        if self.scratch {
+            let _ = fs::remove_dir_all(&self.root);
+        }
+    }
+}
+
+// A relative path with / between its components whatever the platform.
+fn relative_name(path: &Path) -> String {
+    path.components()
+        .map(|c| c.as_os_str().to_string_lossy().into_owned())
+        .collect::<Vec<String>>()
+        .join("/")
+}
+
+// Compare outputs to the files under the golden directory. With
+// CROSSPUB_BLESS set the golden files are replaced by the outputs instead, to
+// accept a change in what crosspub renders.
+pub fn assert_golden(golden: &Path, outputs: &BTreeMap<String, String>) {
+    if std::env::var_os("CROSSPUB_BLESS").is_some() {
+        let _ = fs::remove_dir_all(golden);
+        for (path, contents) in outputs {
+            let path = golden.join(path);
+            fs::create_dir_all(path.parent().unwrap()).expect("could not create a golden directory");
+            fs::write(path, contents).expect("could not write a golden file");
+        }
+        return;
+    }
+
+    let mut expected = BTreeMap::new();
+    let mut pending = vec![golden.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        for entry in fs::read_dir(&dir).expect("no golden files, create them with CROSSPUB_BLESS=1").flatten() {
+            let path = entry.path();
+            if path.is_dir() {
+                pending.push(path);
+                continue;
+            }
+            expected.insert(relative_name(path.strip_prefix(golden).unwrap()), fs::read_to_string(&path).unwrap());
+        }
+    }
+    let golden = golden.to_string_lossy();
+    assert_eq!(outputs.keys().collect::<Vec<_>>(), expected.keys().collect::<Vec<_>>(),
+        "the site writes other files than {}", golden);
+    for (path, contents) in outputs {
+        assert_eq!(contents, &expected[path], "{} differs from {}/{}", path, golden, path);
+    }
+}
@@ -0,0 +1,55 @@
+use chrono::{DateTime, Duration, FixedOffset, Local, NaiveDateTime, Offset, TimeZone, Utc};
+use chrono_tz::Tz;
+
+// The timezone the dates in frontmatter are written in. Without one the
+// timezone of the machine building the site is used, which makes the output
+// depend on where it's built.
+#[derive(Clone, Copy, Debug)]
+pub enum SiteTimezone {
+    Local,
+    Named(Tz),
+}
+
+impl SiteTimezone {
+    // A timezone from the tz database like "Europe/Berlin" or "UTC".
+    pub fn parse(name: Option<&str>) -> Result<SiteTimezone, String> {
+        match name {
+            None => Ok(SiteTimezone::Local),
+            Some(n) => n.parse::<Tz>().map(SiteTimezone::Named),
+        }
+    }
+
+    // The moment a date written in this timezone stands for. A time that
+    // happens twice when the clocks go back is the first one. A time skipped
+    // when they go forward is read with the offset from before the change,
+    // which is the same moment as an hour later.
+    pub fn resolve(&self, date: &NaiveDateTime) -> DateTime<FixedOffset> {
+        self.earliest(date)
+            .or_else(|| self.earliest(&(*date + Duration::hours(1))))
+            .unwrap_or_else(|| Utc.from_utc_datetime(date).into())
+    }
+
+    // The current time, or a fixed one given in seconds since the epoch or in
+    // SOURCE_DATE_EPOCH for reproducible builds.
+    pub fn now(&self, fixed_epoch: Option<i64>) -> DateTime<FixedOffset> {
+        let now = fixed_epoch
+            .or_else(|| std::env::var("SOURCE_DATE_EPOCH").ok().and_then(|s| s.trim().parse::<i64>().ok()))
+            .map(|s| Utc.timestamp(s, 0))
+            .unwrap_or_else(Utc::now);
+        match self {
+            SiteTimezone::Local => now.with_timezone(&Local).into(),
+            SiteTimezone::Named(tz) => fixed(now.with_timezone(tz)),
+        }
+    }
+
+    fn earliest(&self, date: &NaiveDateTime) -> Option<DateTime<FixedOffset>> {
+        match self {
+            SiteTimezone::Local => Local.from_local_datetime(date).earliest().map(|d| d.into()),
+            SiteTimezone::Named(tz) => tz.from_local_datetime(date).earliest().map(fixed),
+        }
+    }
+}
+
+fn fixed(date: DateTime<Tz>) -> DateTime<FixedOffset> {
+    date.with_timezone(&date.offset().fix())
+}
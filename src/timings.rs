@@ -0,0 +1,79 @@
+use std::cell::{Cell, RefCell};
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+// The phases of a build in the order they're reported.
+pub const PHASES: [&str; 7] = ["load", "parse", "render HTML", "render Gemini", "feeds", "IO", "other"];
+
+// Where the time of a build goes, by phase and by page. Phases can be timed
+// inside each other, each only counting the time the ones inside it didn't.
+#[derive(Default)]
+pub struct Timings {
+    phases: RefCell<BTreeMap<&'static str, Duration>>,
+    pages: RefCell<BTreeMap<String, Duration>>,
+    // Time already counted towards some phase.
+    counted: Cell<Duration>,
+}
+
+// The start of a timed part of the build.
+#[derive(Clone, Copy)]
+pub struct Mark {
+    started: Instant,
+    counted: Duration,
+}
+
+impl Timings {
+    pub fn start(&self) -> Mark {
+        Mark {
+            started: Instant::now(),
+            counted: self.counted.get(),
+        }
+    }
+
+    // Add the time since the mark to a phase, less what phases timed in
+    // between counted. Returns the time added.
+    pub fn phase(&self, name: &'static str, mark: Mark) -> Duration {
+        let nested = self.counted.get().saturating_sub(mark.counted);
+        let elapsed = mark.started.elapsed().saturating_sub(nested);
+        *self.phases.borrow_mut().entry(name).or_default() += elapsed;
+        self.counted.set(self.counted.get() + elapsed);
+        elapsed
+    }
+
+    // Add time spent on a page, named by its output path, across phases.
+    pub fn page(&self, name: &str, elapsed: Duration) {
+        *self.pages.borrow_mut().entry(name.to_string()).or_default() += elapsed;
+    }
+
+    pub fn report(&self, total: Duration, slowest: usize) -> String {
+        let mut report = String::from("Timings:\n");
+        let phases = self.phases.borrow();
+        for name in PHASES {
+            let elapsed = phases.get(name).copied().unwrap_or_default();
+            report.push_str(&format!("  {:<14} {:>10}  {:>3}%\n", name, milliseconds(elapsed), percent(elapsed, total)));
+        }
+        report.push_str(&format!("  {:<14} {:>10}\n", "total", milliseconds(total)));
+
+        let page_times = self.pages.borrow();
+        let mut pages: Vec<(&String, &Duration)> = page_times.iter().collect();
+        pages.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+        if slowest > 0 && !pages.is_empty() {
+            report.push_str("Slowest pages:\n");
+            for (name, elapsed) in pages.into_iter().take(slowest) {
+                report.push_str(&format!("  {:>10}  {}\n", milliseconds(*elapsed), name));
+            }
+        }
+        report
+    }
+}
+
+fn milliseconds(duration: Duration) -> String {
+    format!("{:.1} ms", duration.as_secs_f64() * 1000.0)
+}
+
+fn percent(part: Duration, total: Duration) -> u32 {
+    if total.is_zero() {
+        return 0;
+    }
+    (part.as_secs_f64() / total.as_secs_f64() * 100.0).round() as u32
+}
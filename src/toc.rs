@@ -0,0 +1,161 @@
+use serde::Serialize;
+
+use crate::gemtext::{GemtextToken, TokenKind};
+use crate::html::escape_text;
+
+#[derive(Clone, Debug, Serialize)]
+pub struct TocEntry {
+    pub level: usize,
+    pub title: String,
+    pub anchor: String,
+}
+
+// A table of contents as an HTML nested list and a Gemini list, which has no
+// links within a page to point at the headings.
+#[derive(Clone, Default, Debug, Eq, PartialEq, Ord, PartialOrd, Serialize)]
+pub struct Toc {
+    pub html: String,
+    pub gemini: String,
+}
+
+fn heading_level(kind: TokenKind) -> Option<usize> {
+    match kind {
+        TokenKind::Heading => Some(1),
+        TokenKind::SubHeading => Some(2),
+        TokenKind::SubSubHeading => Some(3),
+        _ => None,
+    }
+}
+
+// Give every heading without an id one made from its text, so the table of
+// contents has something to link to. Repeated headings get a number.
+pub fn anchor_headings(tokens: &[GemtextToken]) -> Vec<GemtextToken> {
+    let mut used: Vec<String> = tokens.iter()
+        .filter(|t| heading_level(t.kind).is_some() && !t.extra.is_empty())
+        .map(|t| t.extra.clone())
+        .collect();
+
+    tokens.iter()
+        .map(|t| {
+            if heading_level(t.kind).is_none() || !t.extra.is_empty() {
+                return t.clone();
+            }
+            let base = heading_anchor(&t.data);
+            let mut anchor = base.clone();
+            let mut n = 1;
+            while used.contains(&anchor) {
+                n += 1;
+                anchor = format!("{}-{}", base, n);
+            }
+            used.push(anchor.clone());
+            GemtextToken {
+                extra: anchor,
+                ..t.clone()
+            }
+        })
+        .collect()
+}
+
+pub fn heading_anchor(text: &str) -> String {
+    let mut anchor = String::new();
+    let mut last_dash = true;
+    for c in text.trim().chars() {
+        if c.is_alphanumeric() {
+            anchor.extend(c.to_lowercase());
+            last_dash = false;
+        } else if !last_dash {
+            anchor.push('-');
+            last_dash = true;
+        }
+    }
+    let anchor = anchor.trim_end_matches('-');
+    if anchor.is_empty() {
+        "section".to_owned()
+    } else {
+        anchor.to_owned()
+    }
+}
+
+// The anchored headings of a token chain in order.
+pub fn entries(tokens: &[GemtextToken]) -> Vec<TocEntry> {
+    tokens.iter()
+        .filter(|t| !t.extra.is_empty() && !t.data.trim().is_empty())
+        .filter_map(|t| heading_level(t.kind).map(|level| TocEntry {
+            level,
+            title: t.data.trim().to_owned(),
+            anchor: t.extra.clone(),
+        }))
+        .collect()
+}
+
+pub fn render(entries: &[TocEntry]) -> Toc {
+    if entries.is_empty() {
+        return Toc::default();
+    }
+
+    // Nest the HTML lists relative to the highest level heading present.
+    let top = entries.iter().map(|e| e.level).min().unwrap_or(1);
+    let mut html = String::from("<nav class=\"toc\">\n<ul>\n");
+    let mut depth = top;
+    let mut open_item = false;
+    for entry in entries {
+        let level = entry.level.max(top);
+        if level > depth {
+            // A skipped level still gets an item to hold the deeper list.
+            while depth < level {
+                if !open_item {
+                    html.push_str("<li>");
+                }
+                html.push_str("\n<ul>\n");
+                open_item = false;
+                depth += 1;
+            }
+        } else {
+            if open_item {
+                html.push_str("</li>\n");
+            }
+            while depth > level {
+                html.push_str("</ul>\n</li>\n");
+                depth -= 1;
+            }
+        }
+        html.push_str(&format!("<li><a href=\"#{}\">{}</a>", entry.anchor, escape_text(&entry.title)));
+        open_item = true;
+    }
+    html.push_str("</li>\n");
+    while depth > top {
+        html.push_str("</ul>\n</li>\n");
+        depth -= 1;
+    }
+    html.push_str("</ul>\n</nav>\n");
+
+    let gemini = entries.iter()
+        .map(|e| format!("* {}", e.title))
+        .collect::<Vec<String>>()
+        .join("\n");
+
+    Toc { html, gemini }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(level: usize, title: &str) -> TocEntry {
+        TocEntry { level, title: title.to_owned(), anchor: heading_anchor(title) }
+    }
+
+    #[test]
+    fn skipped_level_nests_in_an_item() {
+        let toc = render(&[entry(1, "Top"), entry(3, "Deep"), entry(1, "Next")]);
+        assert_eq!(toc.html, "<nav class=\"toc\">\n<ul>\n<li><a href=\"#top\">Top</a>\n<ul>\n\
+            <li>\n<ul>\n<li><a href=\"#deep\">Deep</a></li>\n</ul>\n</li>\n</ul>\n</li>\n\
+            <li><a href=\"#next\">Next</a></li>\n</ul>\n</nav>\n");
+    }
+
+    #[test]
+    fn gemini_lists_headings() {
+        let toc = render(&[entry(1, "Top"), entry(2, "Sub")]);
+        assert_eq!(toc.gemini, "* Top\n* Sub");
+    }
+}
@@ -0,0 +1,40 @@
+use serde::{Deserialize, Serialize};
+
+use crate::crosspub::Output;
+use crate::gemini::generate_gemini_from_tokens;
+use crate::gemtext::{gemini_body, parse_gemtext_lossy, select_output, split_frontmatter, Diagnostic, GemtextToken};
+use crate::html::{generate_html_from_tokens, HtmlOptions};
+
+// The tokens of a gemtext body as JSON, for editors, previewers and linters
+// that want to parse gemtext the way crosspub does. Spans and diagnostic
+// lines count from the first line of the body, after any frontmatter.
+#[derive(Serialize, Deserialize)]
+pub struct TokenStream {
+    pub tokens: Vec<GemtextToken>,
+    #[serde(default)]
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+// Parse a source the way a build parses it for one output, skipping its
+// frontmatter.
+pub fn parse(contents: &str, output: &Output) -> String {
+    let lines: Vec<String> = contents.lines().map(String::from).collect();
+    let (_, body) = split_frontmatter(&lines);
+    let lines = match output {
+        Output::Html => select_output(body, "html"),
+        Output::Gemini => gemini_body(body),
+    };
+    let (tokens, diagnostics) = parse_gemtext_lossy(&lines);
+    // Tokens and diagnostics are plain data, serializing them can't fail.
+    serde_json::to_string_pretty(&TokenStream { tokens, diagnostics }).unwrap()
+}
+
+// Render the tokens of a TokenStream, which may have been changed since it
+// was parsed, to the body of an HTML page or to gemtext.
+pub fn render(json: &str, output: &Output, options: &HtmlOptions) -> Result<String, serde_json::Error> {
+    let stream: TokenStream = serde_json::from_str(json)?;
+    Ok(match output {
+        Output::Html => generate_html_from_tokens(&stream.tokens, options),
+        Output::Gemini => generate_gemini_from_tokens(&stream.tokens) + "\n",
+    })
+}
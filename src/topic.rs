@@ -1,68 +1,109 @@
-use std::io::{BufRead, BufReader};
-use std::fs::OpenOptions;
 use std::path::PathBuf;
 use std::process::exit;
 
+use chrono::NaiveDate;
 use serde::Serialize;
-use toml::Value;
 
-use crate::gemtext::parse_gemtext;
+use crate::config::Site;
+use crate::export::{page_path, page_url};
+use crate::frontmatter::{self, TopicFrontmatter};
+use crate::gemtext::{gemini_body, select_output, GemtextToken, parse_gemtext_lossy};
+use crate::html::{generate_html_from_tokens, HtmlOptions};
+use crate::notify::report_diagnostics;
+use crate::toc::{self, Toc};
 
 #[derive(Clone, Default, Debug, Serialize)]
 pub struct Topic {
     pub title: String,
     pub filename: String,
+    // Where the topic is written relative to the output roots, without an
+    // extension. The slug unless the frontmatter says otherwise.
+    pub output_path: String,
+    // The HTML and Gemini pages as full URLs and as paths from the root of
+    // the host, set once the site is known.
+    pub url: String,
+    pub path: String,
+    pub gemini_url: String,
+    pub gemini_path: String,
     pub html_content: String,
     pub gemini_content: String,
+    pub date: String,
+    pub updated: String,
+    pub tags: Vec<String>,
+    pub toc: Toc,
+    pub source_hash: String,
+    pub styles: Vec<String>,
+    pub scripts: Vec<String>,
+    #[serde(skip)]
+    pub html_options: HtmlOptions,
+    #[serde(skip)]
+    pub frontmatter: String,
+    #[serde(skip)]
+    pub tokens: Vec<GemtextToken>,
 }
 
 impl Topic {
-    pub fn from_source(source_path: PathBuf) -> Topic {
-        // Read from source .gmi file.
-        let source = OpenOptions::new().read(true).open(&source_path);
-        let source = match source {
-            Ok(s) => s,
-            Err(_) => {
-                eprintln!("Error: Could not open file {}",
-                    &source_path.to_string_lossy());
+    pub fn from_source(source_path: PathBuf, html_options: HtmlOptions) -> Topic {
+        let lines = frontmatter::read_lines(&source_path);
+
+        // Load frontmatter, which sits between the first two --- lines.
+        let frontmatter_end = match lines.iter().skip(1).position(|l| l.trim() == "---") {
+            Some(i) if lines[0].trim() == "---" => i + 1,
+            _ => {
+                eprintln!("Error: Missing frontmatter in {}", &source_path.to_string_lossy());
                 exit(1);
-            },
+            }
         };
-        let reader = BufReader::new(source);
-        let lines: Vec<String> = reader.lines().map(|l| l.unwrap()).collect();
-
-        // Load frontmatter.
-        let mut topic = Topic::default();
-        topic.title = match lines[1].parse::<Value>() {
-            Ok(v) => {
-                let s = v["title"].to_string();
-                let end = s.len() - 1;
-                s[1..end].to_string()
-            },
+        let frontmatter: TopicFrontmatter = match toml::from_str(&lines[1..frontmatter_end].join("\n")) {
+            Ok(fm) => fm,
             Err(_) => {
-                eprintln!("Could not parse frontmatter title.");
+                eprintln!("Error: Frontmatter formatted incorrectly in {}", &source_path.to_string_lossy());
                 exit(1);
             }
         };
-        topic.filename = match lines[2].parse::<Value>() {
-            Ok(v) => {
-                let s = v["slug"].to_string();
-                let end = s.len() - 1;
-                s[1..end].to_string()
-            },
-            Err(_) => {
-                eprintln!("Could not parse frontmatter slug.");
+        for date in [&frontmatter.date, &frontmatter.updated].into_iter().flatten() {
+            if NaiveDate::parse_from_str(date, "%Y-%m-%d").is_err() {
+                eprintln!("Error: Date \"{}\" in {} should look like 2022-01-31", date, &source_path.to_string_lossy());
                 exit(1);
             }
+        }
+        let mut topic = Topic {
+            title: frontmatter.title,
+            output_path: frontmatter::output_path(frontmatter.output_path.as_deref(), frontmatter.slug.clone()),
+            filename: frontmatter.slug,
+            date: frontmatter.date.unwrap_or_default(),
+            updated: frontmatter.updated.unwrap_or_default(),
+            tags: frontmatter.tags,
+            styles: frontmatter.styles,
+            scripts: frontmatter.scripts,
+            html_options: HtmlOptions {
+                raw_html: frontmatter.raw_html.unwrap_or(html_options.raw_html),
+                reflow: frontmatter.reflow.unwrap_or(html_options.reflow),
+                ..html_options
+            },
+            frontmatter: lines[1..frontmatter_end].join("\n"),
+            ..Default::default()
         };
 
         // Generate content bodies for HTML and Gemini.
-        let tokens = parse_gemtext(&lines[5..]);
-        for token in tokens {
-            topic.html_content.push_str(&token.as_html())
+        let body = &lines[frontmatter_end + 1..];
+        let (mut tokens, diagnostics) = parse_gemtext_lossy(&select_output(body, "html"));
+        report_diagnostics(&source_path, &diagnostics);
+        if frontmatter.toc.unwrap_or(false) {
+            tokens = toc::anchor_headings(&tokens);
+            topic.toc = toc::render(&toc::entries(&tokens));
         }
-        topic.gemini_content = lines[4..].join("\n");
+        topic.html_content = generate_html_from_tokens(&tokens, &topic.html_options);
+        topic.tokens = tokens;
+        topic.gemini_content = gemini_body(body).join("\n");
 
         topic
     }
+
+    pub fn set_urls(&mut self, site: &Site) {
+        self.url = page_url(site, "http", &format!("{}.html", self.output_path));
+        self.path = page_path(site, &format!("{}.html", self.output_path));
+        self.gemini_url = page_url(site, "gemini", &format!("{}.gmi", self.output_path));
+        self.gemini_path = page_path(site, &format!("{}.gmi", self.output_path));
+    }
 }
@@ -0,0 +1,69 @@
+use crate::gemtext::{GemtextToken, TokenKind};
+
+// Curly quotes, dashes and ellipses for the text of tokens. Preformatted text
+// is left alone, as are `code` spans and [[wikilinks]] within a line.
+pub fn smarten_tokens(tokens: &[GemtextToken]) -> Vec<GemtextToken> {
+    tokens.iter()
+        .map(|t| match t.kind {
+            TokenKind::Text | TokenKind::UnorderedList | TokenKind::OrderedList | TokenKind::Blockquote
+                | TokenKind::Footnote | TokenKind::Heading | TokenKind::SubHeading
+                | TokenKind::SubSubHeading => GemtextToken {
+                data: smarten(&t.data),
+                ..t.clone()
+            },
+            // Only the name of a link is text.
+            TokenKind::Link | TokenKind::Reference => GemtextToken {
+                extra: smarten(&t.extra),
+                ..t.clone()
+            },
+            _ => t.clone(),
+        })
+        .collect()
+}
+
+pub fn smarten(text: &str) -> String {
+    let mut smart = String::new();
+    let mut prev: Option<char> = None;
+    let mut rest = text;
+    while let Some(c) = rest.chars().next() {
+        // Spans that are copied as they are.
+        let verbatim = match c {
+            '`' => rest[1..].find('`').map(|end| end + 2),
+            '[' if rest.starts_with("[[") => rest.find("]]").map(|end| end + 2),
+            _ => None,
+        };
+        if let Some(length) = verbatim {
+            smart.push_str(&rest[..length]);
+            prev = rest[..length].chars().last();
+            rest = &rest[length..];
+            continue;
+        }
+
+        let (replacement, length) = if rest.starts_with("...") {
+            ('…', 3)
+        } else if rest.starts_with("---") {
+            ('—', 3)
+        } else if rest.starts_with("--") {
+            ('–', 2)
+        } else if c == '"' {
+            (if opens(prev) { '“' } else { '”' }, 1)
+        } else if c == '\'' {
+            (if opens(prev) { '‘' } else { '’' }, 1)
+        } else {
+            (c, c.len_utf8())
+        };
+        smart.push(replacement);
+        prev = Some(replacement);
+        rest = &rest[length..];
+    }
+    smart
+}
+
+// A quote opens at the start of the text and after spaces, opening brackets
+// and dashes, everywhere else it closes or is an apostrophe.
+fn opens(prev: Option<char>) -> bool {
+    match prev {
+        None => true,
+        Some(c) => c.is_whitespace() || matches!(c, '(' | '[' | '{' | '–' | '—' | '“' | '‘'),
+    }
+}
@@ -0,0 +1,23 @@
+use wasm_bindgen::prelude::*;
+
+use crate::gemtext::{parse_gemtext, select_output, split_frontmatter};
+use crate::html::{generate_html_from_tokens, HtmlOptions};
+
+// Render the source of a post, topic or page to the HTML of its body like a
+// build does, for previews in a browser. options is a JSON object with the
+// fields of HtmlOptions, "{}" for the defaults of a config that doesn't set
+// any. raw_html and reflow in the frontmatter override them as they do in a
+// build. Footnotes and references are linked within the body as usual, while
+// wikilinks and other parts that need the whole site are left as written.
+#[wasm_bindgen]
+pub fn gemtext_to_html(source: &str, options: &str) -> Result<String, JsError> {
+    let mut options: HtmlOptions = serde_json::from_str(options)?;
+    let lines: Vec<String> = source.lines().map(String::from).collect();
+    let (frontmatter, body) = split_frontmatter(&lines);
+    let frontmatter: toml::Value = toml::from_str(&frontmatter.join("\n"))?;
+    let flag = |key: &str| frontmatter.get(key).and_then(toml::Value::as_bool);
+    options.raw_html = flag("raw_html").unwrap_or(options.raw_html);
+    options.reflow = flag("reflow").unwrap_or(options.reflow);
+    let tokens = parse_gemtext(&select_output(body, "html"));
+    Ok(generate_html_from_tokens(&tokens, &options))
+}
@@ -0,0 +1,18 @@
+use std::time::Duration;
+
+use crate::notify::warning;
+
+// Tell a WebSub hub that the feed at feed_url has new content, so it can push
+// it to subscribers. Failing to reach the hub isn't fatal to a build.
+pub fn publish(hub: &str, feed_url: &str) {
+    let agent = ureq::AgentBuilder::new()
+        .timeout(Duration::from_secs(30))
+        .build();
+
+    println!("Notifying WebSub hub {} about {}", hub, feed_url);
+    let result = agent.post(hub)
+        .send_form(&[("hub.mode", "publish"), ("hub.url", feed_url)]);
+    if let Err(e) = result {
+        warning!("Could not notify WebSub hub {}: {}", hub, e);
+    }
+}
@@ -0,0 +1,115 @@
+use std::collections::BTreeMap;
+
+use crate::gemtext::{GemtextToken, TokenKind};
+use crate::html::escape_text;
+
+// Topics a wikilink can point at, by lowercased title and slug, with the
+// output path they're written to.
+pub type Targets = BTreeMap<String, String>;
+
+pub fn targets<'a>(topics: impl Iterator<Item = (&'a str, &'a str, &'a str)>) -> Targets {
+    let mut targets = Targets::new();
+    for (title, slug, output_path) in topics {
+        targets.entry(slug.to_lowercase()).or_insert_with(|| output_path.to_owned());
+        targets.entry(title.to_lowercase()).or_insert_with(|| output_path.to_owned());
+    }
+    targets
+}
+
+// Replace every [[Target]] or [[Target|label]] in text with what replace
+// returns for its target and label.
+fn replace(text: &str, mut replace: impl FnMut(&str, &str) -> String) -> String {
+    let mut replaced = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find("[[") {
+        let after = &rest[start + 2..];
+        let inner = match after.find("]]") {
+            Some(end) if end > 0 && !after[..end].contains('[') => &after[..end],
+            _ => {
+                replaced.push_str(&rest[..start + 2]);
+                rest = after;
+                continue;
+            },
+        };
+        let (target, label) = inner.split_once('|').unwrap_or((inner, inner));
+        replaced.push_str(&rest[..start]);
+        replaced.push_str(&replace(target.trim(), label.trim()));
+        rest = &after[inner.len() + 2..];
+    }
+    replaced.push_str(rest);
+    replaced
+}
+
+// The text with every wikilink replaced by its label, for plain text like
+// summaries.
+pub fn labels(text: &str) -> String {
+    replace(text, |_, label| label.to_owned())
+}
+
+// The output paths of the topics the wikilinks in tokens point at.
+pub fn linked(tokens: &[GemtextToken], targets: &Targets) -> Vec<String> {
+    let mut linked = Vec::new();
+    for t in tokens.iter().filter(|t| matches!(t.kind,
+        TokenKind::Text | TokenKind::UnorderedList | TokenKind::OrderedList | TokenKind::Blockquote)) {
+        replace(&t.data, |target, _| {
+            if let Some(path) = targets.get(&target.to_lowercase()) {
+                linked.push(path.clone());
+            }
+            String::new()
+        });
+    }
+    linked
+}
+
+// Link the wikilinks in the text of prepared tokens to the HTML page of their
+// topic. Targets without a topic are added to missing and left as their label.
+pub fn link_html(tokens: &[GemtextToken], targets: &Targets, url_prefix: &str, missing: &mut Vec<String>) -> Vec<GemtextToken> {
+    tokens.iter()
+        .map(|t| match t.kind {
+            TokenKind::Text | TokenKind::UnorderedList | TokenKind::OrderedList | TokenKind::Blockquote => GemtextToken {
+                data: replace(&t.data, |target, label| {
+                    // Prepared text is escaped, so are the keys it's compared to.
+                    match targets.iter().find(|(k, _)| escape_text(k) == target.to_lowercase()) {
+                        Some((_, path)) => format!("<a class=\"wikilink\" href=\"{}{}.html\">{}</a>", url_prefix, path, label),
+                        None => {
+                            missing.push(target.to_owned());
+                            label.to_owned()
+                        },
+                    }
+                }),
+                ..t.clone()
+            },
+            _ => t.clone(),
+        })
+        .collect()
+}
+
+// Gemtext has no links within a line, so a line with wikilinks keeps their
+// labels and is followed by a link line for each of them.
+pub fn link_gemini(content: &str, targets: &Targets, url_prefix: &str, missing: &mut Vec<String>) -> String {
+    if !content.contains("[[") {
+        return content.to_owned();
+    }
+    let mut lines = Vec::new();
+    let mut in_pft = false;
+    for line in content.lines() {
+        if line.starts_with("```") {
+            in_pft = !in_pft;
+        }
+        if in_pft || line.starts_with("```") || line.starts_with("=>") {
+            lines.push(line.to_owned());
+            continue;
+        }
+        let mut links = Vec::new();
+        let replaced = replace(line, |target, label| {
+            match targets.get(&target.to_lowercase()) {
+                Some(path) => links.push(format!("=> {}{}.gmi {}", url_prefix, path, label)),
+                None => missing.push(target.to_owned()),
+            }
+            label.to_owned()
+        });
+        lines.push(replaced);
+        lines.append(&mut links);
+    }
+    lines.join("\n")
+}
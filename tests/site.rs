@@ -0,0 +1,124 @@
+#![cfg(feature = "cli")]
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use crosspub::testing::{assert_golden, TestSite};
+
+fn golden(name: &str) -> std::path::PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden").join(name)
+}
+
+fn pages_only(outputs: BTreeMap<String, String>) -> BTreeMap<String, String> {
+    outputs.into_iter().filter(|(p, _)| !p.starts_with("html/css/")).collect()
+}
+
+#[test]
+fn basic_site() {
+    let mut site = TestSite::new();
+    site.source("posts/hello.gmi", "---\ntitle = \"Hello\"\ndate = \"2023-12-01\"\nslug = \"hello\"\n---\n\
+        # Hello\nThe first post.\n* [x] Write it\n* [ ] Publish it\n=> https://example.com/essay An essay\n\
+        > Small sites last.\n>> And stay small.\n```rust\nfn main() {}\n```\n## More\n* Capsule :: A Gemini site\n");
+    site.source("posts/second.gmi", "---\ntitle = \"Second\"\ndate = \"2023-12-24 18:00\"\nslug = \"second\"\n---\n\
+        A post with a footnote[^1] and a link.\n=> hello.gmi Back to the first post\n\n[^1]: The note.\n");
+    site.source("topics/garden.gmi", "---\ntitle = \"Garden\"\nslug = \"garden\"\n---\n## Plants\nTomatoes.\n");
+    site.source("pages/contact.gmi", "# Contact\nWrite to me.\n");
+    assert_golden(&golden("basic"), &pages_only(site.build()));
+}
+
+#[test]
+fn builds_are_reproducible() {
+    let mut site = TestSite::new();
+    site.source("posts/hello.gmi", "---\ntitle = \"Hello\"\ndate = \"2023-12-01\"\nslug = \"hello\"\n---\nHi.\n");
+    assert_eq!(site.build(), site.build());
+}
+
+#[test]
+fn clock_sets_the_build_time() {
+    let mut site = TestSite::new();
+    site.source("posts/hello.gmi", "---\ntitle = \"Hello\"\ndate = \"2023-12-01\"\nslug = \"hello\"\n---\nHi.\n");
+    site.config().build.metadata = Some(true);
+    site.clock(1_000_000_000);
+    let index = &site.build()["html/index.html"];
+    assert!(index.contains("<!-- Built 2001-09-09T01:46:40+00:00 -->"), "{}", index);
+}
+
+#[test]
+fn templates_come_from_the_data_dir() {
+    let mut site = TestSite::new();
+    site.source("posts/hello.gmi", "---\ntitle = \"Hello\"\ndate = \"2023-12-01\"\nslug = \"hello\"\n---\nHi.\n");
+    let data_dir = site.root().join("data");
+    copy_dir(&Path::new(env!("CARGO_MANIFEST_DIR")).join("templates"), &data_dir.join("templates"));
+    std::fs::write(data_dir.join("templates/gemini/post.gmi"), "Custom {post.title}\n").unwrap();
+    site.templates(&data_dir);
+    let outputs = site.build();
+    assert_eq!(outputs["gemini/posts/20231201_hello.gmi"], "Custom Hello\n");
+}
+
+#[test]
+fn html_options_come_from_the_config() {
+    let mut site = TestSite::new();
+    site.source("posts/hello.gmi", "---\ntitle = \"Hello\"\ndate = \"2023-12-01\"\nslug = \"hello\"\n---\n\
+        # One\nFirst.\n## Two\nSecond.\n");
+    site.config().html.lede = Some(true);
+    site.config().html.sections = Some(true);
+    let outputs = site.build();
+    let post = &outputs["html/posts/20231201_hello.html"];
+    assert!(post.contains("<p class=\"lede\">First.</p>"), "{}", post);
+    assert!(post.contains("<section>\n<h2 id=\"two\">Two</h2>"), "{}", post);
+}
+
+#[test]
+fn paginated_posts_link_notes_on_every_page() {
+    let mut site = TestSite::new();
+    site.source("posts/paged.gmi", "---\ntitle = \"Paged\"\ndate = \"2023-12-01\"\nslug = \"paged\"\n\
+        paginate_by_heading = true\n---\n## One\nA note[^a] and a source [1].\n## Two\nAnother note[^b].\n\
+        ```refs\n=> https://example.org Source\n```\n[^a]: Note A.\n[^b]: Note B.\n");
+    let outputs = site.build();
+    let first = &outputs["html/posts/20231201_paged.html"];
+    assert!(first.contains("href=\"#fn-a\">1</a>"), "{}", first);
+    assert!(first.contains("<li id=\"fn-a\">Note A."), "{}", first);
+    assert!(first.contains("href=\"#ref-1\">[1]</a>"), "{}", first);
+    assert!(first.contains("<li id=\"ref-1\">"), "{}", first);
+    let second = &outputs["html/posts/20231201_paged-2.html"];
+    assert!(second.contains("href=\"#fn-b\">2</a>"), "{}", second);
+}
+
+#[test]
+fn later_pages_of_paginated_posts_are_linked() {
+    let mut site = TestSite::new();
+    site.source("assets.toml", "diagram = \"images/diagram.png\"\n");
+    site.source("topics/garden.gmi", "---\ntitle = \"Garden\"\nslug = \"garden\"\n---\nTomatoes.\n");
+    site.source("posts/paged.gmi", "---\ntitle = \"Paged\"\ndate = \"2023-12-01\"\nslug = \"paged\"\n\
+        paginate_by_heading = true\n---\n## One\nSee [[Garden]].\n## Two\nSee [[Garden]] again.\n\
+        => asset:diagram The diagram\n");
+    let outputs = site.build();
+    let second = &outputs["html/posts/20231201_paged-2.html"];
+    assert!(second.contains("<a class=\"wikilink\" href=\"/~test/garden.html\">Garden</a>"), "{}", second);
+    assert!(second.contains("href=\"/~test/images/diagram.png\""), "{}", second);
+    assert!(!second.contains("[[") && !second.contains("asset:"), "{}", second);
+    let first = &outputs["html/posts/20231201_paged.html"];
+    assert!(first.contains("<meta name=\"description\" content=\"See Garden.\">"), "{}", first);
+}
+
+#[test]
+fn invalid_utf8_in_a_source_is_replaced() {
+    let site = TestSite::new();
+    std::fs::write(site.root().join("posts/latin1.gmi"),
+        b"---\ntitle = \"Caf\xe9\"\ndate = \"2023-12-01\"\nslug = \"cafe\"\n---\nA caf\xe9.\n").unwrap();
+    let outputs = site.build();
+    let post = &outputs["gemini/posts/20231201_cafe.gmi"];
+    assert!(post.contains("A caf\u{fffd}."), "{}", post);
+}
+
+fn copy_dir(from: &Path, to: &Path) {
+    std::fs::create_dir_all(to).unwrap();
+    for entry in std::fs::read_dir(from).unwrap().flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            copy_dir(&path, &to.join(entry.file_name()));
+        } else {
+            std::fs::copy(&path, to.join(entry.file_name())).unwrap();
+        }
+    }
+}